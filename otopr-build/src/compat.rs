@@ -0,0 +1,363 @@
+//! Checks that a new version of a schema can still read messages written with the old one.
+//!
+//! Decoding only looks at field numbers, so renumbering a field or changing its type
+//! silently misreads old data instead of failing.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+
+/// A change between two versions of a schema that breaks existing data or readers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BreakingChange {
+    MessageRemoved {
+        message: String,
+    },
+    /// A field was removed without reserving its number.
+    FieldRemoved {
+        message: String,
+        name: String,
+        number: i32,
+    },
+    /// A field kept its name but has a different number.
+    FieldNumberChanged {
+        message: String,
+        name: String,
+        old: i32,
+        new: i32,
+    },
+    /// A number is used by a new field. `old_name` is `None` if the number was reserved.
+    FieldNumberReused {
+        message: String,
+        number: i32,
+        old_name: Option<String>,
+        new_name: String,
+    },
+    /// The type of a field changed to one with a different encoding.
+    FieldTypeChanged {
+        message: String,
+        name: String,
+        old: Type,
+        new: Type,
+    },
+    FieldLabelChanged {
+        message: String,
+        name: String,
+        old: Label,
+        new: Label,
+    },
+    EnumRemoved {
+        r#enum: String,
+    },
+    /// An enum value was removed without reserving its number.
+    EnumValueRemoved {
+        r#enum: String,
+        name: String,
+        number: i32,
+    },
+    EnumValueNumberChanged {
+        r#enum: String,
+        name: String,
+        old: i32,
+        new: i32,
+    },
+}
+
+impl fmt::Display for BreakingChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageRemoved { message } => write!(f, "message `{}` was removed", message),
+            Self::FieldRemoved {
+                message,
+                name,
+                number,
+            } => write!(
+                f,
+                "{}: field `{}` = {} was removed without reserving its number",
+                message, name, number
+            ),
+            Self::FieldNumberChanged {
+                message,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "{}: field `{}` changed its number from {} to {}",
+                message, name, old, new
+            ),
+            Self::FieldNumberReused {
+                message,
+                number,
+                old_name: Some(old_name),
+                new_name,
+            } => write!(
+                f,
+                "{}: field number {} of `{}` is reused by `{}`",
+                message, number, old_name, new_name
+            ),
+            Self::FieldNumberReused {
+                message,
+                number,
+                old_name: None,
+                new_name,
+            } => write!(
+                f,
+                "{}: reserved field number {} is used by `{}`",
+                message, number, new_name
+            ),
+            Self::FieldTypeChanged {
+                message,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "{}: field `{}` changed its type from {:?} to {:?}",
+                message, name, old, new
+            ),
+            Self::FieldLabelChanged {
+                message,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "{}: field `{}` changed from {:?} to {:?}",
+                message, name, old, new
+            ),
+            Self::EnumRemoved { r#enum } => write!(f, "enum `{}` was removed", r#enum),
+            Self::EnumValueRemoved {
+                r#enum,
+                name,
+                number,
+            } => write!(
+                f,
+                "{}: value `{}` = {} was removed without reserving its number",
+                r#enum, name, number
+            ),
+            Self::EnumValueNumberChanged {
+                r#enum,
+                name,
+                old,
+                new,
+            } => write!(
+                f,
+                "{}: value `{}` changed its number from {} to {}",
+                r#enum, name, old, new
+            ),
+        }
+    }
+}
+
+/// Compares two versions of a schema, returning every breaking change from `old` to `new`.
+///
+/// Types are matched by their fully qualified names, so moving a type to another file is fine.
+pub fn check_compatibility(
+    old: &FileDescriptorSet,
+    new: &FileDescriptorSet,
+) -> Vec<BreakingChange> {
+    let old = Types::new(old);
+    let new = Types::new(new);
+    let mut changes = Vec::new();
+    for (name, old_message) in &old.messages {
+        match new.messages.get(name) {
+            Some(new_message) => check_message(name, old_message, new_message, &mut changes),
+            None => changes.push(BreakingChange::MessageRemoved {
+                message: name.clone(),
+            }),
+        }
+    }
+    for (name, old_enum) in &old.enums {
+        match new.enums.get(name) {
+            Some(new_enum) => check_enum(name, old_enum, new_enum, &mut changes),
+            None => changes.push(BreakingChange::EnumRemoved {
+                r#enum: name.clone(),
+            }),
+        }
+    }
+    changes
+}
+
+/// All messages and enums by their fully qualified names, without the leading `.`.
+#[derive(Default)]
+struct Types<'a> {
+    messages: BTreeMap<String, &'a DescriptorProto>,
+    enums: BTreeMap<String, &'a EnumDescriptorProto>,
+}
+
+impl<'a> Types<'a> {
+    fn new(set: &'a FileDescriptorSet) -> Self {
+        let mut types = Self::default();
+        for file in &set.file {
+            let scope = file.package().to_owned();
+            types.add(&scope, &file.message_type, &file.enum_type);
+        }
+        types
+    }
+
+    fn add(
+        &mut self,
+        scope: &str,
+        messages: &'a [DescriptorProto],
+        enums: &'a [EnumDescriptorProto],
+    ) {
+        for e in enums {
+            self.enums.insert(qualify(scope, e.name()), e);
+        }
+        for message in messages {
+            let name = qualify(scope, message.name());
+            self.add(&name, &message.nested_type, &message.enum_type);
+            self.messages.insert(name, message);
+        }
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn check_message(
+    message: &str,
+    old: &DescriptorProto,
+    new: &DescriptorProto,
+    changes: &mut Vec<BreakingChange>,
+) {
+    let is_reserved = |number: i32| {
+        new.reserved_range
+            .iter()
+            .any(|r| (r.start()..r.end()).contains(&number))
+    };
+    let new_by_number = |number| new.field.iter().find(|f| f.number() == number);
+    for old_field in &old.field {
+        let name = old_field.name();
+        let renumbered = new
+            .field
+            .iter()
+            .find(|f| f.name() == name && f.number() != old_field.number());
+        if let Some(new_field) = renumbered {
+            changes.push(BreakingChange::FieldNumberChanged {
+                message: message.to_owned(),
+                name: name.to_owned(),
+                old: old_field.number(),
+                new: new_field.number(),
+            });
+        }
+        match new_by_number(old_field.number()) {
+            Some(new_field) if new_field.name() != name => {
+                changes.push(BreakingChange::FieldNumberReused {
+                    message: message.to_owned(),
+                    number: old_field.number(),
+                    old_name: Some(name.to_owned()),
+                    new_name: new_field.name().to_owned(),
+                })
+            }
+            Some(new_field) => check_field(message, old_field, new_field, changes),
+            None if renumbered.is_none() && !is_reserved(old_field.number()) => {
+                changes.push(BreakingChange::FieldRemoved {
+                    message: message.to_owned(),
+                    name: name.to_owned(),
+                    number: old_field.number(),
+                })
+            }
+            None => {}
+        }
+    }
+    for new_field in &new.field {
+        let number = new_field.number();
+        let was_reserved = old
+            .reserved_range
+            .iter()
+            .any(|r| (r.start()..r.end()).contains(&number));
+        if was_reserved {
+            changes.push(BreakingChange::FieldNumberReused {
+                message: message.to_owned(),
+                number,
+                old_name: None,
+                new_name: new_field.name().to_owned(),
+            });
+        }
+    }
+}
+
+fn check_field(
+    message: &str,
+    old: &FieldDescriptorProto,
+    new: &FieldDescriptorProto,
+    changes: &mut Vec<BreakingChange>,
+) {
+    if encoding(old.r#type()) != encoding(new.r#type()) {
+        changes.push(BreakingChange::FieldTypeChanged {
+            message: message.to_owned(),
+            name: new.name().to_owned(),
+            old: old.r#type(),
+            new: new.r#type(),
+        });
+    }
+    if old.label() != new.label() {
+        changes.push(BreakingChange::FieldLabelChanged {
+            message: message.to_owned(),
+            name: new.name().to_owned(),
+            old: old.label(),
+            new: new.label(),
+        });
+    }
+}
+
+/// Types with the same encoding can be changed into each other, as listed in the language guide.
+fn encoding(ty: Type) -> u8 {
+    match ty {
+        Type::Int32 | Type::Int64 | Type::Uint32 | Type::Uint64 | Type::Bool | Type::Enum => 0,
+        Type::Sint32 | Type::Sint64 => 1,
+        Type::Fixed32 | Type::Sfixed32 => 2,
+        Type::Fixed64 | Type::Sfixed64 => 3,
+        Type::String | Type::Bytes | Type::Message => 4,
+        Type::Float => 5,
+        Type::Double => 6,
+        Type::Group => 7,
+    }
+}
+
+fn check_enum(
+    r#enum: &str,
+    old: &EnumDescriptorProto,
+    new: &EnumDescriptorProto,
+    changes: &mut Vec<BreakingChange>,
+) {
+    for old_value in &old.value {
+        let name = old_value.name();
+        match new.value.iter().find(|v| v.name() == name) {
+            Some(new_value) if new_value.number() != old_value.number() => {
+                changes.push(BreakingChange::EnumValueNumberChanged {
+                    r#enum: r#enum.to_owned(),
+                    name: name.to_owned(),
+                    old: old_value.number(),
+                    new: new_value.number(),
+                })
+            }
+            Some(_) => {}
+            None => {
+                let number = old_value.number();
+                // enum reserved ranges include their end.
+                let reserved = new
+                    .reserved_range
+                    .iter()
+                    .any(|r| (r.start()..=r.end()).contains(&number));
+                let still_used = new.value.iter().any(|v| v.number() == number);
+                if !reserved && !still_used {
+                    changes.push(BreakingChange::EnumValueRemoved {
+                        r#enum: r#enum.to_owned(),
+                        name: name.to_owned(),
+                        number,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -2,6 +2,12 @@
 
 use prost_types::FileDescriptorSet;
 
+mod compat;
+pub use compat::{check_compatibility, BreakingChange};
+
+#[cfg(test)]
+mod tests;
+
 pub enum MessageConfig {
     /// Generates "borrowed" instances, since we just need the data for encoding.
     /// 
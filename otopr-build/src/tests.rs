@@ -0,0 +1,243 @@
+use prost_types::descriptor_proto::ReservedRange;
+use prost_types::enum_descriptor_proto::EnumReservedRange;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FileDescriptorProto, FileDescriptorSet,
+};
+
+use crate::{check_compatibility, BreakingChange};
+
+fn field(name: &str, number: i32, label: Label, ty: Type) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        name: Some(name.to_owned()),
+        number: Some(number),
+        label: Some(label as i32),
+        r#type: Some(ty as i32),
+        ..Default::default()
+    }
+}
+
+fn file(
+    fields: Vec<FieldDescriptorProto>,
+    reserved: &[i32],
+    values: &[(&str, i32)],
+) -> FileDescriptorSet {
+    FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("shop.proto".to_owned()),
+            package: Some("shop".to_owned()),
+            message_type: vec![DescriptorProto {
+                name: Some("Item".to_owned()),
+                field: fields,
+                reserved_range: reserved
+                    .iter()
+                    .map(|&n| ReservedRange {
+                        start: Some(n),
+                        end: Some(n + 1),
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            enum_type: vec![EnumDescriptorProto {
+                name: Some("Color".to_owned()),
+                value: values
+                    .iter()
+                    .map(|&(name, number)| EnumValueDescriptorProto {
+                        name: Some(name.to_owned()),
+                        number: Some(number),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+/// The schema every test starts from.
+fn old() -> FileDescriptorSet {
+    file(
+        vec![
+            field("name", 1, Label::Optional, Type::String),
+            field("count", 2, Label::Optional, Type::Int32),
+            field("price", 3, Label::Optional, Type::Int64),
+            field("tags", 4, Label::Repeated, Type::String),
+            field("old", 7, Label::Optional, Type::Bool),
+        ],
+        &[9],
+        &[("RED", 0), ("BLUE", 1), ("GREEN", 2)],
+    )
+}
+
+fn item() -> String {
+    "shop.Item".to_owned()
+}
+
+fn color() -> String {
+    "shop.Color".to_owned()
+}
+
+#[test]
+fn test_compatible_changes() {
+    // `name` becomes bytes, `count` becomes int64, `old` is reserved and `GREEN` is renamed.
+    let new = file(
+        vec![
+            field("name", 1, Label::Optional, Type::Bytes),
+            field("count", 2, Label::Optional, Type::Int64),
+            field("price", 3, Label::Optional, Type::Int64),
+            field("tags", 4, Label::Repeated, Type::String),
+            field("added", 8, Label::Optional, Type::Bool),
+        ],
+        &[7, 9],
+        &[("RED", 0), ("BLUE", 1), ("LIME", 2), ("YELLOW", 3)],
+    );
+    assert_eq!(check_compatibility(&old(), &new), []);
+}
+
+#[test]
+fn test_message_removed() {
+    let mut new = old();
+    new.file[0].message_type.clear();
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::MessageRemoved { message: item() }]
+    );
+}
+
+#[test]
+fn test_field_removed() {
+    let mut new = old();
+    new.file[0].message_type[0].field.remove(2);
+    let changes = check_compatibility(&old(), &new);
+    assert_eq!(
+        changes,
+        [BreakingChange::FieldRemoved {
+            message: item(),
+            name: "price".to_owned(),
+            number: 3,
+        }]
+    );
+    assert_eq!(
+        changes[0].to_string(),
+        "shop.Item: field `price` = 3 was removed without reserving its number"
+    );
+}
+
+#[test]
+fn test_field_number_changed() {
+    let mut new = old();
+    new.file[0].message_type[0].field[2].number = Some(10);
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::FieldNumberChanged {
+            message: item(),
+            name: "price".to_owned(),
+            old: 3,
+            new: 10,
+        }]
+    );
+}
+
+#[test]
+fn test_field_number_reused() {
+    let mut new = old();
+    let fields = &mut new.file[0].message_type[0].field;
+    fields[2].name = Some("cost".to_owned());
+    fields.push(field("added", 9, Label::Optional, Type::Bool));
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [
+            BreakingChange::FieldNumberReused {
+                message: item(),
+                number: 3,
+                old_name: Some("price".to_owned()),
+                new_name: "cost".to_owned(),
+            },
+            BreakingChange::FieldNumberReused {
+                message: item(),
+                number: 9,
+                old_name: None,
+                new_name: "added".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_field_type_changed() {
+    let mut new = old();
+    new.file[0].message_type[0].field[1].r#type = Some(Type::Sint32 as i32);
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::FieldTypeChanged {
+            message: item(),
+            name: "count".to_owned(),
+            old: Type::Int32,
+            new: Type::Sint32,
+        }]
+    );
+}
+
+#[test]
+fn test_field_label_changed() {
+    let mut new = old();
+    new.file[0].message_type[0].field[3].label = Some(Label::Optional as i32);
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::FieldLabelChanged {
+            message: item(),
+            name: "tags".to_owned(),
+            old: Label::Repeated,
+            new: Label::Optional,
+        }]
+    );
+}
+
+#[test]
+fn test_enum_removed() {
+    let mut new = old();
+    new.file[0].enum_type.clear();
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::EnumRemoved { r#enum: color() }]
+    );
+}
+
+#[test]
+fn test_enum_value_removed() {
+    let mut new = old();
+    new.file[0].enum_type[0].value.remove(2);
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::EnumValueRemoved {
+            r#enum: color(),
+            name: "GREEN".to_owned(),
+            number: 2,
+        }]
+    );
+
+    new.file[0].enum_type[0]
+        .reserved_range
+        .push(EnumReservedRange {
+            start: Some(2),
+            end: Some(2),
+        });
+    assert_eq!(check_compatibility(&old(), &new), []);
+}
+
+#[test]
+fn test_enum_value_number_changed() {
+    let mut new = old();
+    new.file[0].enum_type[0].value[1].number = Some(3);
+    assert_eq!(
+        check_compatibility(&old(), &new),
+        [BreakingChange::EnumValueNumberChanged {
+            r#enum: color(),
+            name: "BLUE".to_owned(),
+            old: 1,
+            new: 3,
+        }]
+    );
+}
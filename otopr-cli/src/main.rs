@@ -0,0 +1,139 @@
+//! `otopr`, a command-line tool for inspecting protobuf payloads.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+use otopr::decoding::raw::RawMessage;
+use otopr::dynamic::{DescriptorPool, DynamicMessage, MessageSchema};
+use otopr::encoding::{EncodableMessage, ProtobufSerializer};
+use otopr::json::{ParseOptions, PrintOptions};
+use otopr::text_format;
+
+#[cfg(test)]
+mod tests;
+
+const USAGE: &str = "\
+usage:
+    otopr decode-raw [FILE]
+    otopr decode --descriptor-set SET --type NAME [--json] [FILE]
+    otopr encode --descriptor-set SET --type NAME [--json] [FILE]
+
+Reads FILE, or stdin if it is missing or `-`.
+
+commands:
+    decode-raw  print binary input without a schema, like `protoc --decode_raw`
+    decode      print binary input as a message of type NAME
+    encode      read a message of type NAME and write it in binary
+
+options:
+    --descriptor-set SET  a `FileDescriptorSet` as written by `protoc --descriptor_set_out`
+    --type NAME           the fully qualified message name, like `google.protobuf.Duration`
+    --json                print or read the proto3 JSON mapping instead of text format
+";
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+#[derive(Default)]
+struct Args {
+    command: String,
+    descriptor_set: Option<String>,
+    type_name: Option<String>,
+    json: bool,
+    input: Option<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut this = Args {
+            command: args
+                .next()
+                .ok_or_else(|| format!("missing command\n\n{}", USAGE))?,
+            ..Args::default()
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--descriptor-set" => this.descriptor_set = args.next(),
+                "--type" => this.type_name = args.next(),
+                "--json" => this.json = true,
+                "-h" | "--help" => this.command = "help".to_owned(),
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option `{}`", arg).into())
+                }
+                _ if this.input.is_none() => this.input = Some(arg),
+                _ => return Err(format!("unexpected argument `{}`", arg).into()),
+            }
+        }
+        Ok(this)
+    }
+
+    fn read_input(&self) -> Result<Vec<u8>> {
+        match self.input.as_deref() {
+            None | Some("-") => {
+                let mut buf = Vec::new();
+                io::stdin().read_to_end(&mut buf)?;
+                Ok(buf)
+            }
+            Some(path) => Ok(fs::read(path).map_err(|e| format!("{}: {}", path, e))?),
+        }
+    }
+
+    fn schema(&self) -> Result<MessageSchema> {
+        let path = self
+            .descriptor_set
+            .as_deref()
+            .ok_or("missing `--descriptor-set`")?;
+        let type_name = self.type_name.as_deref().ok_or("missing `--type`")?;
+        let set = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let pool = DescriptorPool::decode(set.as_slice())?;
+        Ok(pool
+            .get_message(type_name)
+            .ok_or_else(|| format!("no message named `{}` in {}", type_name, path))?)
+    }
+}
+
+fn run(args: Args, out: &mut impl Write) -> Result<()> {
+    match args.command.as_str() {
+        "decode-raw" => {
+            let raw = RawMessage::decode(args.read_input()?.as_slice())?;
+            write!(out, "{}", raw)?;
+        }
+        "decode" => {
+            let schema = args.schema()?;
+            let message = DynamicMessage::decode(schema, args.read_input()?.as_slice())?;
+            if args.json {
+                let json = message.to_json(&PrintOptions::default());
+                writeln!(out, "{}", otopr::json::Value::to_string(&json))?;
+            } else {
+                write!(out, "{}", text_format::to_string(&message))?;
+            }
+        }
+        "encode" => {
+            let schema = args.schema()?;
+            let input = String::from_utf8(args.read_input()?)?;
+            let message = if args.json {
+                let json = input.parse()?;
+                DynamicMessage::from_json(schema, &json, &ParseOptions::default())?
+            } else {
+                let mut message = DynamicMessage::new(schema);
+                text_format::Parser::new(&input).merge_fields(&mut message, None)?;
+                message
+            };
+            let mut buf = Vec::with_capacity(message.encoded_size());
+            message.encode(&mut ProtobufSerializer::new(&mut buf));
+            out.write_all(&buf)?;
+        }
+        "help" => write!(out, "{}", USAGE)?,
+        command => return Err(format!("unknown command `{}`\n\n{}", command, USAGE).into()),
+    }
+    Ok(())
+}
+
+fn main() {
+    let res = Args::parse(std::env::args().skip(1)).and_then(|args| run(args, &mut io::stdout()));
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
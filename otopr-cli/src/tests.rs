@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::PathBuf;
+
+use otopr::descriptor::FileDescriptorSet;
+use otopr::reflect::ProtoFile;
+use otopr::Repeated;
+
+use super::*;
+
+#[derive(otopr::EncodableMessage, otopr::DecodableMessage, Default, Debug, PartialEq)]
+#[otopr(name = "cli.Item")]
+struct Item {
+    #[otopr(1)]
+    name: String,
+    #[otopr(2)]
+    count: u32,
+}
+
+fn item() -> Item {
+    Item {
+        name: "pen".to_owned(),
+        count: 3,
+    }
+}
+
+fn encode(message: &impl EncodableMessage) -> Vec<u8> {
+    let mut buf = Vec::new();
+    message.encode(&mut ProtobufSerializer::new(&mut buf));
+    buf
+}
+
+/// A file in the temporary directory, unique to the test and this process.
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("otopr-cli-{}-{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Runs the command line `args` with the file `name` holding `input`, returning the output.
+fn otopr(name: &str, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let set = FileDescriptorSet {
+        file: Repeated::new(vec![ProtoFile::new("cli.proto")
+            .package("cli")
+            .message::<Item>()
+            .to_descriptor()
+            .unwrap()]),
+    };
+    let set = temp_file(&format!("{}.pb", name), &encode(&set));
+    let input = temp_file(name, input);
+
+    let args = args.iter().map(|&a| a.to_owned()).chain(vec![
+        "--descriptor-set".to_owned(),
+        set.display().to_string(),
+        input.display().to_string(),
+    ]);
+    let mut out = Vec::new();
+    let res = Args::parse(args).and_then(|args| run(args, &mut out));
+    fs::remove_file(set).unwrap();
+    fs::remove_file(input).unwrap();
+    res.unwrap();
+    out
+}
+
+#[test]
+fn test_decode_raw() {
+    let out = otopr("decode-raw", &["decode-raw"], &encode(&item()));
+    assert_eq!(String::from_utf8(out).unwrap(), "1: \"pen\"\n2: 3\n");
+}
+
+#[test]
+fn test_decode_and_encode() {
+    let bytes = encode(&item());
+    let text = otopr("decode", &["decode", "--type", "cli.Item"], &bytes);
+    assert_eq!(
+        String::from_utf8(text.clone()).unwrap(),
+        "name: \"pen\"\ncount: 3\n"
+    );
+    let encoded = otopr("encode", &["encode", "--type", "cli.Item"], &text);
+    assert_eq!(encoded, bytes);
+}
+
+#[test]
+fn test_json() {
+    let bytes = encode(&item());
+    let json = otopr(
+        "decode-json",
+        &["decode", "--type", "cli.Item", "--json"],
+        &bytes,
+    );
+    assert_eq!(
+        String::from_utf8(json.clone()).unwrap(),
+        "{\"count\":3,\"name\":\"pen\"}\n"
+    );
+    let encoded = otopr(
+        "encode-json",
+        &["encode", "--type", "cli.Item", "--json"],
+        &json,
+    );
+    assert_eq!(encoded, bytes);
+}
@@ -1,12 +1,14 @@
 use std::iter::FromIterator;
 use std::time::SystemTime;
 
-use proc_macro2::{Ident, Span, TokenStream as Ts2};
+use proc_macro2::{Ident, Span, TokenStream as Ts2, TokenTree};
+use quote::{quote, ToTokens};
 use syn::{
     parenthesized,
     parse::{Parse, ParseStream, Parser},
-    parse2, Attribute, Data, Error, Expr, LitInt, Member, Token,
-    Type,
+    parse2, parse_quote,
+    spanned::Spanned,
+    Attribute, Data, Error, Expr, ExprLit, ExprPath, Lit, LitInt, Member, Path, Token, Type,
 };
 
 mod input_cfg;
@@ -31,8 +33,10 @@ pub fn fields_from(input: Data) -> syn::Result<Vec<Field>> {
         .enumerate()
         .map(|(n, field)| {
             let res = Field::new(n, field, default_field_number);
-            if let Ok(f) = &res {
-                default_field_number = f.cfg.field_number + 1;
+            match &res {
+                // skipped fields do not take up a field number.
+                Ok(f) if f.cfg.skip.is_none() => default_field_number = f.cfg.field_number + 1,
+                _ => {}
             }
             res
         })
@@ -58,29 +62,206 @@ pub fn random_ident_str() -> String {
 
 impl Field {
     pub fn new(n: usize, f: syn::Field, default_field_number: u64) -> syn::Result<Self> {
+        // errors about implicit field numbers point at the field.
+        let span = f.ident.as_ref().map_or_else(|| f.ty.span(), Ident::span);
         Ok(Self {
             member: f.ident.map_or_else(|| Member::from(n), Member::from),
             ty: f.ty.clone(),
-            cfg: FieldConfig::from_attrs(f.attrs, default_field_number)?,
+            cfg: FieldConfig::from_attrs(f.attrs, default_field_number, span)?,
         })
     }
+
+    /// The name in [`MessageDescriptor`] tables, which is the index for tuple structs.
+    pub fn descriptor_name(&self) -> String {
+        match &self.member {
+            Member::Named(id) => id.to_string().trim_start_matches("r#").to_owned(),
+            Member::Unnamed(index) => index.index.to_string(),
+        }
+    }
+
+    /// The name of this field in `.proto` files, for derives that need one.
+    pub fn proto_name(&self, derive: &str) -> syn::Result<String> {
+        match &self.member {
+            Member::Named(id) => Ok(id.to_string().trim_start_matches("r#").to_owned()),
+            Member::Unnamed(_) => Err(Error::new(
+                self.cfg.field_number_span,
+                format!("`{}` requires named fields", derive),
+            )),
+        }
+    }
+}
+
+/// Converts a snake_case name to camelCase like `protoc` does, starting with an uppercase
+/// letter if `upper_first`.
+pub fn camel_case(name: &str, upper_first: bool) -> String {
+    let mut upper = upper_first;
+    name.chars()
+        .filter_map(|c| match c {
+            '_' => {
+                upper = true;
+                None
+            }
+            c if upper => {
+                upper = false;
+                Some(c.to_ascii_uppercase())
+            }
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Whether `tokens` mentions any of `params`, such as a type parameter in a field type.
+pub fn mentions_any(tokens: impl ToTokens, params: &[&Ident]) -> bool {
+    fn walk(ts: Ts2, params: &[&Ident]) -> bool {
+        ts.into_iter().any(|tt| match tt {
+            TokenTree::Ident(id) => params.contains(&&id),
+            TokenTree::Group(g) => walk(g.stream(), params),
+            _ => false,
+        })
+    }
+    walk(tokens.into_token_stream(), params)
+}
+
+/// The range that protobuf implementations reserve for themselves.
+const IMPLEMENTATION_RESERVED: std::ops::RangeInclusive<u64> = 19000..=19999;
+
+/// The largest field number, since tags keep 3 of their 32 bits for the wire type.
+const MAX_FIELD_NUMBER: u64 = (1 << 29) - 1;
+
+/// Rejects field numbers that are zero, too large, used twice, or reserved by protobuf or
+/// `#[otopr(reserved(..))]`.
+pub fn check_field_numbers(fields: &[Field], reserved: &[Reserved]) -> syn::Result<()> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let n = field.cfg.field_number;
+            let span = field.cfg.field_number_span;
+            if n == 0 {
+                return Err(Error::new(span, "field number cannot be zero"));
+            }
+            if n > MAX_FIELD_NUMBER {
+                return Err(Error::new(
+                    span,
+                    format!("field number cannot be larger than {}", MAX_FIELD_NUMBER),
+                ));
+            }
+            if IMPLEMENTATION_RESERVED.contains(&n) {
+                return Err(Error::new(
+                    span,
+                    "field numbers 19000 to 19999 are reserved for the protobuf implementation",
+                ));
+            }
+            if let Some(r) = reserved.iter().find(|r| r.contains(n)) {
+                let mut e = Error::new(span, format!("field number {} is reserved", n));
+                e.combine(Error::new_spanned(&r.expr, "reserved here"));
+                return Err(e);
+            }
+            if let Some(other) = fields[..i].iter().find(|f| f.cfg.field_number == n) {
+                return Err(Error::new(
+                    span,
+                    format!(
+                        "field number {} is already used by `{}`",
+                        n,
+                        other.descriptor_name()
+                    ),
+                ));
+            }
+            Ok(())
+        })
+        .collect::<SynResult<()>>()
+        .inner()
 }
 
 pub struct FieldConfig {
     pub field_number: u64,
     pub field_number_span: Span,
     pub encode_via: Option<(Type, Expr)>,
+    /// The proto type and conversion module from `#[otopr(with(Proto, module))]`.
+    pub with: Option<(Type, Path)>,
+    /// A scalar type like `sint32` that the field is encoded as.
+    pub scalar: Option<Ident>,
+    /// `#[otopr(packed)]`, for repeated scalars.
+    pub packed: Option<Ident>,
+    /// `#[otopr(skip)]`, for fields that are not encoded.
+    pub skip: Option<Ident>,
+    /// The value of the field when it is missing from the input.
+    pub default: Option<Expr>,
+}
+
+/// Scalar types that fields can be encoded as, and the names of their markers in `__private::scalar`.
+const SCALARS: &[(&str, &str)] = &[
+    ("sint32", "Sint32"),
+    ("sint64", "Sint64"),
+    ("fixed32", "Fixed32"),
+    ("fixed64", "Fixed64"),
+    ("sfixed32", "Sfixed32"),
+    ("sfixed64", "Sfixed64"),
+];
+
+impl Field {
+    /// The `FieldEncoding` of fields with a scalar type or `packed`.
+    pub fn scalar_encoding(&self) -> Option<Ts2> {
+        let FieldConfig { scalar, packed, .. } = &self.cfg;
+        let marker = match scalar {
+            Some(id) => SCALARS
+                .iter()
+                .find(|(name, _)| id == name)
+                .map(|(_, marker)| Ident::new(marker, id.span()))?,
+            None if packed.is_some() => Ident::new("Native", Span::call_site()),
+            None => return None,
+        };
+        let marker = quote! { ::otopr::__private::scalar::#marker };
+        Some(if packed.is_some() {
+            quote! { ::otopr::Packed<#marker> }
+        } else {
+            marker
+        })
+    }
+}
+
+impl Field {
+    /// Sets `this.field` to its `#[otopr(default = ..)]` value, if any.
+    pub fn default_init(&self, this: &Ts2) -> Option<Ts2> {
+        let member = &self.member;
+        self.cfg
+            .default
+            .as_ref()
+            .map(|default| quote! { #this.#member = #default; })
+    }
+}
+
+/// The fields that are encoded, without the `#[otopr(skip)]` ones.
+pub fn wire_fields(fields: Vec<Field>) -> Vec<Field> {
+    fields
+        .into_iter()
+        .filter(|f| f.cfg.skip.is_none())
+        .collect()
 }
 
 impl FieldConfig {
-    pub fn from_attrs(attrs: Vec<Attribute>, default_field_number: u64) -> syn::Result<Self> {
+    pub fn from_attrs(
+        attrs: Vec<Attribute>,
+        default_field_number: u64,
+        default_span: Span,
+    ) -> syn::Result<Self> {
         let mut field_number = None;
         let mut encode_via = None;
-        let mut field_number_span = Span::call_site();
+        let mut with = None;
+        let mut scalar = None;
+        let mut packed = None;
+        let mut skip = None;
+        let mut default = None;
+        let mut field_number_span = default_span;
         for attr in attrs.into_iter().filter(|a| a.path.is_ident("otopr")) {
             let OtoprAttr {
                 field_number: f,
                 encode_via: ev,
+                with: w,
+                scalar: sc,
+                packed: p,
+                skip: sk,
+                default: d,
             } = parse2(attr.tokens)?;
             if let Some(f) = f {
                 field_number = Some(f.base10_parse()?);
@@ -89,6 +270,50 @@ impl FieldConfig {
             if let Some(ev) = ev {
                 encode_via = Some(ev);
             }
+            if let Some(w) = w {
+                with = Some(w);
+            }
+            scalar = sc.or(scalar);
+            packed = p.or(packed);
+            skip = sk.or(skip);
+            default = d.or(default);
+        }
+
+        if let Some(id) = &skip {
+            if field_number.is_some()
+                || encode_via.is_some()
+                || with.is_some()
+                || scalar.is_some()
+                || packed.is_some()
+            {
+                return Err(Error::new_spanned(
+                    id,
+                    "skipped fields cannot have a field number or an encoding",
+                ));
+            }
+        }
+
+        if encode_via.is_some() || with.is_some() {
+            if let Some(id) = scalar.as_ref().or(packed.as_ref()) {
+                return Err(Error::new_spanned(
+                    id,
+                    format!("cannot use `{}` with `encode_via` or `with`", id),
+                ));
+            }
+        }
+
+        if let Some((proto, module)) = &with {
+            if encode_via.is_some() {
+                return Err(Error::new_spanned(
+                    module,
+                    "cannot use both `encode_via` and `with`",
+                ));
+            }
+            // encoding converts to the proto type first, like `encode_via` does.
+            encode_via = Some((
+                parse_quote!(<#proto as ::otopr::__private::Encodable>::Wire),
+                parse_quote!(#module::to_proto(x)),
+            ));
         }
 
         let field_number = field_number.unwrap_or(default_field_number);
@@ -96,6 +321,11 @@ impl FieldConfig {
             field_number,
             field_number_span,
             encode_via,
+            with,
+            scalar,
+            packed,
+            skip,
+            default,
         })
     }
 }
@@ -103,15 +333,32 @@ impl FieldConfig {
 pub struct OtoprAttr {
     pub field_number: Option<LitInt>,
     pub encode_via: Option<(Type, Expr)>,
+    pub with: Option<(Type, Path)>,
+    pub scalar: Option<Ident>,
+    pub packed: Option<Ident>,
+    pub skip: Option<Ident>,
+    pub default: Option<Expr>,
 }
 
 impl Parse for OtoprAttr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let content;
         let _ = parenthesized!(content in input);
-        let tts = content.parse_terminated::<Ts2, Token![,]>(|p| p.parse())?;
+        // each item is the tokens up to the next comma.
+        let tts = content.parse_terminated::<Ts2, Token![,]>(|p| {
+            let mut ts = Ts2::new();
+            while !p.is_empty() && !p.peek(Token![,]) {
+                ts.extend(Some(p.parse::<TokenTree>()?));
+            }
+            Ok(ts)
+        })?;
         let mut field_number = None;
         let mut via = None;
+        let mut with = None;
+        let mut scalar = None;
+        let mut packed = None;
+        let mut skip = None;
+        let mut default = None;
         for ts in tts {
             Parser::parse2(
                 |p: ParseStream| {
@@ -127,8 +374,37 @@ impl Parse for OtoprAttr {
                             let _: Token![,] = content.parse()?;
                             let expr = content.parse()?;
                             via = Some((ty, expr));
+                        } else if id == "with" {
+                            let content;
+                            let _ = parenthesized!(content in p);
+                            let ty = content.parse()?;
+                            let _: Token![,] = content.parse()?;
+                            let module = content.parse()?;
+                            with = Some((ty, module));
+                        } else if id == "packed" {
+                            packed = Some(id);
+                        } else if id == "skip" {
+                            skip = Some(id);
+                        } else if id == "default" {
+                            let _: Token![=] = p.parse()?;
+                            // a string is the path of a function returning the value, like in serde.
+                            default = Some(match p.parse()? {
+                                Expr::Lit(ExprLit {
+                                    lit: Lit::Str(path),
+                                    ..
+                                }) => {
+                                    let path: ExprPath = path.parse()?;
+                                    parse_quote!(#path())
+                                }
+                                expr => expr,
+                            });
+                        } else if SCALARS.iter().any(|(name, _)| id == name) {
+                            scalar = Some(id);
                         } else {
-                            return Err(Error::new_spanned(id, "expected 'encode_via'"));
+                            return Err(Error::new_spanned(
+                                id,
+                                "expected `encode_via`, `with`, `packed`, `skip`, `default` or a scalar type like `sint32`",
+                            ));
                         }
                     } else {
                         return Err(lookahead.error());
@@ -141,6 +417,11 @@ impl Parse for OtoprAttr {
         Ok(Self {
             field_number,
             encode_via: via,
+            with,
+            scalar,
+            packed,
+            skip,
+            default,
         })
     }
 }
@@ -4,18 +4,83 @@ use syn::{
     parenthesized,
     parse::{ParseStream, Parser},
     punctuated::Punctuated,
-    Attribute, Error, Token, TypeParam, WhereClause,
+    Attribute, Error, Expr, ExprLit, ExprRange, Lit, LitStr, RangeLimits, Token, TypeParam,
+    WhereClause,
 };
 
 pub struct InputCfg {
     pub encode_where_clause: Option<WhereClause>,
     pub encode_extra_type_params: Option<Punctuated<TypeParam, Token![,]>>,
+    pub decode_where_clause: Option<WhereClause>,
+    /// The fully qualified protobuf name from `#[otopr(name = "pkg.Msg")]`.
+    pub name: Option<LitStr>,
+    /// Field numbers from `#[otopr(reserved(5, 9..=12))]`.
+    pub reserved: Vec<Reserved>,
+}
+
+/// An inclusive range of reserved field numbers.
+pub struct Reserved {
+    pub start: u64,
+    pub end: u64,
+    pub expr: Expr,
+}
+
+impl Reserved {
+    fn from_expr(expr: &Expr) -> syn::Result<Self> {
+        fn number(expr: &Option<Box<Expr>>) -> Option<syn::Result<u64>> {
+            match expr.as_deref() {
+                Some(Expr::Lit(ExprLit {
+                    lit: Lit::Int(n), ..
+                })) => Some(n.base10_parse()),
+                _ => None,
+            }
+        }
+
+        let (start, end) = match expr {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(n), ..
+            }) => {
+                let n = n.base10_parse()?;
+                (n, n)
+            }
+            Expr::Range(ExprRange {
+                from, limits, to, ..
+            }) => match (number(from), number(to), limits) {
+                (Some(start), Some(end), RangeLimits::Closed(_)) => (start?, end?),
+                (Some(start), Some(end), RangeLimits::HalfOpen(_)) => {
+                    (start?, end?.saturating_sub(1))
+                }
+                _ => return Err(Error::new_spanned(expr, "expected a range like `9..=12`")),
+            },
+            _ => {
+                return Err(Error::new_spanned(
+                    expr,
+                    "expected a field number or a range like `9..=12`",
+                ))
+            }
+        };
+        if start > end {
+            return Err(Error::new_spanned(expr, "range is empty"));
+        }
+        Ok(Self {
+            start,
+            end,
+            expr: expr.clone(),
+        })
+    }
+
+    pub fn contains(&self, n: u64) -> bool {
+        (self.start..=self.end).contains(&n)
+    }
 }
 
 impl InputCfg {
     pub fn from_attrs(attrs: Vec<Attribute>) -> syn::Result<Self> {
         let mut encode_where_clause = None;
         let mut encode_extra_type_params = None;
+        let mut decode_where_clause = None;
+        let mut name = None;
+        let mut reserved = Vec::new();
         for attr in attrs {
             if attr
                 .path
@@ -38,10 +103,25 @@ impl InputCfg {
                                 let _ = parenthesized!(content2 in content);
                                 encode_extra_type_params =
                                     Some(Punctuated::parse_terminated(&content2)?);
+                            } else if id == "decode_where_clause" {
+                                let content2;
+                                let _ = parenthesized!(content2 in content);
+                                decode_where_clause = Some(content2.parse()?);
+                            } else if id == "name" {
+                                let _: Token![=] = content.parse()?;
+                                name = Some(content.parse()?);
+                            } else if id == "reserved" {
+                                let content2;
+                                let _ = parenthesized!(content2 in content);
+                                for expr in
+                                    Punctuated::<Expr, Token![,]>::parse_terminated(&content2)?
+                                {
+                                    reserved.push(Reserved::from_expr(&expr)?);
+                                }
                             } else {
                                 return Err(Error::new_spanned(
                                     id,
-                                    "expected `encode_where_clause` or `encode_extra_type_params`",
+                                    "expected `encode_where_clause`, `encode_extra_type_params`, `decode_where_clause`, `name` or `reserved`",
                                 ));
                             }
                         } else {
@@ -57,6 +137,9 @@ impl InputCfg {
         Ok(Self {
             encode_where_clause,
             encode_extra_type_params,
+            decode_where_clause,
+            name,
+            reserved,
         })
     }
 }
@@ -1,40 +1,103 @@
-use proc_macro2::{Ident, Span, TokenStream as Ts2};
+use proc_macro2::{Literal, Span, TokenStream as Ts2};
 
 use quote::{quote, ToTokens};
-use syn::{DeriveInput, Generics};
+use syn::{parse_quote, DeriveInput, Lifetime, LifetimeDef, WhereClause};
 
 use crate::common::*;
 
 impl Field {
-    pub fn match_arm(&self) -> Ts2 {
+    /// The match arm decoding this field. `generic` messages compare tags in a guard,
+    /// since patterns cannot depend on generic parameters.
+    pub fn match_arm(&self, de: &Lifetime, generic: bool) -> Ts2 {
+        let Field {
+            member,
+            ty,
+            cfg: FieldConfig {
+                field_number, with, ..
+            },
+        } = self;
+        let fnum = quote! { <Self as ::otopr::__private::HasFieldDecode<#field_number>>::FNUM };
+        let pat = if generic {
+            quote! { t if t == #fnum }
+        } else {
+            fnum.clone()
+        };
+        if let Some(encoding) = self.scalar_encoding() {
+            let encoding = quote! { <#encoding as ::otopr::__private::FieldEncoding<#ty>> };
+            // repeated scalars also accept the other one of packed and unpacked.
+            return quote! {
+                #pat => #encoding::merge_from(&mut self.#member, d)?,
+                t if matches!(#encoding::ALT_WIRE, Some(w) if t == #fnum >> 3 << 3 | w as Self::Tag) => #encoding::merge_alt(&mut self.#member, d)?,
+            };
+        }
+        match with {
+            // the converted value replaces the old one instead of merging.
+            Some((proto, module)) => quote! {
+                #pat => {
+                    let proto = <#proto as ::otopr::__private::Decodable<#de>>::decode(d)?;
+                    let value: ::core::result::Result<#ty, _> = #module::from_proto(proto);
+                    self.#member = value.map_err(|e| ::otopr::__private::DecodingError::Conversion(e.into()))?;
+                }
+            },
+            None => quote! {
+                #pat => <#ty as ::otopr::__private::Decodable<#de>>::merge_from(&mut self.#member, d)?,
+            },
+        }
+    }
+
+    /// The match arm decoding this field with its submask, for fields that have submessages.
+    fn masked_match_arm(&self, de: &Lifetime, generic: bool) -> Option<Ts2> {
+        if self.scalar_encoding().is_some() || self.cfg.with.is_some() {
+            return None;
+        }
         let Field {
             member,
             ty,
             cfg: FieldConfig { field_number, .. },
-            ..
         } = self;
-        quote! {
-            <Self as ::otopr::__private::HasFieldDecode<#field_number>>::FNUM => <#ty as ::otopr::__private::Decodable>::merge_from(&mut self.#member, d)?,
+        let number = Literal::u32_unsuffixed(*field_number as u32);
+        let fnum = quote! { <Self as ::otopr::__private::HasFieldDecode<#field_number>>::FNUM };
+        let pat = if generic {
+            quote! { t if t == #fnum }
+        } else {
+            fnum
+        };
+        Some(quote! {
+            #pat => match mask.submask(#number) {
+                ::core::option::Option::Some(m) => <#ty as ::otopr::__private::Decodable<#de>>::merge_from_masked(&mut self.#member, d, m)?,
+                ::core::option::Option::None => self.decode_field(d, tag)?,
+            },
+        })
+    }
+
+    /// The wire type of the field when decoding.
+    fn decoded_wire_ty(&self, de: &Lifetime) -> Ts2 {
+        let ty = &self.ty;
+        match (self.scalar_encoding(), &self.cfg.with) {
+            (Some(encoding), _) => {
+                quote! { <#encoding as ::otopr::__private::FieldEncoding<#ty>>::Wire }
+            }
+            (None, Some((proto, _))) => {
+                quote! { <#proto as ::otopr::__private::Decodable<#de>>::Wire }
+            }
+            (None, None) => quote! { <#ty as ::otopr::__private::Decodable<#de>>::Wire },
         }
     }
 
     pub fn has_field_decode_impl(
         &self,
         cty: &Ts2,
+        de: &Lifetime,
         impl_generics: &Ts2,
-        name: &Ident,
-        generics: &Generics,
+        self_ty: &Ts2,
     ) -> Ts2 {
-        let Field {
-            ty,
-            cfg: FieldConfig { field_number, .. },
-            ..
-        } = self;
+        let FieldConfig { field_number, .. } = &self.cfg;
+        let wire = self.decoded_wire_ty(de);
         quote! {
             #[doc(hidden)] // internal implementation details
-            impl #impl_generics ::otopr::__private::HasFieldDecode<#field_number> for #name #generics {
+            impl #impl_generics ::otopr::__private::HasFieldDecode<#field_number> for #self_ty {
                 type VarInt = #cty;
-                const FNUM: #cty = (#field_number << 3) as #cty | <<#ty as ::otopr::__private::Decodable>::Wire as ::otopr::__private::WireType>::BITS;
+                const FNUM: #cty = (#field_number << 3) as #cty | <#wire as ::otopr::__private::WireType>::BITS;
             }
         }
     }
@@ -76,60 +139,159 @@ impl Field {
         })
     }
 
-    pub fn merge(&self) -> Ts2 {
-        let Field { member, ty, .. } = self;
+    pub fn merge(&self, de: &Lifetime) -> Ts2 {
+        let Field { member, ty, cfg } = self;
 
-        quote! {
-            <#ty as ::otopr::__private::Decodable<'de>>::merge(&mut self.#member, other.#member);
+        if let Some(encoding) = self.scalar_encoding() {
+            quote! {
+                <#encoding as ::otopr::__private::FieldEncoding<#ty>>::merge(&mut self.#member, other.#member);
+            }
+        } else if cfg.with.is_some() {
+            quote! {
+                self.#member = other.#member;
+            }
+        } else {
+            quote! {
+                <#ty as ::otopr::__private::Decodable<#de>>::merge(&mut self.#member, other.#member);
+            }
         }
     }
 }
 
 pub(crate) fn derive_decodable_message(input: DeriveInput) -> syn::Result<Ts2> {
     let name = input.ident;
-    let mut generics = input.generics;
-    let impl_generics = generics.clone();
-    generics.type_params_mut().for_each(|f| f.bounds.clear());
-    let impl_generics = if !impl_generics.lifetimes().any(|d| d.lifetime.ident == "de") {
-        let params = impl_generics.params;
-        quote! {
-            <'de, #params>
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let mut generics = input.generics.clone();
+
+    // borrowed fields point into the input, so a lifetime named `'de` or the only lifetime of
+    // the message is used for it. Otherwise `'de` is added and outlives all lifetimes.
+    let lifetimes: Vec<_> = generics.lifetimes().map(|d| d.lifetime.clone()).collect();
+    let de = match lifetimes.iter().find(|l| l.ident == "de") {
+        Some(l) => l.clone(),
+        None if lifetimes.len() == 1 => lifetimes[0].clone(),
+        None => {
+            let de: Lifetime = parse_quote!('de);
+            let mut def = LifetimeDef::new(de.clone());
+            def.bounds = lifetimes.into_iter().collect();
+            generics.params.insert(0, def.into());
+            de
         }
-    } else {
-        impl_generics.into_token_stream()
     };
 
+    let input_cfg = InputCfg::from_attrs(input.attrs)?;
     let fields = fields_from(input.data)?;
+    let this = quote! { self };
+    let default_inits: Vec<_> = fields
+        .iter()
+        .filter_map(|f| f.default_init(&this))
+        .collect();
+    let fields = wire_fields(fields);
+    check_field_numbers(&fields, &input_cfg.reserved)?;
+
+    // fields with generic types need `Decodable` bounds.
+    let type_params: Vec<_> = input.generics.type_params().map(|p| &p.ident).collect();
+    let where_clause = generics.make_where_clause();
+    for f in &fields {
+        let ty = &f.ty;
+        if !mentions_any(ty, &type_params) {
+            continue;
+        }
+        where_clause
+            .predicates
+            .push(match (f.scalar_encoding(), &f.cfg.with) {
+                (Some(encoding), _) => {
+                    parse_quote!(#encoding: ::otopr::__private::FieldEncoding<#ty>)
+                }
+                (None, Some((proto, _))) => {
+                    parse_quote!(#proto: ::otopr::__private::Decodable<#de>)
+                }
+                (None, None) => parse_quote!(#ty: ::otopr::__private::Decodable<#de>),
+            });
+    }
+    if let Some(w) = input_cfg.decode_where_clause {
+        where_clause.predicates.extend(w.predicates);
+    }
+    let mut default_where_clause: WhereClause = where_clause.clone();
+    default_where_clause
+        .predicates
+        .push(parse_quote!(Self: Default));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
 
     let max = Field::max_field_tag_size(&fields)?;
     let cty = &max;
 
-    let const_defs = fields.iter().map(|f| f.has_field_decode_impl(cty, &impl_generics, &name, &generics));
-    let match_arms = fields.iter().map(Field::match_arm);
-    let merges = fields.iter().map(Field::merge);
+    let impl_generics = impl_generics.into_token_stream();
+    let self_ty = quote! { #name #ty_generics #where_clause };
+    let const_defs = fields
+        .iter()
+        .map(|f| f.has_field_decode_impl(cty, &de, &impl_generics, &self_ty));
+    let generic = !type_params.is_empty();
+    let match_arms = fields.iter().map(|f| f.match_arm(&de, generic));
+    let masked_match_arms: Vec<_> = fields
+        .iter()
+        .filter_map(|f| f.masked_match_arm(&de, generic))
+        .collect();
+    // fields without submessages decode as usual when they are in the mask.
+    let decode_masked_field = if masked_match_arms.is_empty() {
+        quote! { self.decode_field(d, tag)?; }
+    } else {
+        quote! {
+            match tag {
+                #(#masked_match_arms)*
+                _ => self.decode_field(d, tag)?,
+            }
+        }
+    };
+    let merges = fields.iter().map(|f| f.merge(&de));
+
+    let init_defaults = if default_inits.is_empty() {
+        None
+    } else {
+        Some(quote! {
+            fn init_defaults(&mut self) {
+                #(#default_inits)*
+            }
+        })
+    };
 
     let methods = quote! {
         type Tag = #cty;
-        fn decode_field<B: ::otopr::__private::Buf>(&mut self, d: &mut ::otopr::__private::Deserializer<'de, B>, tag: Self::Tag) -> ::otopr::__private::Result<()> {
+        #init_defaults
+        fn decode_field<B: ::otopr::__private::Buf>(&mut self, d: &mut ::otopr::__private::Deserializer<#de, B>, tag: Self::Tag) -> ::otopr::__private::Result<()> {
             match tag {
                 #(#match_arms)*
                 _ => ::otopr::__private::WireTypes::new((tag & 0b111) as u8)?.skip(d)?,
             }
             Ok(())
         }
+        fn decode_field_masked<B: ::otopr::__private::Buf>(&mut self, d: &mut ::otopr::__private::Deserializer<#de, B>, tag: Self::Tag, mask: &::otopr::__private::Mask) -> ::otopr::__private::Result<()> {
+            if !mask.contains((tag >> 3) as u32) {
+                ::otopr::__private::WireTypes::new((tag & 0b111) as u8)?.skip(d)?;
+                return Ok(());
+            }
+            #decode_masked_field
+            Ok(())
+        }
     };
 
     Ok(quote! {
         #(#const_defs)*
-        impl #impl_generics ::otopr::__private::DecodableMessage<'de> for #name #generics {
+        impl #impl_generics ::otopr::__private::DecodableMessage<#de> for #self_ty {
             #methods
         }
-        impl #impl_generics ::otopr::__private::Decodable<'de> for #name #generics where Self: Default {
+        impl #impl_generics ::otopr::__private::Decodable<#de> for #name #ty_generics #default_where_clause {
             type Wire = ::otopr::__private::LengthDelimitedWire;
-            fn decode<B: ::otopr::__private::Buf>(d: &mut ::otopr::__private::Deserializer<'de, B>) -> ::otopr::__private::Result<Self> {
+            fn decode<B: ::otopr::__private::Buf>(d: &mut ::otopr::__private::Deserializer<#de, B>) -> ::otopr::__private::Result<Self> {
+                let len = d.read_varint()?;
+                let tk = d.set_limit(len);
+                let message = <Self as ::otopr::__private::DecodableMessage<#de>>::decode(d);
+                d.reset_limit(tk);
+                message
+            }
+            fn decode_masked<B: ::otopr::__private::Buf>(d: &mut ::otopr::__private::Deserializer<#de, B>, mask: &::otopr::__private::Mask) -> ::otopr::__private::Result<Self> {
                 let len = d.read_varint()?;
                 let tk = d.set_limit(len);
-                let message = <Self as ::otopr::__private::DecodableMessage<'de>>::decode(d);
+                let message = <Self as ::otopr::__private::DecodableMessage<#de>>::decode_masked(d, mask);
                 d.reset_limit(tk);
                 message
             }
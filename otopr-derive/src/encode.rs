@@ -1,7 +1,7 @@
-use proc_macro2::{Ident, Span, TokenStream as Ts2};
+use proc_macro2::{Ident, Literal, Span, TokenStream as Ts2};
 
-use quote::quote;
-use syn::{punctuated::Pair, DeriveInput, Error, GenericParam, Generics};
+use quote::{format_ident, quote};
+use syn::{parse_quote, punctuated::Pair, DeriveInput, GenericParam, Generics, LitStr, Visibility};
 
 use crate::common::*;
 
@@ -19,7 +19,11 @@ impl Field {
             ..
         } = self;
 
-        if let Some((_, expr)) = encode_via {
+        if let Some(encoding) = self.scalar_encoding() {
+            quote! {{
+                <#encoding as ::otopr::__private::FieldEncoding<#ty>>::encoded_size(&self.#member, #field_number)
+            }}
+        } else if let Some((_, expr)) = encode_via {
             quote! {{
                 let x = &self.#member;
                 let encode = #expr;
@@ -44,7 +48,11 @@ impl Field {
                 },
             ..
         } = self;
-        let tt = if let Some((_, expr)) = encode_via {
+        let tt = if let Some(encoding) = self.scalar_encoding() {
+            quote! {
+                <#encoding as ::otopr::__private::FieldEncoding<#ty>>::encode_field_precomputed(&self.#member, s, &<Self as ::otopr::__private::HasField<#field_number>>::PRECOMP);
+            }
+        } else if let Some((_, expr)) = encode_via {
             quote! {
                 {
                     let x = &self.#member;
@@ -64,14 +72,110 @@ impl Field {
         Ok(tt)
     }
 
+    /// Whether the field is encoded as itself, so masks can apply to its submessages.
+    fn maskable(&self) -> bool {
+        self.scalar_encoding().is_none() && self.cfg.encode_via.is_none()
+    }
+
+    pub fn encoded_size_masked(&self) -> Ts2 {
+        let Field {
+            member,
+            ty,
+            cfg: FieldConfig { field_number, .. },
+        } = self;
+        let number = Literal::u32_unsuffixed(*field_number as u32);
+        let unmasked = self.encoded_size();
+        let size = if self.maskable() {
+            quote! {
+                match mask.submask(#number) {
+                    ::core::option::Option::Some(m) => <#ty as ::otopr::__private::Encodable>::encoded_size_masked(&self.#member, #field_number, m),
+                    ::core::option::Option::None => #unmasked,
+                }
+            }
+        } else {
+            unmasked
+        };
+        quote! {
+            if mask.contains(#number) { #size } else { 0 }
+        }
+    }
+
+    pub fn encode_masked(&self) -> syn::Result<Ts2> {
+        let Field {
+            member,
+            ty,
+            cfg: FieldConfig { field_number, .. },
+        } = self;
+        let number = Literal::u32_unsuffixed(*field_number as u32);
+        let unmasked = self.encode()?;
+        let encode = if self.maskable() {
+            quote! {
+                match mask.submask(#number) {
+                    ::core::option::Option::Some(m) => <#ty as ::otopr::__private::Encodable>::encode_field_masked(&self.#member, s, #field_number, m),
+                    ::core::option::Option::None => { #unmasked }
+                }
+            }
+        } else {
+            unmasked
+        };
+        Ok(quote! {
+            if mask.contains(#number) { #encode }
+        })
+    }
+
     pub fn wire_ty(&self) -> Ts2 {
         let self_ty = &self.ty;
+        if let Some(encoding) = self.scalar_encoding() {
+            return quote! { <#encoding as ::otopr::__private::FieldEncoding<#self_ty>>::Wire };
+        }
         match &self.cfg.encode_via {
             Some((ty, _)) => quote! { #ty },
             None => quote! { <#self_ty as ::otopr::__private::Encodable>::Wire },
         }
     }
 
+    /// An entry in the `MessageDescriptor::FIELDS` table.
+    pub fn descriptor(&self) -> Ts2 {
+        let name = self.descriptor_name();
+        let number = Literal::u64_unsuffixed(self.cfg.field_number);
+        let wire = self.wire_ty();
+        let ty = &self.ty;
+        let mut packed = quote! { false };
+        let (cardinality, kind) = if let Some(encoding) = self.scalar_encoding() {
+            let encoding = quote! { <#encoding as ::otopr::__private::FieldEncoding<#ty>> };
+            packed = quote! { #encoding::PACKED };
+            (
+                quote! { #encoding::CARDINALITY },
+                quote! { #encoding::KIND },
+            )
+        } else if let Some((proto, _)) = &self.cfg.with {
+            (
+                quote! { <#proto as ::otopr::__private::ProtoType>::CARDINALITY },
+                quote! { <#proto as ::otopr::__private::ProtoType>::KIND },
+            )
+        } else if self.cfg.encode_via.is_some() {
+            (
+                quote! { ::otopr::__private::Cardinality::Singular },
+                quote! { ::otopr::__private::Kind::Custom },
+            )
+        } else {
+            (
+                quote! { <#ty as ::otopr::__private::ProtoType>::CARDINALITY },
+                quote! { <#ty as ::otopr::__private::ProtoType>::KIND },
+            )
+        };
+        quote! {
+            ::otopr::__private::FieldDescriptor {
+                name: #name,
+                number: #number,
+                wire_type: <#wire as ::otopr::__private::WireType>::WIRE_TYPE,
+                cardinality: #cardinality,
+                kind: #kind,
+                packed: #packed,
+            }
+        }
+    }
+
     pub fn has_field_impl(
         &self,
         impl_generics: &Generics,
@@ -89,14 +193,7 @@ impl Field {
             ..
         } = self;
         let ty = self.wire_ty();
-        let num_bytes_it_takes = if *field_number == 0 {
-            return Err(Error::new(
-                *field_number_span,
-                "field number cannot be zero",
-            ));
-        } else {
-            Self::field_tag_num_bytes(*field_number, *field_number_span)?
-        };
+        let num_bytes_it_takes = Self::field_tag_num_bytes(*field_number, *field_number_span)?;
         Ok(quote! {
             #[doc(hidden)] // internal implementation details
             impl #impl_generics ::otopr::__private::HasField<#field_number> for #name #generics #where_clause {
@@ -140,14 +237,83 @@ impl Field {
     }
 }
 
+/// `FIELD_X` constants and a `{Name}FieldNumber` enum with the numbers of the fields.
+fn field_numbers(name: &Ident, vis: &Visibility, generics: &Generics, fields: &[Field]) -> Ts2 {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let enum_name = format_ident!("{}FieldNumber", name);
+    let numbers: Vec<_> = fields
+        .iter()
+        .map(|f| Literal::u32_unsuffixed(f.cfg.field_number as u32))
+        .collect();
+    let names: Vec<_> = fields.iter().map(Field::descriptor_name).collect();
+    let consts = names
+        .iter()
+        .map(|n| format_ident!("FIELD_{}", n.to_uppercase()));
+    let variants: Vec<_> = names
+        .iter()
+        .map(|n| match n.parse::<usize>() {
+            Ok(index) => format_ident!("Field{}", index),
+            Err(_) => format_ident!("{}", camel_case(n, true)),
+        })
+        .collect();
+    let enum_doc = format!("The field numbers of [`{}`].", name);
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(pub const #consts: u32 = #numbers;)*
+        }
+
+        #[doc = #enum_doc]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #vis enum #enum_name {
+            #(#variants = #numbers,)*
+        }
+
+        impl #enum_name {
+            pub fn number(self) -> u32 {
+                match self {
+                    #(Self::#variants => #numbers,)*
+                }
+            }
+
+            pub fn from_number(number: u32) -> ::core::option::Option<Self> {
+                match number {
+                    #(#numbers => ::core::option::Option::Some(Self::#variants),)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+
+            /// The name of the field in the message descriptor.
+            pub fn name(self) -> &'static str {
+                match self {
+                    #(Self::#variants => #names,)*
+                }
+            }
+        }
+    }
+}
+
 pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
     let name = input.ident;
-    let mut impl_generics = input.generics;
+    let (name_impl_generics, name_ty_generics, name_where_clause) = input.generics.split_for_impl();
+    let mut impl_generics = input.generics.clone();
     let mut generics = impl_generics.clone();
     let mut where_clause = impl_generics.where_clause.take();
 
     let input_cfg = InputCfg::from_attrs(input.attrs)?;
 
+    let descriptor_name = input_cfg
+        .name
+        .as_ref()
+        .map_or_else(|| name.to_string(), LitStr::value);
+    let name_impl = input_cfg.name.map(|proto_name| {
+        quote! {
+            impl #name_impl_generics ::otopr::__private::Name for #name #name_ty_generics #name_where_clause {
+                const NAME: &'static str = #proto_name;
+            }
+        }
+    });
+
     match (&mut where_clause, input_cfg.encode_where_clause) {
         (Some(w), Some(w1)) => w.predicates.extend(w1.predicates),
         (w, Some(w1)) => *w = Some(w1),
@@ -172,7 +338,29 @@ pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
 
     generics.type_params_mut().for_each(|f| f.bounds.clear());
 
-    let fields = fields_from(input.data)?;
+    let fields = wire_fields(fields_from(input.data)?);
+    check_field_numbers(&fields, &input_cfg.reserved)?;
+
+    // fields with generic types need `ProtoType` bounds.
+    let type_params: Vec<_> = input.generics.type_params().map(|p| &p.ident).collect();
+    let mut descriptor_where_clause = input
+        .generics
+        .where_clause
+        .clone()
+        .unwrap_or_else(|| parse_quote!(where));
+    for f in &fields {
+        let ty = &f.ty;
+        if f.cfg.encode_via.is_none()
+            && f.scalar_encoding().is_none()
+            && mentions_any(ty, &type_params)
+        {
+            descriptor_where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::otopr::__private::ProtoType));
+        }
+    }
+    let field_descriptors = fields.iter().map(Field::descriptor);
+    let field_numbers = field_numbers(&name, &input.vis, &input.generics, &fields);
 
     let field_encoded_sizes = fields.iter().map(Field::encoded_size);
     let field_encodes: Vec<_> = fields
@@ -181,6 +369,13 @@ pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
         .collect::<SynResult<_>>()
         .inner()?;
 
+    let field_encoded_sizes_masked = fields.iter().map(Field::encoded_size_masked);
+    let field_encodes_masked: Vec<_> = fields
+        .iter()
+        .map(Field::encode_masked)
+        .collect::<SynResult<_>>()
+        .inner()?;
+
     let methods = quote! {
         fn encoded_size(&self) -> usize {
             0 #(+ #field_encoded_sizes)*
@@ -188,6 +383,12 @@ pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
         fn encode<__BufMut: ::otopr::__private::BufMut>(&self, s: &mut ::otopr::__private::ProtobufSerializer<__BufMut>) {
             #(#field_encodes)*
         }
+        fn encoded_size_masked(&self, mask: &::otopr::__private::Mask) -> usize {
+            0 #(+ #field_encoded_sizes_masked)*
+        }
+        fn encode_masked<__BufMut: ::otopr::__private::BufMut>(&self, s: &mut ::otopr::__private::ProtobufSerializer<__BufMut>, mask: &::otopr::__private::Mask) {
+            #(#field_encodes_masked)*
+        }
     };
 
     let has_field_impls = fields
@@ -199,6 +400,20 @@ pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
     Ok(quote! {
         #(#has_field_impls)*
 
+        #name_impl
+
+        #field_numbers
+
+        impl #name_impl_generics ::otopr::__private::MessageDescriptor for #name #name_ty_generics #descriptor_where_clause {
+            const NAME: &'static str = #descriptor_name;
+            const FIELDS: &'static [::otopr::__private::FieldDescriptor] = &[#(#field_descriptors),*];
+        }
+        impl #name_impl_generics ::otopr::__private::ProtoType for #name #name_ty_generics #descriptor_where_clause {
+            const KIND: ::otopr::__private::Kind = ::otopr::__private::Kind::Message(
+                ::otopr::__private::MessageType::of::<Self>(),
+            );
+        }
+
         impl #impl_generics ::otopr::__private::EncodableMessage for #name #generics #where_clause {
             #methods
         }
@@ -216,6 +431,17 @@ pub(crate) fn derive_encodable_message(input: DeriveInput) -> syn::Result<Ts2> {
                 s.write_varint(::otopr::__private::EncodableMessage::encoded_size(self));
                 ::otopr::__private::EncodableMessage::encode(self, s)
             }
+
+            fn encoded_size_masked<__VarInt: ::otopr::__private::VarInt>(&self, field_number: __VarInt, mask: &::otopr::__private::Mask) -> usize {
+                let calc_size = ::otopr::__private::EncodableMessage::encoded_size_masked(self, mask);
+                ::otopr::__private::VarInt::size(field_number) + ::otopr::__private::VarInt::size(calc_size) + calc_size
+            }
+
+            fn encode_field_masked<__VarInt: ::otopr::__private::VarInt>(&self, s: &mut ::otopr::__private::ProtobufSerializer<impl ::otopr::__private::BufMut>, field_number: __VarInt, mask: &::otopr::__private::Mask) {
+                s.write_varint(field_number << 3 | __VarInt::from(<::otopr::__private::LengthDelimitedWire as ::otopr::__private::WireType>::BITS));
+                s.write_varint(::otopr::__private::EncodableMessage::encoded_size_masked(self, mask));
+                ::otopr::__private::EncodableMessage::encode_masked(self, s, mask)
+            }
         }
     })
 }
@@ -1,89 +1,152 @@
-use std::fmt::Display;
-use std::ops::BitAnd;
-use std::ops::BitOr;
-use std::ops::ShrAssign;
-use std::str::FromStr;
+use std::convert::TryFrom;
 
 use proc_macro2::Ident;
+use proc_macro2::Literal;
 use proc_macro2::Span;
 use proc_macro2::TokenStream as Ts2;
+use syn::parse::ParseStream;
+use syn::Attribute;
 use syn::Data;
 use syn::DeriveInput;
 use syn::Error;
 use syn::Expr;
 use syn::ExprArray;
 use syn::ExprLit;
+use syn::ExprUnary;
 use syn::Lit;
+use syn::LitStr;
+use syn::UnOp;
 
 use quote::quote;
+use syn::parenthesized;
 use syn::punctuated::Punctuated;
 use syn::LitInt;
+use syn::Token;
 
 use crate::common::random_ident_str;
 use crate::common::SynResult;
 
 struct Variant {
     name: Ident,
-    discriminant: LitInt,
+    /// The name of the value in `.proto` files.
+    proto_name: String,
+    value: i32,
 }
 
-impl Variant {
-    fn bytes_storage(&self) -> syn::Result<u8> {
-        Ok(if self.discriminant.base10_parse::<u8>().is_ok() {
-            1
-        } else if self.discriminant.base10_parse::<u16>().is_ok() {
-            2
-        } else if self.discriminant.base10_parse::<u32>().is_ok() {
-            4
-        } else if self.discriminant.base10_parse::<u64>().is_ok() {
-            8
-        } else {
-            return Err(Error::new_spanned(
-                &self.discriminant,
-                "discriminant is too big",
-            ));
+/// An alias like `#[otopr(alias(STARTED = Running))]`, which is an associated const of the enum.
+struct Alias {
+    name: Ident,
+    proto_name: String,
+    /// The variant the alias stands for, which is what decoding produces.
+    canonical: Ident,
+}
+
+impl Alias {
+    /// Parses `STARTED = Running` with an optional `, name = "PHASE_STARTED"`.
+    fn parse(p: ParseStream) -> syn::Result<Self> {
+        let content;
+        parenthesized!(content in p);
+        let name: Ident = content.parse()?;
+        let _: Token![=] = content.parse()?;
+        let canonical = content.parse()?;
+        let mut proto_name = None;
+        if !content.is_empty() {
+            let _: Token![,] = content.parse()?;
+            let id: Ident = content.parse()?;
+            if id != "name" {
+                return Err(Error::new_spanned(id, "expected `name`"));
+            }
+            let _: Token![=] = content.parse()?;
+            proto_name = Some(content.parse::<LitStr>()?.value());
+        }
+        Ok(Self {
+            proto_name: proto_name.unwrap_or_else(|| name.to_string()),
+            name,
+            canonical,
         })
     }
-    fn varint_bytes<N>(&self) -> syn::Result<ExprArray>
-    where
-        N: FromStr,
-        N: ToString,
-        N::Err: Display,
-        N: From<u8>,
-        N: Ord,
-        N: ShrAssign<u8>,
-        N: BitAnd<Output = N>,
-        N: BitOr<Output = N>,
-        N: Copy,
-    {
+}
+
+impl Variant {
+    /// The smallest unsigned type holding the value, or `None` if it is negative.
+    fn bytes_storage(&self) -> Option<u8> {
+        match self.value {
+            v if v < 0 => None,
+            v if v <= u8::MAX as i32 => Some(1),
+            v if v <= u16::MAX as i32 => Some(2),
+            _ => Some(4),
+        }
+    }
+
+    /// The value as a varint. Negative values are sign-extended to ten bytes.
+    fn varint_bytes(&self) -> ExprArray {
         let mut arr = ExprArray {
             attrs: vec![],
             bracket_token: syn::token::Bracket(Span::mixed_site()),
             elems: Punctuated::new(),
         };
 
-        let mut num = self.discriminant.base10_parse::<N>()?;
-        let seven_bits = N::from(0b0111_1111);
-        let msb = N::from(0b1000_0000);
-
-        while num > seven_bits {
-            arr.elems.push(Expr::Lit(ExprLit {
+        let mut num = self.value as i64 as u64;
+        let byte = |n: u64| {
+            Expr::Lit(ExprLit {
                 attrs: vec![],
-                lit: Lit::Int(LitInt::new(
-                    &((num & seven_bits) | msb).to_string(),
-                    Span::call_site(),
-                )),
-            }));
+                lit: Lit::Int(LitInt::new(&n.to_string(), Span::call_site())),
+            })
+        };
+
+        while num > 0b0111_1111 {
+            arr.elems.push(byte((num & 0b0111_1111) | 0b1000_0000));
             num >>= 7;
         }
+        arr.elems.push(byte(num));
 
-        arr.elems.push(Expr::Lit(ExprLit {
-            attrs: vec![],
-            lit: Lit::Int(LitInt::new(&num.to_string(), Span::call_site())),
-        }));
+        arr
+    }
+}
 
-        Ok(arr)
+/// Parses discriminants like `1` and `-1`.
+fn discriminant_value(expr: &Expr) -> syn::Result<i32> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(n), ..
+        }) => n
+            .base10_parse()
+            .map_err(|_| Error::new_spanned(n, "enum values must fit in an `i32`")),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr: inner,
+            ..
+        }) => match &**inner {
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(n), ..
+            }) => n
+                .base10_parse::<i64>()
+                .ok()
+                .and_then(|n| i32::try_from(-n).ok())
+                .ok_or_else(|| Error::new_spanned(n, "enum values must fit in an `i32`")),
+            _ => Err(Error::new_spanned(expr, "must be an integer literal")),
+        },
+        _ => Err(Error::new_spanned(expr, "must be an integer literal")),
+    }
+}
+
+/// Calls `item` with each item of the `#[otopr(..)]` attributes, which is followed by its arguments in the stream.
+fn parse_attrs(
+    attrs: &[Attribute],
+    mut item: impl FnMut(Ident, ParseStream) -> syn::Result<()>,
+) -> syn::Result<()> {
+    for attr in attrs.iter().filter(|a| a.path.is_ident("otopr")) {
+        attr.parse_args_with(|p: ParseStream| {
+            while !p.is_empty() {
+                item(p.parse()?, p)?;
+                if !p.is_empty() {
+                    let _: Token![,] = p.parse()?;
+                }
+            }
+            Ok(())
+        })?;
     }
+    Ok(())
 }
 
 fn const_bytes(arr: ExprArray) -> (Ident, Ts2) {
@@ -114,87 +177,234 @@ pub fn derive_enumeration(input: DeriveInput) -> syn::Result<Ts2> {
 
     let name = input.ident;
 
-    let mut default = None;
+    // closed enums reject unknown values instead of falling back to the default.
+    let mut closed = None;
+    let mut allow_alias = false;
+    let mut aliases = Vec::new();
+    parse_attrs(&input.attrs, |id, p| {
+        if id == "closed" {
+            closed = Some(id);
+        } else if id == "allow_alias" {
+            allow_alias = true;
+        } else if id == "alias" {
+            aliases.push(Alias::parse(p)?);
+        } else {
+            return Err(Error::new_spanned(
+                id,
+                "expected `closed`, `allow_alias` or `alias`",
+            ));
+        }
+        Ok(())
+    })?;
+    if let (Some(alias), false) = (aliases.first(), allow_alias) {
+        return Err(Error::new_spanned(
+            &alias.name,
+            "aliases need `#[otopr(allow_alias)]` on the enum",
+        ));
+    }
+
+    // the variant keeping unknown values, like `#[otopr(unknown)] Unknown(i32)`.
+    let mut unknown = None;
 
     let variants = enumeration
         .variants
         .into_iter()
-        .map(|v| match v.fields {
-            syn::Fields::Unnamed(_) | syn::Fields::Named(_) => Err(Error::new_spanned(
-                v,
-                "Cannot have fields on protobuf enumerations",
-            )),
-            syn::Fields::Unit => match v.discriminant {
-                None => Err(Error::new_spanned(
-                    v,
-                    "must have discriminant for this variant",
-                )),
-                Some((_, discriminant)) => match discriminant {
-                    Expr::Lit(ExprLit {
-                        lit: Lit::Int(discriminant),
-                        ..
-                    }) => {
-                        if let Ok(0u8) = discriminant.base10_parse() {
-                            default = Some(v.ident.clone())
-                        }
-                        Ok(Variant {
-                            name: v.ident,
-                            discriminant,
-                        })
+        .map(|v| {
+            let mut is_unknown = false;
+            let mut proto_name = None;
+            parse_attrs(&v.attrs, |id, p| {
+                if id == "unknown" {
+                    is_unknown = true;
+                } else if id == "name" {
+                    let _: Token![=] = p.parse()?;
+                    proto_name = Some(p.parse::<LitStr>()?.value());
+                } else {
+                    return Err(Error::new_spanned(id, "expected `unknown` or `name`"));
+                }
+                Ok(())
+            })?;
+            if is_unknown {
+                match &v.fields {
+                    syn::Fields::Unnamed(f) if f.unnamed.len() == 1 && v.discriminant.is_none() => {
                     }
-                    disco => Err(Error::new_spanned(disco, "must be an integer literal")),
-                },
-            },
+                    _ => return Err(Error::new_spanned(
+                        v,
+                        "the unknown variant must have a single `i32` field and no discriminant",
+                    )),
+                }
+                if let Some(id) = &closed {
+                    return Err(Error::new_spanned(
+                        id,
+                        "closed enums cannot have an unknown variant",
+                    ));
+                }
+                if unknown.replace(v.ident.clone()).is_some() {
+                    return Err(Error::new_spanned(
+                        v,
+                        "there can only be one unknown variant",
+                    ));
+                }
+                return Ok(None);
+            }
+            if !matches!(v.fields, syn::Fields::Unit) {
+                return Err(Error::new_spanned(
+                    v,
+                    "Cannot have fields on protobuf enumerations",
+                ));
+            }
+            let value = match &v.discriminant {
+                Some((_, discriminant)) => discriminant_value(discriminant)?,
+                None => {
+                    return Err(Error::new_spanned(
+                        v,
+                        "must have discriminant for this variant",
+                    ))
+                }
+            };
+            Ok(Some(Variant {
+                proto_name: proto_name.unwrap_or_else(|| v.ident.to_string()),
+                name: v.ident,
+                value,
+            }))
         })
         .collect::<SynResult<Vec<_>>>()
-        .inner()?;
+        .inner()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
 
-    let default = default.ok_or_else(|| {
-        Error::new(
-            Span::mixed_site(),
-            "expected a default variant with the discriminant set to 0",
-        )
-    })?;
+    let alias_values: Vec<_> = aliases
+        .iter()
+        .map(|a| {
+            variants
+                .iter()
+                .find(|v| v.name == a.canonical)
+                .map(|v| Literal::i32_unsuffixed(v.value))
+                .ok_or_else(|| Error::new_spanned(&a.canonical, "expected a variant of the enum"))
+        })
+        .collect::<SynResult<_>>()
+        .inner()?;
 
-    let storage = variants
+    let default = variants
         .iter()
-        .map(Variant::bytes_storage)
-        .fold(Ok(1), |res, other| match (res, other) {
-            (Ok(x), Ok(y)) => Ok(x.max(y)),
-            (Ok(_), Err(e)) => Err(e),
-            (Err(e), Ok(_)) => Err(e),
-            (Err(mut e), Err(e2)) => {
-                e.combine(e2);
-                Err(e)
-            }
+        .find(|v| v.value == 0)
+        .map(|v| v.name.clone())
+        .ok_or_else(|| {
+            Error::new(
+                Span::mixed_site(),
+                "expected a default variant with the discriminant set to 0",
+            )
         })?;
 
-    let storage_ty = match storage {
-        1 => quote! { u8 },
-        2 => quote! { u16 },
-        4 => quote! { u32 },
-        8 => quote! { u64 },
-        _ => unreachable!(),
-    };
+    let (cid, cdef): (Vec<_>, Vec<_>) = variants
+        .iter()
+        .map(|v| const_bytes(v.varint_bytes()))
+        .unzip();
 
-    let varint_bytes = variants
+    let variant_idents: Vec<_> = variants.iter().map(|v| &v.name).collect();
+    let variant_discrs: Vec<_> = variants
         .iter()
-        .map(|v| match storage {
-            1 => v.varint_bytes::<u8>(),
-            2 => v.varint_bytes::<u16>(),
-            4 => v.varint_bytes::<u32>(),
-            8 => v.varint_bytes::<u64>(),
-            _ => unreachable!(),
-        })
-        .collect::<SynResult<Vec<_>>>()
-        .inner()?;
+        .map(|v| Literal::i32_unsuffixed(v.value))
+        .collect();
+    let variant_names: Vec<_> = variants.iter().map(|v| &v.proto_name).collect();
+    let enum_name = name.to_string();
 
-    let (variant_idents, variant_discrs): (Vec<_>, Vec<_>) = variants
-        .into_iter()
-        .map(|v| (v.name, v.discriminant))
-        .unzip();
+    let alias_idents: Vec<_> = aliases.iter().map(|a| &a.name).collect();
+    let alias_canonicals: Vec<_> = aliases.iter().map(|a| &a.canonical).collect();
+    let alias_names: Vec<_> = aliases.iter().map(|a| &a.proto_name).collect();
+    let alias_docs = aliases.iter().map(|a| {
+        format!(
+            "An alias of [`Self::{}`], named `{}` in the `.proto` definition.",
+            a.canonical, a.proto_name
+        )
+    });
 
-    let (cid, cdef): (Vec<_>, Vec<_>) = varint_bytes.into_iter().map(const_bytes).unzip();
+    let unknown_arms = unknown.as_ref().map(|u| {
+        quote! {
+            Self::#u(value) => ::otopr::VarInt::size(*value),
+        }
+    });
+    let unknown_encode = unknown.as_ref().map(|u| {
+        quote! {
+            Self::#u(value) => s.write_varint(*value),
+        }
+    });
+    let unknown_to_i32 = unknown
+        .as_ref()
+        .map(|u| quote! { Self::#u(value) => *value, });
+    let unknown_from_i32 = match &unknown {
+        Some(u) => quote! { value => ::core::option::Option::Some(Self::#u(value)), },
+        None => quote! { _ => ::core::option::Option::None, },
+    };
+    let unknown_name = unknown
+        .as_ref()
+        .map(|u| quote! { Self::#u(_) => return ::core::option::Option::None, });
+
+    let storage = variants
+        .iter()
+        .map(|v| v.bytes_storage())
+        .try_fold(1, |x, other| other.map(|y| x.max(y)));
+
+    // open and closed enums, and enums with negative values, read the whole value,
+    // which can be negative or too big for the known ones.
+    let decodable = match (&unknown, &closed, storage) {
+        (None, None, Some(storage)) => {
+            let storage_ty = match storage {
+                1 => quote! { u8 },
+                2 => quote! { u16 },
+                4 => quote! { u32 },
+                _ => unreachable!(),
+            };
+            quote! {
+                fn decode<B: ::otopr::__private::Buf>(deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<Self> {
+                    Ok(match <#storage_ty as ::otopr::__private::VarInt>::read_field_tag(deserializer) {
+                        #(Ok(#variant_discrs) => Self::#variant_idents,)*
+                        Ok(_) | Err(Ok(_)) => Self::#default,
+                        Err(Err(e)) => return Err(e),
+                    })
+                }
+                fn merge_from<B: ::otopr::__private::Buf>(&mut self, deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<()> {
+                    match <#storage_ty as ::otopr::__private::VarInt>::read_field_tag(deserializer) {
+                        #(Ok(#variant_discrs) => *self = Self::#variant_idents,)*
+                        Ok(_) | Err(Ok(_)) => {}
+                        Err(Err(e)) => return Err(e),
+                    }
+                    Ok(())
+                }
+            }
+        }
+        (None, None, None) => quote! {
+            fn decode<B: ::otopr::__private::Buf>(deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<Self> {
+                Ok(match <i32 as ::otopr::__private::VarInt>::read(deserializer)? {
+                    #(#variant_discrs => Self::#variant_idents,)*
+                    _ => Self::#default,
+                })
+            }
+            fn merge_from<B: ::otopr::__private::Buf>(&mut self, deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<()> {
+                match <i32 as ::otopr::__private::VarInt>::read(deserializer)? {
+                    #(#variant_discrs => *self = Self::#variant_idents,)*
+                    _ => {}
+                }
+                Ok(())
+            }
+        },
+        (unknown, ..) => {
+            let fallback = match unknown {
+                Some(u) => quote! { value => Self::#u(value), },
+                None => quote! {
+                    value => return Err(::otopr::__private::DecodingError::UnknownEnumValue(value)),
+                },
+            };
+            quote! {
+                fn decode<B: ::otopr::__private::Buf>(deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<Self> {
+                    Ok(match <i32 as ::otopr::__private::VarInt>::read(deserializer)? {
+                        #(#variant_discrs => Self::#variant_idents,)*
+                        #fallback
+                    })
+                }
+            }
+        }
+    };
 
     Ok(quote! {
         #(#cdef)*
@@ -208,31 +418,67 @@ pub fn derive_enumeration(input: DeriveInput) -> syn::Result<Ts2> {
             fn encoded_size<V: ::otopr::__private::VarInt>(&self, field_number: V) -> usize {
                 ::otopr::VarInt::size(field_number) + match self {
                     #(Self::#variant_idents => #cid.len(),)*
+                    #unknown_arms
                 }
             }
             fn encode(&self, s: &mut ::otopr::encoding::ProtobufSerializer<impl ::otopr::__private::BufMut>) {
-                s.write_bytes(match self {
-                    #(Self::#variant_idents => &#cid,)*
-                })
+                match self {
+                    #(Self::#variant_idents => s.write_bytes(&#cid),)*
+                    #unknown_encode
+                }
             }
         }
-        impl<'a> ::otopr::__private::Decodable<'a> for #name {
-            type Wire = ::otopr::__private::VarIntWire;
-            fn decode<B: ::otopr::__private::Buf>(deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<Self> {
-                Ok(match <#storage_ty as ::otopr::__private::VarInt>::read_field_tag(deserializer) {
-                    #(Ok(#variant_discrs) => Self::#variant_idents,)*
-                    Ok(_) | Err(Ok(_)) => Self::#default,
-                    Err(Err(e)) => return Err(e),
+        impl ::otopr::__private::Enumeration for #name {
+            fn to_i32(&self) -> i32 {
+                match self {
+                    #(Self::#variant_idents => #variant_discrs,)*
+                    #unknown_to_i32
+                }
+            }
+            fn from_i32(value: i32) -> ::core::option::Option<Self> {
+                match value {
+                    #(#variant_discrs => ::core::option::Option::Some(Self::#variant_idents),)*
+                    #unknown_from_i32
+                }
+            }
+            fn name(&self) -> ::core::option::Option<&'static str> {
+                Self::as_str_name(self)
+            }
+            fn from_name(name: &str) -> ::core::option::Option<Self> {
+                Self::from_str_name(name)
+            }
+        }
+        impl #name {
+            #(
+                #[doc = #alias_docs]
+                pub const #alias_idents: Self = Self::#alias_canonicals;
+            )*
+
+            /// The name of this value in the `.proto` definition.
+            pub fn as_str_name(&self) -> ::core::option::Option<&'static str> {
+                ::core::option::Option::Some(match self {
+                    #(Self::#variant_idents => #variant_names,)*
+                    #unknown_name
                 })
             }
-            fn merge_from<B: ::otopr::__private::Buf>(&mut self, deserializer: &mut ::otopr::__private::Deserializer<'a, B>) -> ::otopr::__private::Result<()> {
-                match <#storage_ty as ::otopr::__private::VarInt>::read_field_tag(deserializer) {
-                    #(Ok(#variant_discrs) => *self = Self::#variant_idents,)*
-                    Ok(_) | Err(Ok(_)) => {}
-                    Err(Err(e)) => return Err(e),
+            /// The value with the given name in the `.proto` definition.
+            pub fn from_str_name(name: &str) -> ::core::option::Option<Self> {
+                match name {
+                    #(#variant_names => ::core::option::Option::Some(Self::#variant_idents),)*
+                    #(#alias_names => ::core::option::Option::Some(Self::#alias_canonicals),)*
+                    _ => ::core::option::Option::None,
                 }
-                Ok(())
             }
         }
+        impl ::otopr::__private::ProtoType for #name {
+            const KIND: ::otopr::__private::Kind = ::otopr::__private::Kind::Enum(::otopr::__private::EnumType {
+                name: #enum_name,
+                values: &[#((#variant_names, #variant_discrs),)* #((#alias_names, #alias_values),)*],
+            });
+        }
+        impl<'a> ::otopr::__private::Decodable<'a> for #name {
+            type Wire = ::otopr::__private::VarIntWire;
+            #decodable
+        }
     })
 }
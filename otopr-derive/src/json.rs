@@ -0,0 +1,64 @@
+use proc_macro2::TokenStream as Ts2;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::common::*;
+
+pub(crate) fn derive_proto_json(input: DeriveInput) -> syn::Result<Ts2> {
+    let name = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = fields_from(input.data)?;
+    let this = quote! { this };
+    let default_inits: Vec<_> = fields
+        .iter()
+        .filter_map(|f| f.default_init(&this))
+        .collect();
+    let fields = wire_fields(fields);
+    let members: Vec<_> = fields.iter().map(|f| &f.member).collect();
+    let tys: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let proto_names = fields
+        .iter()
+        .map(|f| f.proto_name("ProtoJson"))
+        .collect::<SynResult<Vec<_>>>()
+        .inner()?;
+    let json_names: Vec<_> = proto_names.iter().map(|n| camel_case(n, false)).collect();
+    // fields with a custom default are printed even when zero, since zero is not their default.
+    let writes = fields.iter().map(|f| {
+        if f.cfg.default.is_some() {
+            quote! { field_always }
+        } else {
+            quote! { field }
+        }
+    });
+    let patterns = proto_names.iter().zip(&json_names).map(|(proto, json)| {
+        if proto == json {
+            quote! { #json }
+        } else {
+            quote! { #json | #proto }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::otopr::json::ProtoJson for #name #ty_generics #where_clause {
+            fn to_json(&self, options: &::otopr::json::PrintOptions) -> ::otopr::json::Value {
+                let mut object = ::otopr::json::ObjectWriter::new(options);
+                #(object.#writes(#json_names, #proto_names, &self.#members);)*
+                object.finish()
+            }
+
+            fn from_json(value: &::otopr::json::Value, options: &::otopr::json::ParseOptions) -> ::otopr::json::Result<Self> {
+                let mut this = <Self as ::core::default::Default>::default();
+                #(#default_inits)*
+                for (key, value) in ::otopr::json::object_fields(value)? {
+                    match key {
+                        #(#patterns => this.#members = <#tys as ::otopr::json::ProtoJson>::from_json(value, options)?,)*
+                        _ if options.ignore_unknown_fields => {}
+                        _ => return ::core::result::Result::Err(::otopr::json::JsonError::UnknownField(key.into())),
+                    }
+                }
+                ::core::result::Result::Ok(this)
+            }
+        }
+    })
+}
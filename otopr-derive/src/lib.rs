@@ -10,6 +10,8 @@ mod common;
 mod decode;
 mod encode;
 mod enumeration;
+mod json;
+mod text_format;
 
 #[proc_macro_derive(EncodableMessage, attributes(otopr))]
 pub fn derive_encodable_message(ts: TokenStream) -> TokenStream {
@@ -25,9 +27,23 @@ pub fn derive_decodable_message(ts: TokenStream) -> TokenStream {
         .into()
 }
 
-#[proc_macro_derive(Enumeration)]
+#[proc_macro_derive(Enumeration, attributes(otopr))]
 pub fn derive_enumeration(ts: TokenStream) -> TokenStream {
     enumeration::derive_enumeration(parse_macro_input!(ts as DeriveInput))
         .unwrap_or_else(|e| e.into_compile_error())
         .into()
 }
+
+#[proc_macro_derive(ProtoJson, attributes(otopr))]
+pub fn derive_proto_json(ts: TokenStream) -> TokenStream {
+    json::derive_proto_json(parse_macro_input!(ts as DeriveInput))
+        .unwrap_or_else(|e| e.into_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(TextFormat, attributes(otopr))]
+pub fn derive_text_format(ts: TokenStream) -> TokenStream {
+    text_format::derive_text_format(parse_macro_input!(ts as DeriveInput))
+        .unwrap_or_else(|e| e.into_compile_error())
+        .into()
+}
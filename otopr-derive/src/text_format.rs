@@ -0,0 +1,80 @@
+use proc_macro2::TokenStream as Ts2;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::common::*;
+
+pub(crate) fn derive_text_format(input: DeriveInput) -> syn::Result<Ts2> {
+    let name = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = fields_from(input.data)?;
+    let this = quote! { self };
+    let default_inits: Vec<_> = fields
+        .iter()
+        .filter_map(|f| f.default_init(&this))
+        .collect();
+    let fields = wire_fields(fields);
+    let members: Vec<_> = fields.iter().map(|f| &f.member).collect();
+    let names = fields
+        .iter()
+        .map(|f| f.proto_name("TextFormat"))
+        .collect::<SynResult<Vec<_>>>()
+        .inner()?;
+    let numbers = fields.iter().map(|f| f.cfg.field_number.to_string());
+    // `print_field` skips zero values, which are not the default of fields with `default = ..`.
+    let prints = fields.iter().zip(&names).map(|(f, name)| {
+        let member = &f.member;
+        if f.cfg.default.is_some() {
+            quote! { p.field(#name, &self.#member); }
+        } else {
+            quote! { ::otopr::text_format::TextFormat::print_field(&self.#member, #name, p); }
+        }
+    });
+    let init_defaults = if default_inits.is_empty() {
+        None
+    } else {
+        Some(quote! {
+            fn init_defaults(&mut self) {
+                #(#default_inits)*
+            }
+        })
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::otopr::text_format::TextMessage for #name #ty_generics #where_clause {
+            fn print_fields(&self, p: &mut ::otopr::text_format::Printer) {
+                #(#prints)*
+            }
+
+            #init_defaults
+
+            fn merge_field(&mut self, name: &str, p: &mut ::otopr::text_format::Parser<'_>) -> ::otopr::text_format::Result<()> {
+                match name {
+                    #(#names | #numbers => ::otopr::text_format::TextFormat::parse_field(&mut self.#members, p),)*
+                    _ => p.unknown_field(name),
+                }
+            }
+        }
+
+        impl #impl_generics ::otopr::text_format::TextFormat for #name #ty_generics #where_clause {
+            const MESSAGE: bool = true;
+
+            fn print_value(&self, p: &mut ::otopr::text_format::Printer) {
+                p.message(self)
+            }
+
+            fn parse_value(p: &mut ::otopr::text_format::Parser<'_>) -> ::otopr::text_format::Result<Self> {
+                let mut this = <Self as ::core::default::Default>::default();
+                ::otopr::text_format::TextMessage::init_defaults(&mut this);
+                p.message(&mut this)?;
+                ::core::result::Result::Ok(this)
+            }
+
+            fn parse_field(&mut self, p: &mut ::otopr::text_format::Parser<'_>) -> ::otopr::text_format::Result<()> {
+                p.field_separator(true)?;
+                p.message(self)
+            }
+        }
+    })
+}
@@ -1,11 +1,19 @@
 //! Internal module. Should only be used by macros.
 
-pub use crate::decoding::{Decodable, DecodableMessage, Deserializer, Result};
+pub use crate::decoding::{Decodable, DecodableMessage, DecodingError, Deserializer, Result};
 pub use crate::encoding::{Encodable, EncodableMessage, ProtobufSerializer};
+pub use crate::mask::Mask;
+pub use crate::reflect::{
+    Cardinality, EnumType, FieldDescriptor, Kind, MessageDescriptor, MessageType, ProtoType,
+};
+pub use crate::traits::{Enumeration, Name};
 pub use crate::wire_types::*;
 pub use crate::VarInt;
 pub use bytes::{Buf, BufMut};
 
+pub mod scalar;
+pub use scalar::FieldEncoding;
+
 pub trait HasField<const NUM: u64> {
     type PreCompArray: AsRef<[u8]>;
     const PRECOMP: Self::PreCompArray;
@@ -20,6 +28,7 @@ pub struct __ConstBoundWorkaround<T>(T);
 
 impl<T: WireType> WireType for __ConstBoundWorkaround<T> {
     const BITS: u8 = T::BITS;
+    const WIRE_TYPE: WireTypes = T::WIRE_TYPE;
 }
 
 impl<T: WireType> crate::traits::private::Sealed for __ConstBoundWorkaround<T> {}
@@ -0,0 +1,270 @@
+//! Encoding plain Rust types as other scalars, for `#[otopr(sint32)]`, `#[otopr(packed)]` and similar.
+
+use bytes::{Buf, BufMut};
+
+use crate::decoding::{Decodable, Deserializer, Result};
+use crate::encoding::{Encodable, ProtobufSerializer};
+use crate::reflect::{Cardinality, Kind};
+use crate::wire_types::{LengthDelimitedWire, WireType};
+use crate::{Packed, Signed, VarInt};
+
+/// A proto scalar type that values of type `T` can be encoded as.
+pub trait ScalarEncoding<T> {
+    type Repr: Encodable + for<'de> Decodable<'de>;
+    const KIND: Kind;
+
+    fn to_repr(value: &T) -> Self::Repr;
+    fn from_repr(repr: Self::Repr) -> T;
+}
+
+/// How a field of type `T` is encoded.
+pub trait FieldEncoding<T> {
+    type Wire: WireType;
+    const KIND: Kind;
+    const CARDINALITY: Cardinality;
+    const PACKED: bool = false;
+    /// The bits of the other wire type that repeated scalars accept when decoding.
+    const ALT_WIRE: Option<u8> = None;
+
+    fn encoded_size<V: VarInt>(value: &T, field_number: V) -> usize;
+    fn encode_field_precomputed(
+        value: &T,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: &[u8],
+    );
+    fn merge_from<B: Buf>(value: &mut T, d: &mut Deserializer<'_, B>) -> Result<()>;
+
+    /// Decodes a field with the wire type in `ALT_WIRE`.
+    fn merge_alt<B: Buf>(value: &mut T, d: &mut Deserializer<'_, B>) -> Result<()> {
+        Self::merge_from(value, d)
+    }
+
+    fn merge(value: &mut T, other: T) {
+        *value = other;
+    }
+}
+
+pub struct Sint32;
+pub struct Sint64;
+pub struct Fixed32;
+pub struct Fixed64;
+pub struct Sfixed32;
+pub struct Sfixed64;
+/// The type's own encoding, for `#[otopr(packed)]` alone.
+pub struct Native;
+
+macro_rules! scalar {
+    ($($marker:ident($ty:ty) as $repr:ty = $kind:ident, $to:expr, $from:expr;)*) => {$(
+        impl ScalarEncoding<$ty> for $marker {
+            type Repr = $repr;
+            const KIND: Kind = Kind::$kind;
+
+            fn to_repr(value: &$ty) -> $repr {
+                let to: fn($ty) -> $repr = $to;
+                to(*value)
+            }
+
+            fn from_repr(repr: $repr) -> $ty {
+                let from: fn($repr) -> $ty = $from;
+                from(repr)
+            }
+        }
+    )*};
+}
+
+scalar! {
+    Sint32(i32) as Signed<i32> = SInt32, Signed::new, |r| r.get();
+    Sint64(i64) as Signed<i64> = SInt64, Signed::new, |r| r.get();
+    Fixed32(u32) as crate::Fixed32 = Fixed32, crate::Fixed32::new, crate::Fixed32::get;
+    Fixed64(u64) as crate::Fixed64 = Fixed64, crate::Fixed64::new, crate::Fixed64::get;
+    Sfixed32(i32) as crate::Fixed32 = SFixed32, |v| crate::Fixed32::new(v as u32), |r| r.get() as i32;
+    Sfixed64(i64) as crate::Fixed64 = SFixed64, |v| crate::Fixed64::new(v as u64), |r| r.get() as i64;
+    Native(i32) as i32 = Int32, |v| v, |r| r;
+    Native(i64) as i64 = Int64, |v| v, |r| r;
+    Native(u32) as u32 = UInt32, |v| v, |r| r;
+    Native(u64) as u64 = UInt64, |v| v, |r| r;
+    Native(bool) as bool = Bool, |v| v, |r| r;
+    Native(f32) as f32 = Float, |v| v, |r| r;
+    Native(f64) as f64 = Double, |v| v, |r| r;
+}
+
+/// The size of a value without its field tag.
+fn value_size<E: Encodable>(value: &E) -> usize {
+    value.encoded_size(0u8) - 0u8.size()
+}
+
+fn packed_size<S: ScalarEncoding<T>, T>(values: &[T]) -> usize {
+    values.iter().map(|v| value_size(&S::to_repr(v))).sum()
+}
+
+fn merge_unpacked<S: ScalarEncoding<T>, T, B: Buf>(
+    values: &mut Vec<T>,
+    d: &mut Deserializer<'_, B>,
+) -> Result<()> {
+    values.push(S::from_repr(S::Repr::decode(d)?));
+    Ok(())
+}
+
+fn merge_packed<S: ScalarEncoding<T>, T, B: Buf>(
+    values: &mut Vec<T>,
+    d: &mut Deserializer<'_, B>,
+) -> Result<()> {
+    let len = d.read_varint()?;
+    let token = d.set_limit(len);
+    let mut res = Ok(());
+    while res.is_ok() && d.has_remaining() {
+        res = merge_unpacked::<S, T, B>(values, d);
+    }
+    d.reset_limit(token);
+    res
+}
+
+macro_rules! field_encodings {
+    ($($marker:ident),*) => {$(
+        impl<T> FieldEncoding<T> for $marker
+        where
+            $marker: ScalarEncoding<T>,
+        {
+            type Wire = <<$marker as ScalarEncoding<T>>::Repr as Encodable>::Wire;
+            const KIND: Kind = <$marker as ScalarEncoding<T>>::KIND;
+            const CARDINALITY: Cardinality = Cardinality::Singular;
+
+            fn encoded_size<V: VarInt>(value: &T, field_number: V) -> usize {
+                Self::to_repr(value).encoded_size(field_number)
+            }
+
+            fn encode_field_precomputed(
+                value: &T,
+                s: &mut ProtobufSerializer<impl BufMut>,
+                field_number: &[u8],
+            ) {
+                s.write_bytes(field_number);
+                Self::to_repr(value).encode(s);
+            }
+
+            fn merge_from<B: Buf>(value: &mut T, d: &mut Deserializer<'_, B>) -> Result<()> {
+                *value = Self::from_repr(<Self as ScalarEncoding<T>>::Repr::decode(d)?);
+                Ok(())
+            }
+        }
+
+        impl<T> FieldEncoding<Option<T>> for $marker
+        where
+            $marker: ScalarEncoding<T>,
+        {
+            type Wire = <<$marker as ScalarEncoding<T>>::Repr as Encodable>::Wire;
+            const KIND: Kind = <$marker as ScalarEncoding<T>>::KIND;
+            const CARDINALITY: Cardinality = Cardinality::Optional;
+
+            fn encoded_size<V: VarInt>(value: &Option<T>, field_number: V) -> usize {
+                value.as_ref().map_or(0, |v| <Self as FieldEncoding<T>>::encoded_size(v, field_number))
+            }
+
+            fn encode_field_precomputed(
+                value: &Option<T>,
+                s: &mut ProtobufSerializer<impl BufMut>,
+                field_number: &[u8],
+            ) {
+                if let Some(v) = value {
+                    <Self as FieldEncoding<T>>::encode_field_precomputed(v, s, field_number)
+                }
+            }
+
+            fn merge_from<B: Buf>(value: &mut Option<T>, d: &mut Deserializer<'_, B>) -> Result<()> {
+                *value = Some(Self::from_repr(<Self as ScalarEncoding<T>>::Repr::decode(d)?));
+                Ok(())
+            }
+
+            fn merge(value: &mut Option<T>, other: Option<T>) {
+                if other.is_some() {
+                    *value = other;
+                }
+            }
+        }
+
+        impl<T> FieldEncoding<Vec<T>> for $marker
+        where
+            $marker: ScalarEncoding<T>,
+        {
+            type Wire = <<$marker as ScalarEncoding<T>>::Repr as Encodable>::Wire;
+            const KIND: Kind = <$marker as ScalarEncoding<T>>::KIND;
+            const CARDINALITY: Cardinality = Cardinality::Repeated;
+            const ALT_WIRE: Option<u8> = Some(LengthDelimitedWire::BITS);
+
+            fn encoded_size<V: VarInt>(value: &Vec<T>, field_number: V) -> usize {
+                value.iter().map(|v| <Self as FieldEncoding<T>>::encoded_size(v, field_number)).sum()
+            }
+
+            fn encode_field_precomputed(
+                value: &Vec<T>,
+                s: &mut ProtobufSerializer<impl BufMut>,
+                field_number: &[u8],
+            ) {
+                for v in value {
+                    <Self as FieldEncoding<T>>::encode_field_precomputed(v, s, field_number)
+                }
+            }
+
+            fn merge_from<B: Buf>(value: &mut Vec<T>, d: &mut Deserializer<'_, B>) -> Result<()> {
+                merge_unpacked::<Self, T, B>(value, d)
+            }
+
+            fn merge_alt<B: Buf>(value: &mut Vec<T>, d: &mut Deserializer<'_, B>) -> Result<()> {
+                merge_packed::<Self, T, B>(value, d)
+            }
+
+            fn merge(value: &mut Vec<T>, other: Vec<T>) {
+                value.extend(other)
+            }
+        }
+
+        impl<T> FieldEncoding<Vec<T>> for Packed<$marker>
+        where
+            $marker: ScalarEncoding<T>,
+        {
+            type Wire = LengthDelimitedWire;
+            const KIND: Kind = <$marker as ScalarEncoding<T>>::KIND;
+            const CARDINALITY: Cardinality = Cardinality::Repeated;
+            const PACKED: bool = true;
+            const ALT_WIRE: Option<u8> =
+                Some(<<<$marker as ScalarEncoding<T>>::Repr as Encodable>::Wire as WireType>::BITS);
+
+            fn encoded_size<V: VarInt>(value: &Vec<T>, field_number: V) -> usize {
+                if value.is_empty() {
+                    return 0;
+                }
+                let len = packed_size::<$marker, T>(value);
+                field_number.size() + len.size() + len
+            }
+
+            fn encode_field_precomputed(
+                value: &Vec<T>,
+                s: &mut ProtobufSerializer<impl BufMut>,
+                field_number: &[u8],
+            ) {
+                if value.is_empty() {
+                    return;
+                }
+                s.write_bytes(field_number);
+                s.write_varint(packed_size::<$marker, T>(value));
+                for v in value {
+                    <$marker as ScalarEncoding<T>>::to_repr(v).encode(s);
+                }
+            }
+
+            fn merge_from<B: Buf>(value: &mut Vec<T>, d: &mut Deserializer<'_, B>) -> Result<()> {
+                merge_packed::<$marker, T, B>(value, d)
+            }
+
+            fn merge_alt<B: Buf>(value: &mut Vec<T>, d: &mut Deserializer<'_, B>) -> Result<()> {
+                merge_unpacked::<$marker, T, B>(value, d)
+            }
+
+            fn merge(value: &mut Vec<T>, other: Vec<T>) {
+                value.extend(other)
+            }
+        }
+    )*};
+}
+
+field_encodings!(Sint32, Sint64, Fixed32, Fixed64, Sfixed32, Sfixed64, Native);
@@ -0,0 +1,166 @@
+//! Framing of messages for [`tokio_util::codec`].
+//!
+//! Every frame is a message prefixed with its length as a varint, the same
+//! framing used by `writeDelimitedTo`/`parseDelimitedFrom` in other protobuf implementations.
+
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::decoding::{DecodableMessage, DecodingError, Deserializer};
+use crate::encoding::{EncodableMessage, ProtobufSerializer};
+use crate::VarInt;
+
+/// The default maximum frame length, 4 MiB.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 4 * 1024 * 1024;
+
+/// A codec encoding `E`s and decoding `D`s as varint length-prefixed frames.
+pub struct ProtobufCodec<E, D = E> {
+    max_frame_length: usize,
+    _marker: PhantomData<fn(E) -> D>,
+}
+
+impl<E, D> ProtobufCodec<E, D> {
+    /// Creates a new codec with the maximum frame length set to [`DEFAULT_MAX_FRAME_LENGTH`].
+    pub fn new() -> Self {
+        Self::with_max_frame_length(DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    /// Creates a new codec which rejects frames longer than `max_frame_length` bytes.
+    pub fn with_max_frame_length(max_frame_length: usize) -> Self {
+        Self {
+            max_frame_length,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+}
+
+impl<E, D> Default for ProtobufCodec<E, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, D> Clone for ProtobufCodec<E, D> {
+    fn clone(&self) -> Self {
+        Self::with_max_frame_length(self.max_frame_length)
+    }
+}
+
+impl<E, D> fmt::Debug for ProtobufCodec<E, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtobufCodec")
+            .field("max_frame_length", &self.max_frame_length)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Io(io::Error),
+    Decoding(DecodingError),
+    /// The length of a frame exceeded the maximum frame length of the codec.
+    FrameTooLarge {
+        length: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::Decoding(e) => write!(f, "decoding error: {}", e),
+            Self::FrameTooLarge { length, max } => write!(
+                f,
+                "frame of {} bytes exceeds the maximum frame length of {} bytes",
+                length, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Decoding(e) => Some(e),
+            Self::FrameTooLarge { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<DecodingError> for CodecError {
+    fn from(e: DecodingError) -> Self {
+        Self::Decoding(e)
+    }
+}
+
+impl<E, D> Decoder for ProtobufCodec<E, D>
+where
+    D: for<'de> DecodableMessage<'de> + Default,
+{
+    type Item = D;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, CodecError> {
+        let mut peek = &src[..];
+        let length: usize = match Deserializer::new(&mut peek).read_varint() {
+            Ok(length) => length,
+            // the length prefix itself is incomplete.
+            Err(DecodingError::Eof) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if length > self.max_frame_length {
+            return Err(CodecError::FrameTooLarge {
+                length,
+                max: self.max_frame_length,
+            });
+        }
+
+        let prefix_length = src.len() - peek.len();
+        if peek.len() < length {
+            src.reserve(length - peek.len());
+            return Ok(None);
+        }
+
+        src.advance(prefix_length);
+        let mut frame = src.split_to(length).freeze();
+        let message = D::decode(&mut Deserializer::new(&mut frame))?;
+        Ok(Some(message))
+    }
+}
+
+impl<E: EncodableMessage, D> Encoder<E> for ProtobufCodec<E, D> {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let length = item.encoded_size();
+        if length > self.max_frame_length {
+            return Err(CodecError::FrameTooLarge {
+                length,
+                max: self.max_frame_length,
+            });
+        }
+
+        dst.reserve(length.size() + length);
+        let mut s = ProtobufSerializer::new(dst);
+        s.write_varint(length);
+        item.encode(&mut s);
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
-use std::{borrow::Cow, str::Utf8Error};
+use std::{borrow::Cow, fmt, str::Utf8Error};
 
-use bytes::Buf;
+use bytes::{buf::Chain, Buf, Bytes};
 
-use crate::{wire_types::*, Message, VarInt};
+use crate::{mask::Mask, wire_types::*, BytesStr, Message, VarInt};
+
+pub mod raw;
 
 pub trait Decodable<'de>: Sized {
     type Wire: WireType;
@@ -20,6 +22,25 @@ pub trait Decodable<'de>: Sized {
     fn merge(&mut self, other: Self) {
         *self = other;
     }
+
+    /// Like [`decode()`], but only with the fields in `mask` for messages.
+    ///
+    /// Types without fields ignore the mask.
+    fn decode_masked<B: Buf>(
+        deserializer: &mut Deserializer<'de, B>,
+        _mask: &Mask,
+    ) -> Result<Self> {
+        Self::decode(deserializer)
+    }
+
+    fn merge_from_masked<B: Buf>(
+        &mut self,
+        deserializer: &mut Deserializer<'de, B>,
+        mask: &Mask,
+    ) -> Result<()> {
+        self.merge(Self::decode_masked(deserializer, mask)?);
+        Ok(())
+    }
 }
 
 pub trait DecodableMessage<'de>: Sized {
@@ -38,11 +59,27 @@ pub trait DecodableMessage<'de>: Sized {
         tag: Self::Tag,
     ) -> Result<()>;
 
+    /// Decodes a field with the given tag if it is in `mask`, and skips it otherwise.
+    ///
+    /// Messages that do not support masks decode all fields.
+    fn decode_field_masked<B: Buf>(
+        &mut self,
+        deserializer: &mut Deserializer<'de, B>,
+        tag: Self::Tag,
+        _mask: &Mask,
+    ) -> Result<()> {
+        self.decode_field(deserializer, tag)
+    }
+
+    /// Sets fields whose value when missing is not their `Default`, before any field is decoded.
+    fn init_defaults(&mut self) {}
+
     fn decode<B: Buf>(deserializer: &mut Deserializer<'de, B>) -> Result<Self>
     where
         Self: Default,
     {
         let mut message = Self::default();
+        message.init_defaults();
         loop {
             if !deserializer.has_remaining() {
                 break;
@@ -55,6 +92,26 @@ pub trait DecodableMessage<'de>: Sized {
         }
         Ok(message)
     }
+
+    /// Decodes only the fields in `mask`, leaving the others at their defaults.
+    fn decode_masked<B: Buf>(deserializer: &mut Deserializer<'de, B>, mask: &Mask) -> Result<Self>
+    where
+        Self: Default,
+    {
+        let mut message = Self::default();
+        message.init_defaults();
+        loop {
+            if !deserializer.has_remaining() {
+                break;
+            }
+            match Self::Tag::read_field_tag(deserializer) {
+                Ok(tag) => message.decode_field_masked(deserializer, tag, mask)?,
+                Err(Ok(wire)) => wire.skip(deserializer)?,
+                Err(Err(e)) => return Err(e),
+            }
+        }
+        Ok(message)
+    }
 }
 
 #[derive(Debug)]
@@ -63,6 +120,46 @@ pub enum DecodingError {
     VarIntOverflow,
     Utf8Error(Utf8Error),
     UnknownWireType(u8),
+    /// Tried to decode a borrowed value with a deserializer that cannot borrow from its buffer.
+    BorrowUnsupported,
+    /// A `google.protobuf.Any` had a type URL with no registered type.
+    UnknownTypeUrl(String),
+    /// A field with `#[otopr(with(..))]` could not be converted from its proto type.
+    Conversion(Box<dyn std::error::Error + Send + Sync>),
+    /// A value of an `#[otopr(closed)]` enum that has no variant.
+    UnknownEnumValue(i32),
+    /// A group that ends without being started or with another field number, or that is
+    /// nested too deeply.
+    InvalidGroup,
+    /// Messages nested more deeply than a decoder without a static type allows.
+    TooDeep,
+}
+
+impl fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Eof => f.write_str("unexpected end of input"),
+            Self::VarIntOverflow => f.write_str("varint overflowed its storage type"),
+            Self::Utf8Error(e) => write!(f, "invalid utf-8: {}", e),
+            Self::UnknownWireType(w) => write!(f, "unknown wire type {}", w),
+            Self::BorrowUnsupported => f.write_str("cannot borrow from the input buffer"),
+            Self::UnknownTypeUrl(url) => write!(f, "no type registered for `{}`", url),
+            Self::Conversion(e) => write!(f, "invalid field value: {}", e),
+            Self::UnknownEnumValue(v) => write!(f, "unknown enum value {}", v),
+            Self::InvalidGroup => f.write_str("invalid group"),
+            Self::TooDeep => f.write_str("messages nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for DecodingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Utf8Error(e) => Some(e),
+            Self::Conversion(e) => Some(&**e),
+            _ => None,
+        }
+    }
 }
 
 impl From<Utf8Error> for DecodingError {
@@ -79,16 +176,69 @@ pub struct LimitToken {
     set_to: usize,
 }
 
+/// A buffer whose contents live for at least `'de`, so that bytes can be borrowed from it.
+pub trait BorrowBuf<'de>: Buf {
+    /// Removes the first `len` bytes from this buffer and returns them.
+    ///
+    /// Returns `None` without advancing the buffer if the bytes are not
+    /// contiguous or if there are not enough bytes remaining.
+    fn borrow_bytes(&mut self, len: usize) -> Option<&'de [u8]>;
+}
+
+impl<'de, T: BorrowBuf<'de>, U: BorrowBuf<'de>> BorrowBuf<'de> for Chain<T, U> {
+    fn borrow_bytes(&mut self, len: usize) -> Option<&'de [u8]> {
+        let first = self.first_mut();
+        if !first.has_remaining() {
+            self.last_mut().borrow_bytes(len)
+        } else if first.remaining() >= len {
+            first.borrow_bytes(len)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'de, 'a: 'de> BorrowBuf<'de> for &'a [u8] {
+    fn borrow_bytes(&mut self, len: usize) -> Option<&'de [u8]> {
+        if self.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.split_at(len);
+        *self = rest;
+        Some(bytes)
+    }
+}
+
+/// [`BorrowBuf::borrow_bytes`] of the underlying buffer, if it implements `BorrowBuf<'de>`.
+type BorrowFn<'de, B> = fn(&mut B, usize) -> Option<&'de [u8]>;
+
 pub struct Deserializer<'de, B> {
     pub(crate) buf: &'de mut B,
     limit: usize,
+    borrow: Option<BorrowFn<'de, B>>,
 }
 
 impl<'de, B: Buf> Deserializer<'de, B> {
+    /// Creates a deserializer that copies all bytes it reads out of `buf`.
+    ///
+    /// Use [`Deserializer::borrowed`] to decode types borrowing from the input, such as `&'de str`.
     pub fn new(buf: &'de mut B) -> Self {
         Self {
             buf,
             limit: usize::MAX,
+            borrow: None,
+        }
+    }
+
+    /// Creates a deserializer that can borrow from `buf`, such as a `&'de [u8]`.
+    pub fn borrowed(buf: &'de mut B) -> Self
+    where
+        B: BorrowBuf<'de>,
+    {
+        Self {
+            buf,
+            limit: usize::MAX,
+            borrow: Some(B::borrow_bytes),
         }
     }
 
@@ -103,9 +253,6 @@ impl<'de, B: Buf> Deserializer<'de, B> {
     pub fn reset_limit(&mut self, token: LimitToken) {
         let limit_used = token.set_to - self.limit;
         self.limit = token.prev_limit - limit_used;
-
-        // avoid panicking.
-        std::mem::forget(token);
     }
 
     /// get an u8 from the underlying buffer, assuming this is within limits.
@@ -139,47 +286,76 @@ impl<'de, B: Buf> Deserializer<'de, B> {
         V::read(self)
     }
 
-    pub fn read_bytes_borrowed<'a>(&mut self, len: usize) -> Result<&'a [u8]> {
-        self.check_limit(len, |buf| {
-            let c = buf.chunk();
-            if c.len() >= len {
-                // SAFETY: already checked above
-                let c_raw = unsafe { c.get_unchecked(..len) } as *const [u8];
-                buf.advance(len);
-                Ok(unsafe { &*c_raw })
+    /// Reads a little-endian `u32`, as used by the `fixed32` wire type.
+    pub fn read_u32(&mut self) -> Result<u32> {
+        self.check_limit(4, |buf| {
+            if buf.remaining() < 4 {
+                Err(DecodingError::Eof)
             } else {
+                Ok(buf.get_u32_le())
+            }
+        })
+    }
+
+    /// Reads a little-endian `u64`, as used by the `fixed64` wire type.
+    pub fn read_u64(&mut self) -> Result<u64> {
+        self.check_limit(8, |buf| {
+            if buf.remaining() < 8 {
                 Err(DecodingError::Eof)
+            } else {
+                Ok(buf.get_u64_le())
             }
         })
     }
 
-    pub fn read_bytes<'a>(&mut self, len: usize) -> Result<Cow<'a, [u8]>> {
+    /// Borrows `len` bytes from the underlying buffer.
+    ///
+    /// Fails with [`DecodingError::BorrowUnsupported`] unless this deserializer
+    /// was created with [`Deserializer::borrowed`].
+    pub fn read_bytes_borrowed(&mut self, len: usize) -> Result<&'de [u8]> {
+        let borrow = self.borrow.ok_or(DecodingError::BorrowUnsupported)?;
+        self.check_limit(len, |buf| borrow(buf, len).ok_or(DecodingError::Eof))
+    }
+
+    /// Reads `len` bytes, borrowing if possible and copying otherwise.
+    pub fn read_bytes(&mut self, len: usize) -> Result<Cow<'de, [u8]>> {
         use bytes::BufMut;
+        let borrow = self.borrow;
         self.check_limit(len, |buf| {
-            let c = buf.chunk();
             if buf.remaining() < len {
                 Err(DecodingError::Eof)
-            } else if c.len() >= len {
-                // SAFETY: already checked above
-                let c_raw = unsafe { c.get_unchecked(..len) } as *const [u8];
-                buf.advance(len);
-                Ok(Cow::Borrowed(unsafe { &*c_raw }))
+            } else if let Some(bytes) = borrow.and_then(|borrow| borrow(buf, len)) {
+                Ok(Cow::Borrowed(bytes))
             } else {
                 let mut v = Vec::with_capacity(len);
-                (&mut v).put(buf.take(len));
+                v.put(buf.take(len));
                 Ok(Cow::Owned(v))
             }
         })
     }
 
+    /// Reads `len` bytes into a [`Bytes`].
+    ///
+    /// This does not copy if the underlying buffer is a `Bytes` or a `BytesMut`,
+    /// the returned value shares the reference-counted storage instead.
+    pub fn read_bytes_shared(&mut self, len: usize) -> Result<Bytes> {
+        self.check_limit(len, |buf| {
+            if buf.remaining() < len {
+                Err(DecodingError::Eof)
+            } else {
+                Ok(buf.copy_to_bytes(len))
+            }
+        })
+    }
+
     pub fn read_bytes_owned(&mut self, len: usize) -> Result<Box<[u8]>> {
         use bytes::BufMut;
         self.check_limit(len, |buf| {
             if buf.remaining() < len {
                 Err(DecodingError::Eof)
             } else {
-                let mut v= Vec::with_capacity(len);
-                (&mut v).put(buf.take(len));
+                let mut v = Vec::with_capacity(len);
+                v.put(buf.take(len));
                 Ok(v.into_boxed_slice())
             }
         })
@@ -210,6 +386,15 @@ impl Decodable<'_> for Vec<u8> {
     }
 }
 
+impl Decodable<'_> for Bytes {
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'_, B>) -> Result<Self> {
+        let len = deserializer.read_varint()?;
+        deserializer.read_bytes_shared(len)
+    }
+}
+
 impl Decodable<'_> for Box<[u8]> {
     type Wire = LengthDelimitedWire;
 
@@ -229,6 +414,26 @@ impl<'de> Decodable<'de> for &'de str {
     }
 }
 
+impl<'de> Decodable<'de> for Cow<'de, [u8]> {
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'de, B>) -> Result<Self> {
+        let len = deserializer.read_varint()?;
+        deserializer.read_bytes(len)
+    }
+}
+
+impl<'de> Decodable<'de> for Cow<'de, str> {
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'de, B>) -> Result<Self> {
+        Ok(match Cow::<[u8]>::decode(deserializer)? {
+            Cow::Borrowed(bytes) => Cow::Borrowed(std::str::from_utf8(bytes)?),
+            Cow::Owned(bytes) => String::from_utf8(bytes).map_err(|e| e.utf8_error())?.into(),
+        })
+    }
+}
+
 impl Decodable<'_> for String {
     type Wire = LengthDelimitedWire;
 
@@ -247,6 +452,52 @@ impl Decodable<'_> for Box<str> {
     }
 }
 
+impl Decodable<'_> for BytesStr {
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'_, B>) -> Result<Self> {
+        Ok(BytesStr::from_utf8(Bytes::decode(deserializer)?)?)
+    }
+}
+
+impl<'de, T: Decodable<'de>> Decodable<'de> for Option<T> {
+    type Wire = T::Wire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'de, B>) -> Result<Self> {
+        T::decode(deserializer).map(Some)
+    }
+
+    fn merge_from<B: Buf>(&mut self, deserializer: &mut Deserializer<'de, B>) -> Result<()> {
+        match self {
+            Some(t) => t.merge_from(deserializer),
+            None => Self::decode(deserializer).map(|t| *self = t),
+        }
+    }
+
+    fn decode_masked<B: Buf>(deserializer: &mut Deserializer<'de, B>, mask: &Mask) -> Result<Self> {
+        T::decode_masked(deserializer, mask).map(Some)
+    }
+
+    fn merge_from_masked<B: Buf>(
+        &mut self,
+        deserializer: &mut Deserializer<'de, B>,
+        mask: &Mask,
+    ) -> Result<()> {
+        match self {
+            Some(t) => t.merge_from_masked(deserializer, mask),
+            None => Self::decode_masked(deserializer, mask).map(|t| *self = t),
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        match (self.as_mut(), other) {
+            (Some(t), Some(other)) => t.merge(other),
+            (None, other @ Some(_)) => *self = other,
+            (_, None) => {}
+        }
+    }
+}
+
 impl<'de, M: DecodableMessage<'de> + Default> Decodable<'de> for Message<M> {
     type Wire = LengthDelimitedWire;
 
@@ -257,4 +508,12 @@ impl<'de, M: DecodableMessage<'de> + Default> Decodable<'de> for Message<M> {
         deserializer.reset_limit(tk);
         Ok(Message(message?))
     }
+
+    fn decode_masked<B: Buf>(deserializer: &mut Deserializer<'de, B>, mask: &Mask) -> Result<Self> {
+        let len = deserializer.read_varint()?;
+        let tk = deserializer.set_limit(len);
+        let message = M::decode_masked(deserializer, mask);
+        deserializer.reset_limit(tk);
+        Ok(Message(message?))
+    }
 }
@@ -0,0 +1,169 @@
+//! Decoding without a schema, like `protoc --decode_raw`.
+//!
+//! Length-delimited values are shown as nested messages if they parse as one,
+//! then as strings if they are valid UTF-8 and as bytes otherwise. Like `protoc`, this is a guess:
+//! a short string can also be a valid message.
+
+use std::fmt;
+
+use bytes::{Buf, Bytes};
+
+use super::{DecodableMessage, DecodingError, Deserializer, Result};
+use crate::wire_types::WireTypes;
+
+/// Nested messages deeper than this are shown as bytes, and groups fail to decode.
+const MAX_DEPTH: usize = 64;
+
+/// A message decoded without a schema, with its fields in the order they appear in the input.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawMessage {
+    pub fields: Vec<RawField>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawField {
+    pub number: u64,
+    pub wire_type: WireTypes,
+    pub value: RawValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawValue {
+    VarInt(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    /// A length-delimited value that parsed as a message.
+    Message(RawMessage),
+    /// A length-delimited value that is valid UTF-8 but not a message.
+    String(String),
+    Bytes(Bytes),
+    /// The fields between a start-group and its end-group.
+    Group(RawMessage),
+}
+
+impl RawMessage {
+    /// Decodes all of `buf`.
+    pub fn decode(mut buf: impl Buf) -> Result<Self> {
+        DecodableMessage::decode(&mut Deserializer::new(&mut buf))
+    }
+
+    /// The values of all fields with this number.
+    pub fn get(&self, number: u64) -> impl Iterator<Item = &RawValue> {
+        self.fields
+            .iter()
+            .filter(move |f| f.number == number)
+            .map(|f| &f.value)
+    }
+}
+
+impl<'de> DecodableMessage<'de> for RawMessage {
+    type Tag = u64;
+
+    fn decode_field<B: Buf>(&mut self, d: &mut Deserializer<'de, B>, tag: u64) -> Result<()> {
+        self.fields.push(decode_field(d, tag, 0)?);
+        Ok(())
+    }
+}
+
+fn decode_field<B: Buf>(d: &mut Deserializer<'_, B>, tag: u64, depth: usize) -> Result<RawField> {
+    let wire_type = WireTypes::new((tag & 0b111) as u8)?;
+    let value = match wire_type {
+        WireTypes::VarIntWire => RawValue::VarInt(d.read_varint()?),
+        WireTypes::Fixed64Wire => RawValue::Fixed64(d.read_u64()?),
+        WireTypes::Fixed32Wire => RawValue::Fixed32(d.read_u32()?),
+        WireTypes::LengthDelimitedWire => {
+            let len = d.read_varint()?;
+            let bytes = d.read_bytes_shared(len)?;
+            guess_length_delimited(bytes, depth)
+        }
+        WireTypes::StartGroupWire => RawValue::Group(decode_group(d, tag >> 3, depth + 1)?),
+        WireTypes::EndGroupWire => return Err(DecodingError::InvalidGroup),
+    };
+    Ok(RawField {
+        number: tag >> 3,
+        wire_type,
+        value,
+    })
+}
+
+/// Decodes the fields of a group up to its end-group, which has the same field number.
+fn decode_group<B: Buf>(
+    d: &mut Deserializer<'_, B>,
+    number: u64,
+    depth: usize,
+) -> Result<RawMessage> {
+    if depth > MAX_DEPTH {
+        return Err(DecodingError::InvalidGroup);
+    }
+    let mut group = RawMessage::default();
+    loop {
+        let tag: u64 = d.read_varint()?;
+        if tag & 0b111 == WireTypes::EndGroupWire as u64 {
+            if tag >> 3 != number {
+                return Err(DecodingError::InvalidGroup);
+            }
+            return Ok(group);
+        }
+        group.fields.push(decode_field(d, tag, depth)?);
+    }
+}
+
+fn guess_length_delimited(bytes: Bytes, depth: usize) -> RawValue {
+    if !bytes.is_empty() && depth < MAX_DEPTH {
+        if let Some(message) = decode_nested(bytes.clone(), depth + 1) {
+            return RawValue::Message(message);
+        }
+    }
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(s) => RawValue::String(s),
+        Err(_) => RawValue::Bytes(bytes),
+    }
+}
+
+fn decode_nested(mut bytes: Bytes, depth: usize) -> Option<RawMessage> {
+    let d = &mut Deserializer::new(&mut bytes);
+    let mut message = RawMessage::default();
+    while d.has_remaining() {
+        let tag: u64 = d.read_varint().ok()?;
+        // field number 0 is never valid, so this is most likely not a message.
+        if tag >> 3 == 0 {
+            return None;
+        }
+        message.fields.push(decode_field(d, tag, depth).ok()?);
+    }
+    Some(message)
+}
+
+impl RawMessage {
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        for field in &self.fields {
+            write!(f, "{:1$}{2}", "", indent * 2, field.number)?;
+            match &field.value {
+                RawValue::VarInt(n) => writeln!(f, ": {}", n)?,
+                RawValue::Fixed64(n) => writeln!(f, ": 0x{:016x}", n)?,
+                RawValue::Fixed32(n) => writeln!(f, ": 0x{:08x}", n)?,
+                RawValue::String(s) => writeln!(f, ": {}", quoted(s.as_bytes()))?,
+                RawValue::Bytes(b) => writeln!(f, ": {}", quoted(b))?,
+                RawValue::Message(m) | RawValue::Group(m) => {
+                    writeln!(f, " {{")?;
+                    m.fmt_indented(f, indent + 1)?;
+                    writeln!(f, "{:1$}}}", "", indent * 2)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn quoted(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    crate::text_format::write_quoted(&mut out, bytes);
+    out
+}
+
+/// Prints the fields like `protoc --decode_raw`, one per line.
+impl fmt::Display for RawMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
@@ -0,0 +1,169 @@
+//! The messages of `google/protobuf/descriptor.proto` that describe `.proto` files,
+//! as produced by `protoc --descriptor_set_out`.
+//!
+//! Only the fields needed to encode and decode messages are included, other fields are skipped when decoding.
+
+use crate::Repeated;
+
+/// The `type` of a [`FieldDescriptorProto`].
+pub mod field_type {
+    pub const DOUBLE: i32 = 1;
+    pub const FLOAT: i32 = 2;
+    pub const INT64: i32 = 3;
+    pub const UINT64: i32 = 4;
+    pub const INT32: i32 = 5;
+    pub const FIXED64: i32 = 6;
+    pub const FIXED32: i32 = 7;
+    pub const BOOL: i32 = 8;
+    pub const STRING: i32 = 9;
+    pub const GROUP: i32 = 10;
+    pub const MESSAGE: i32 = 11;
+    pub const BYTES: i32 = 12;
+    pub const UINT32: i32 = 13;
+    pub const ENUM: i32 = 14;
+    pub const SFIXED32: i32 = 15;
+    pub const SFIXED64: i32 = 16;
+    pub const SINT32: i32 = 17;
+    pub const SINT64: i32 = 18;
+}
+
+/// The `label` of a [`FieldDescriptorProto`].
+pub mod field_label {
+    pub const OPTIONAL: i32 = 1;
+    pub const REQUIRED: i32 = 2;
+    pub const REPEATED: i32 = 3;
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.FileDescriptorSet")]
+pub struct FileDescriptorSet {
+    #[otopr(1)]
+    pub file: Repeated<Vec<FileDescriptorProto>>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.FileDescriptorProto")]
+pub struct FileDescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+    #[otopr(2)]
+    pub package: Option<String>,
+    #[otopr(3)]
+    pub dependency: Repeated<Vec<String>>,
+    #[otopr(4)]
+    pub message_type: Repeated<Vec<DescriptorProto>>,
+    #[otopr(5)]
+    pub enum_type: Repeated<Vec<EnumDescriptorProto>>,
+    /// `proto2` if unset.
+    #[otopr(12)]
+    pub syntax: Option<String>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.DescriptorProto")]
+pub struct DescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+    #[otopr(2)]
+    pub field: Repeated<Vec<FieldDescriptorProto>>,
+    #[otopr(3)]
+    pub nested_type: Repeated<Vec<DescriptorProto>>,
+    #[otopr(4)]
+    pub enum_type: Repeated<Vec<EnumDescriptorProto>>,
+    #[otopr(7)]
+    pub options: Option<MessageOptions>,
+    #[otopr(8)]
+    pub oneof_decl: Repeated<Vec<OneofDescriptorProto>>,
+    #[otopr(9)]
+    pub reserved_range: Repeated<Vec<ReservedRange>>,
+    #[otopr(10)]
+    pub reserved_name: Repeated<Vec<String>>,
+}
+
+/// `DescriptorProto.ReservedRange`, a range of reserved field numbers with an exclusive end.
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.DescriptorProto.ReservedRange")]
+pub struct ReservedRange {
+    #[otopr(1)]
+    pub start: Option<i32>,
+    #[otopr(2)]
+    pub end: Option<i32>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.MessageOptions")]
+pub struct MessageOptions {
+    /// Set on the messages generated for `map` fields.
+    #[otopr(7)]
+    pub map_entry: Option<bool>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.FieldDescriptorProto")]
+pub struct FieldDescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+    #[otopr(3)]
+    pub number: Option<i32>,
+    /// One of the constants in [`field_label`].
+    #[otopr(4)]
+    pub label: Option<i32>,
+    /// One of the constants in [`field_type`].
+    #[otopr(5)]
+    pub r#type: Option<i32>,
+    /// The fully qualified name of message and enum types, starting with a `.`.
+    #[otopr(6)]
+    pub type_name: Option<String>,
+    #[otopr(7)]
+    pub default_value: Option<String>,
+    #[otopr(8)]
+    pub options: Option<FieldOptions>,
+    #[otopr(9)]
+    pub oneof_index: Option<i32>,
+    #[otopr(10)]
+    pub json_name: Option<String>,
+    #[otopr(17)]
+    pub proto3_optional: Option<bool>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.FieldOptions")]
+pub struct FieldOptions {
+    #[otopr(2)]
+    pub packed: Option<bool>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.OneofDescriptorProto")]
+pub struct OneofDescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.EnumDescriptorProto")]
+pub struct EnumDescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+    #[otopr(2)]
+    pub value: Repeated<Vec<EnumValueDescriptorProto>>,
+    #[otopr(3)]
+    pub options: Option<EnumOptions>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.EnumOptions")]
+pub struct EnumOptions {
+    /// Set on enums with several names for one value.
+    #[otopr(2)]
+    pub allow_alias: Option<bool>,
+}
+
+#[derive(crate::EncodableMessage, crate::DecodableMessage, Clone, Debug, Default, PartialEq)]
+#[otopr(name = "google.protobuf.EnumValueDescriptorProto")]
+pub struct EnumValueDescriptorProto {
+    #[otopr(1)]
+    pub name: Option<String>,
+    #[otopr(2)]
+    pub number: Option<i32>,
+}
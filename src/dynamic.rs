@@ -0,0 +1,1574 @@
+//! Messages whose schema is only known at runtime, from a [`FileDescriptorSet`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::decoding::{Decodable, DecodableMessage, DecodingError, Deserializer};
+use crate::descriptor::{
+    field_label, field_type, DescriptorProto, EnumDescriptorProto, FieldDescriptorProto,
+    FileDescriptorSet,
+};
+use crate::encoding::{EncodableMessage, ProtobufSerializer};
+use crate::reflect::camel_case;
+use crate::text_format::{self, Parser, Printer, TextFormat, TextMessage};
+use crate::wire_types::*;
+use crate::VarInt;
+
+/// How deeply messages can be nested when decoding, like the default limit of `protoc`.
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug)]
+pub enum DescriptorError {
+    Decoding(DecodingError),
+    /// A field refers to a message or enum type that is not in the pool.
+    UnknownType(String),
+    /// A field has a type that is not supported, such as a group.
+    UnsupportedType {
+        field: String,
+        r#type: i32,
+    },
+    /// A name is defined more than once.
+    DuplicateName(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decoding(e) => write!(f, "invalid descriptor set: {}", e),
+            Self::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            Self::UnsupportedType { field, r#type } => {
+                write!(f, "field `{}` has unsupported type {}", field, r#type)
+            }
+            Self::DuplicateName(name) => write!(f, "`{}` is defined more than once", name),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodingError> for DescriptorError {
+    fn from(e: DecodingError) -> Self {
+        Self::Decoding(e)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TypeIndex {
+    Message(usize),
+    Enum(usize),
+}
+
+#[derive(Clone, Copy)]
+enum KindIndex {
+    Scalar(i32),
+    Message(usize),
+    Enum(usize),
+}
+
+struct FieldData {
+    name: String,
+    json_name: String,
+    number: u32,
+    cardinality: Cardinality,
+    kind: KindIndex,
+    packed: bool,
+    oneof: Option<usize>,
+}
+
+struct MessageData {
+    full_name: String,
+    fields: Vec<FieldData>,
+    by_number: HashMap<u32, usize>,
+    by_name: HashMap<String, usize>,
+    oneofs: Vec<String>,
+    map_entry: bool,
+}
+
+struct EnumData {
+    full_name: String,
+    values: Vec<(String, i32)>,
+}
+
+#[derive(Default)]
+struct PoolData {
+    messages: Vec<MessageData>,
+    enums: Vec<EnumData>,
+    names: HashMap<String, TypeIndex>,
+}
+
+/// A set of message and enum types, built from a [`FileDescriptorSet`].
+///
+/// Cloning a pool is cheap, as are the schemas handed out by it.
+#[derive(Clone)]
+pub struct DescriptorPool {
+    data: Arc<PoolData>,
+}
+
+/// Fields of a message before the types they refer to are resolved.
+struct PendingMessage<'a> {
+    scope: String,
+    proto3: bool,
+    descriptor: &'a DescriptorProto,
+}
+
+impl DescriptorPool {
+    /// Decodes a `FileDescriptorSet`, as written by `protoc --descriptor_set_out`, and builds a pool from it.
+    pub fn decode(mut buf: impl Buf) -> Result<Self, DescriptorError> {
+        let set: FileDescriptorSet = DecodableMessage::decode(&mut Deserializer::new(&mut buf))?;
+        Self::from_file_descriptor_set(&set)
+    }
+
+    pub fn from_file_descriptor_set(set: &FileDescriptorSet) -> Result<Self, DescriptorError> {
+        let mut data = PoolData::default();
+        let mut pending = Vec::new();
+
+        for file in set.file.iter() {
+            let package = file.package.clone().unwrap_or_default();
+            let proto3 = file.syntax.as_deref() == Some("proto3");
+            for message in file.message_type.iter() {
+                data.add_message(&package, proto3, message, &mut pending)?;
+            }
+            for enumeration in file.enum_type.iter() {
+                data.add_enum(&package, enumeration)?;
+            }
+        }
+
+        for (index, message) in pending.into_iter().enumerate() {
+            for field in message.descriptor.field.iter() {
+                let field = data.resolve_field(&message, field)?;
+                let m = &mut data.messages[index];
+                m.by_number.insert(field.number, m.fields.len());
+                m.by_name.insert(field.name.clone(), m.fields.len());
+                m.fields.push(field);
+            }
+        }
+
+        Ok(Self {
+            data: Arc::new(data),
+        })
+    }
+
+    /// Looks up a message by its fully qualified name, like `google.protobuf.Duration`.
+    pub fn get_message(&self, full_name: &str) -> Option<MessageSchema> {
+        match self.data.names.get(full_name.trim_start_matches('.')) {
+            Some(TypeIndex::Message(index)) => Some(MessageSchema {
+                pool: self.clone(),
+                index: *index,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Looks up an enum by its fully qualified name.
+    pub fn get_enum(&self, full_name: &str) -> Option<EnumSchema> {
+        match self.data.names.get(full_name.trim_start_matches('.')) {
+            Some(TypeIndex::Enum(index)) => Some(EnumSchema {
+                pool: self.clone(),
+                index: *index,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = MessageSchema> + '_ {
+        (0..self.data.messages.len()).map(move |index| MessageSchema {
+            pool: self.clone(),
+            index,
+        })
+    }
+
+    pub fn enums(&self) -> impl Iterator<Item = EnumSchema> + '_ {
+        (0..self.data.enums.len()).map(move |index| EnumSchema {
+            pool: self.clone(),
+            index,
+        })
+    }
+}
+
+impl fmt::Debug for DescriptorPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.data.names.keys()).finish()
+    }
+}
+
+fn join(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+impl PoolData {
+    fn add_name(&mut self, full_name: &str, index: TypeIndex) -> Result<(), DescriptorError> {
+        if self.names.insert(full_name.to_owned(), index).is_some() {
+            return Err(DescriptorError::DuplicateName(full_name.to_owned()));
+        }
+        Ok(())
+    }
+
+    fn add_message<'a>(
+        &mut self,
+        scope: &str,
+        proto3: bool,
+        descriptor: &'a DescriptorProto,
+        pending: &mut Vec<PendingMessage<'a>>,
+    ) -> Result<(), DescriptorError> {
+        let full_name = join(scope, descriptor.name.as_deref().unwrap_or_default());
+        self.add_name(&full_name, TypeIndex::Message(self.messages.len()))?;
+        self.messages.push(MessageData {
+            full_name: full_name.clone(),
+            fields: Vec::new(),
+            by_number: HashMap::new(),
+            by_name: HashMap::new(),
+            oneofs: descriptor
+                .oneof_decl
+                .iter()
+                .map(|o| o.name.clone().unwrap_or_default())
+                .collect(),
+            map_entry: descriptor
+                .options
+                .as_ref()
+                .and_then(|o| o.map_entry)
+                .unwrap_or_default(),
+        });
+        pending.push(PendingMessage {
+            scope: full_name.clone(),
+            proto3,
+            descriptor,
+        });
+
+        for nested in descriptor.nested_type.iter() {
+            self.add_message(&full_name, proto3, nested, pending)?;
+        }
+        for enumeration in descriptor.enum_type.iter() {
+            self.add_enum(&full_name, enumeration)?;
+        }
+        Ok(())
+    }
+
+    fn add_enum(
+        &mut self,
+        scope: &str,
+        descriptor: &EnumDescriptorProto,
+    ) -> Result<(), DescriptorError> {
+        let full_name = join(scope, descriptor.name.as_deref().unwrap_or_default());
+        self.add_name(&full_name, TypeIndex::Enum(self.enums.len()))?;
+        self.enums.push(EnumData {
+            full_name,
+            values: descriptor
+                .value
+                .iter()
+                .map(|v| {
+                    (
+                        v.name.clone().unwrap_or_default(),
+                        v.number.unwrap_or_default(),
+                    )
+                })
+                .collect(),
+        });
+        Ok(())
+    }
+
+    /// Finds a type by a fully qualified name starting with a `.`, or by a name relative to `scope`.
+    fn resolve(&self, scope: &str, name: &str) -> Option<TypeIndex> {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return self.names.get(full_name).copied();
+        }
+        let mut scope = scope;
+        loop {
+            if let Some(index) = self.names.get(&join(scope, name)) {
+                return Some(*index);
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope.rfind('.').map_or("", |n| &scope[..n]);
+        }
+    }
+
+    fn resolve_field(
+        &self,
+        message: &PendingMessage<'_>,
+        field: &FieldDescriptorProto,
+    ) -> Result<FieldData, DescriptorError> {
+        let name = field.name.clone().unwrap_or_default();
+        let r#type = field.r#type.unwrap_or_default();
+        let type_name = field.type_name.as_deref().unwrap_or_default();
+        let kind = match (r#type, self.resolve(&message.scope, type_name)) {
+            (field_type::MESSAGE, Some(TypeIndex::Message(index))) => KindIndex::Message(index),
+            (field_type::ENUM, Some(TypeIndex::Enum(index))) => KindIndex::Enum(index),
+            // protoc leaves out the type of fields when only their type name is known.
+            (0, Some(TypeIndex::Message(index))) => KindIndex::Message(index),
+            (0, Some(TypeIndex::Enum(index))) => KindIndex::Enum(index),
+            (field_type::MESSAGE | field_type::ENUM | 0, _) => {
+                return Err(DescriptorError::UnknownType(type_name.to_owned()))
+            }
+            (field_type::DOUBLE..=field_type::SINT64, _) if r#type != field_type::GROUP => {
+                KindIndex::Scalar(r#type)
+            }
+            _ => {
+                return Err(DescriptorError::UnsupportedType {
+                    field: name,
+                    r#type,
+                })
+            }
+        };
+        let cardinality = match field.label {
+            Some(field_label::REPEATED) => Cardinality::Repeated,
+            Some(field_label::REQUIRED) => Cardinality::Required,
+            _ => Cardinality::Optional,
+        };
+        let packable = matches!(kind, KindIndex::Scalar(t) if !matches!(t, field_type::STRING | field_type::BYTES))
+            || matches!(kind, KindIndex::Enum(_));
+        let packed = cardinality == Cardinality::Repeated
+            && packable
+            && field
+                .options
+                .as_ref()
+                .and_then(|o| o.packed)
+                .unwrap_or(message.proto3);
+        Ok(FieldData {
+            json_name: field
+                .json_name
+                .clone()
+                .unwrap_or_else(|| camel_case(&name, false)),
+            name,
+            number: field.number.unwrap_or_default() as u32,
+            cardinality,
+            kind,
+            packed,
+            oneof: field
+                .oneof_index
+                .filter(|_| !field.proto3_optional.unwrap_or_default())
+                .map(|i| i as usize),
+        })
+    }
+}
+
+/// A message type in a [`DescriptorPool`].
+#[derive(Clone)]
+pub struct MessageSchema {
+    pool: DescriptorPool,
+    index: usize,
+}
+
+impl MessageSchema {
+    fn data(&self) -> &MessageData {
+        &self.pool.data.messages[self.index]
+    }
+
+    pub fn pool(&self) -> &DescriptorPool {
+        &self.pool
+    }
+
+    /// The fully qualified name, like `google.protobuf.Duration`.
+    pub fn full_name(&self) -> &str {
+        &self.data().full_name
+    }
+
+    /// The name without the package and enclosing messages.
+    pub fn name(&self) -> &str {
+        let full_name = self.full_name();
+        full_name.rsplit('.').next().unwrap_or(full_name)
+    }
+
+    /// Whether this is the entry message generated for a `map` field.
+    pub fn is_map_entry(&self) -> bool {
+        self.data().map_entry
+    }
+
+    /// The fields in the order they are declared.
+    pub fn fields(&self) -> impl ExactSizeIterator<Item = FieldSchema> + '_ {
+        (0..self.data().fields.len()).map(move |index| FieldSchema {
+            message: self.clone(),
+            index,
+        })
+    }
+
+    pub fn get_field(&self, number: u32) -> Option<FieldSchema> {
+        self.data()
+            .by_number
+            .get(&number)
+            .map(|&index| FieldSchema {
+                message: self.clone(),
+                index,
+            })
+    }
+
+    /// Looks up a field by its name in the `.proto` file.
+    pub fn get_field_by_name(&self, name: &str) -> Option<FieldSchema> {
+        self.data().by_name.get(name).map(|&index| FieldSchema {
+            message: self.clone(),
+            index,
+        })
+    }
+
+    /// Looks up a field by its name in the JSON mapping.
+    pub fn get_field_by_json_name(&self, json_name: &str) -> Option<FieldSchema> {
+        self.fields().find(|f| f.json_name() == json_name)
+    }
+}
+
+impl PartialEq for MessageSchema {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.pool.data, &other.pool.data) && self.index == other.index
+    }
+}
+
+impl fmt::Debug for MessageSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MessageSchema")
+            .field(&self.full_name())
+            .finish()
+    }
+}
+
+/// An enum type in a [`DescriptorPool`].
+#[derive(Clone)]
+pub struct EnumSchema {
+    pool: DescriptorPool,
+    index: usize,
+}
+
+impl EnumSchema {
+    fn data(&self) -> &EnumData {
+        &self.pool.data.enums[self.index]
+    }
+
+    pub fn full_name(&self) -> &str {
+        &self.data().full_name
+    }
+
+    pub fn name(&self) -> &str {
+        let full_name = self.full_name();
+        full_name.rsplit('.').next().unwrap_or(full_name)
+    }
+
+    /// The names and numbers of the values in the order they are declared.
+    pub fn values(&self) -> impl ExactSizeIterator<Item = (&str, i32)> {
+        self.data()
+            .values
+            .iter()
+            .map(|(name, n)| (name.as_str(), *n))
+    }
+
+    /// The name of the first value with this number.
+    pub fn name_of(&self, number: i32) -> Option<&str> {
+        self.values()
+            .find(|(_, n)| *n == number)
+            .map(|(name, _)| name)
+    }
+
+    pub fn number_of(&self, name: &str) -> Option<i32> {
+        self.values()
+            .find(|(n, _)| *n == name)
+            .map(|(_, number)| number)
+    }
+
+    /// The number of the first value, which is the default.
+    pub fn default_number(&self) -> i32 {
+        self.values().next().map_or(0, |(_, n)| n)
+    }
+}
+
+impl PartialEq for EnumSchema {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.pool.data, &other.pool.data) && self.index == other.index
+    }
+}
+
+impl fmt::Debug for EnumSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EnumSchema")
+            .field(&self.full_name())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cardinality {
+    Optional,
+    Required,
+    Repeated,
+}
+
+/// The type of a field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Double,
+    Float,
+    Int64,
+    UInt64,
+    Int32,
+    Fixed64,
+    Fixed32,
+    Bool,
+    String,
+    Bytes,
+    UInt32,
+    SFixed32,
+    SFixed64,
+    SInt32,
+    SInt64,
+    Message(MessageSchema),
+    Enum(EnumSchema),
+}
+
+impl Kind {
+    /// The wire type of a single value of this kind.
+    pub fn wire_type(&self) -> WireTypes {
+        match self {
+            Kind::Double | Kind::Fixed64 | Kind::SFixed64 => WireTypes::Fixed64Wire,
+            Kind::Float | Kind::Fixed32 | Kind::SFixed32 => WireTypes::Fixed32Wire,
+            Kind::String | Kind::Bytes | Kind::Message(_) => WireTypes::LengthDelimitedWire,
+            _ => WireTypes::VarIntWire,
+        }
+    }
+
+    /// The value of a field of this kind that is not set.
+    pub fn default_value(&self) -> Value {
+        match self {
+            Kind::Double => Value::F64(0.0),
+            Kind::Float => Value::F32(0.0),
+            Kind::Int64 | Kind::SFixed64 | Kind::SInt64 => Value::I64(0),
+            Kind::UInt64 | Kind::Fixed64 => Value::U64(0),
+            Kind::Int32 | Kind::SFixed32 | Kind::SInt32 => Value::I32(0),
+            Kind::UInt32 | Kind::Fixed32 => Value::U32(0),
+            Kind::Bool => Value::Bool(false),
+            Kind::String => Value::String(String::new()),
+            Kind::Bytes => Value::Bytes(Bytes::new()),
+            Kind::Message(schema) => Value::Message(DynamicMessage::new(schema.clone())),
+            Kind::Enum(schema) => Value::EnumNumber(schema.default_number()),
+        }
+    }
+}
+
+/// A field of a [`MessageSchema`].
+#[derive(Clone)]
+pub struct FieldSchema {
+    message: MessageSchema,
+    index: usize,
+}
+
+impl FieldSchema {
+    fn data(&self) -> &FieldData {
+        &self.message.data().fields[self.index]
+    }
+
+    /// The message this field belongs to.
+    pub fn containing_message(&self) -> &MessageSchema {
+        &self.message
+    }
+
+    pub fn name(&self) -> &str {
+        &self.data().name
+    }
+
+    pub fn json_name(&self) -> &str {
+        &self.data().json_name
+    }
+
+    pub fn number(&self) -> u32 {
+        self.data().number
+    }
+
+    pub fn cardinality(&self) -> Cardinality {
+        self.data().cardinality
+    }
+
+    pub fn kind(&self) -> Kind {
+        let pool = &self.message.pool;
+        match self.data().kind {
+            KindIndex::Message(index) => Kind::Message(MessageSchema {
+                pool: pool.clone(),
+                index,
+            }),
+            KindIndex::Enum(index) => Kind::Enum(EnumSchema {
+                pool: pool.clone(),
+                index,
+            }),
+            KindIndex::Scalar(t) => match t {
+                field_type::DOUBLE => Kind::Double,
+                field_type::FLOAT => Kind::Float,
+                field_type::INT64 => Kind::Int64,
+                field_type::UINT64 => Kind::UInt64,
+                field_type::INT32 => Kind::Int32,
+                field_type::FIXED64 => Kind::Fixed64,
+                field_type::FIXED32 => Kind::Fixed32,
+                field_type::BOOL => Kind::Bool,
+                field_type::STRING => Kind::String,
+                field_type::BYTES => Kind::Bytes,
+                field_type::UINT32 => Kind::UInt32,
+                field_type::SFIXED32 => Kind::SFixed32,
+                field_type::SFIXED64 => Kind::SFixed64,
+                field_type::SINT32 => Kind::SInt32,
+                field_type::SINT64 => Kind::SInt64,
+                _ => unreachable!("unsupported types are rejected when building the pool"),
+            },
+        }
+    }
+
+    pub fn is_list(&self) -> bool {
+        self.cardinality() == Cardinality::Repeated && !self.is_map()
+    }
+
+    pub fn is_map(&self) -> bool {
+        self.cardinality() == Cardinality::Repeated
+            && matches!(self.kind(), Kind::Message(entry) if entry.is_map_entry())
+    }
+
+    /// Whether this field is encoded packed. Both forms are accepted when decoding.
+    pub fn is_packed(&self) -> bool {
+        self.data().packed
+    }
+
+    /// The name of the `oneof` this field is part of.
+    pub fn containing_oneof(&self) -> Option<&str> {
+        let oneof = self.data().oneof?;
+        self.message.data().oneofs.get(oneof).map(String::as_str)
+    }
+
+    /// The key and value fields of the entry message of a `map` field.
+    pub fn map_entry_fields(&self) -> Option<(FieldSchema, FieldSchema)> {
+        match self.kind() {
+            Kind::Message(entry) if self.is_map() => {
+                Some((entry.get_field(1)?, entry.get_field(2)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The value of this field when it is not set.
+    pub fn default_value(&self) -> Value {
+        if self.is_map() {
+            Value::Map(BTreeMap::new())
+        } else if self.is_list() {
+            Value::List(Vec::new())
+        } else {
+            self.kind().default_value()
+        }
+    }
+}
+
+impl PartialEq for FieldSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message && self.index == other.index
+    }
+}
+
+impl fmt::Debug for FieldSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldSchema")
+            .field("name", &self.name())
+            .field("number", &self.number())
+            .finish()
+    }
+}
+
+/// The value of a field in a [`DynamicMessage`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Bytes),
+    EnumNumber(i32),
+    Message(DynamicMessage),
+    List(Vec<Value>),
+    Map(BTreeMap<MapKey, Value>),
+}
+
+/// The key of a `map` field.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    String(String),
+}
+
+impl From<MapKey> for Value {
+    fn from(key: MapKey) -> Self {
+        match key {
+            MapKey::Bool(b) => Value::Bool(b),
+            MapKey::I32(n) => Value::I32(n),
+            MapKey::I64(n) => Value::I64(n),
+            MapKey::U32(n) => Value::U32(n),
+            MapKey::U64(n) => Value::U64(n),
+            MapKey::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl Value {
+    /// Converts a value to a map key, if it has a type allowed as a key.
+    pub fn into_map_key(self) -> Option<MapKey> {
+        Some(match self {
+            Value::Bool(b) => MapKey::Bool(b),
+            Value::I32(n) => MapKey::I32(n),
+            Value::I64(n) => MapKey::I64(n),
+            Value::U32(n) => MapKey::U32(n),
+            Value::U64(n) => MapKey::U64(n),
+            Value::String(s) => MapKey::String(s),
+            _ => return None,
+        })
+    }
+
+    /// Whether this value can be stored in a field of this kind, ignoring cardinality.
+    pub fn is_valid_for(&self, kind: &Kind) -> bool {
+        match (self, kind) {
+            (Value::Message(m), Kind::Message(schema)) => m.schema() == schema,
+            (Value::Message(_), _) | (Value::List(_), _) | (Value::Map(_), _) => false,
+            (value, kind) => {
+                std::mem::discriminant(value) == std::mem::discriminant(&kind.default_value())
+            }
+        }
+    }
+
+    /// Whether this value can be stored in `field`.
+    pub fn is_valid_for_field(&self, field: &FieldSchema) -> bool {
+        match self {
+            Value::List(values) if field.is_list() => {
+                let kind = field.kind();
+                values.iter().all(|v| v.is_valid_for(&kind))
+            }
+            Value::Map(entries) => match field.map_entry_fields() {
+                Some((key, value)) => {
+                    let (key, value) = (key.kind(), value.kind());
+                    entries.iter().all(|(k, v)| {
+                        Value::from(k.clone()).is_valid_for(&key) && v.is_valid_for(&value)
+                    })
+                }
+                None => false,
+            },
+            value => {
+                field.cardinality() != Cardinality::Repeated && value.is_valid_for(&field.kind())
+            }
+        }
+    }
+}
+
+/// A message of a type from a [`DescriptorPool`].
+///
+/// Unknown fields are skipped when decoding.
+#[derive(Clone, PartialEq)]
+pub struct DynamicMessage {
+    schema: MessageSchema,
+    fields: BTreeMap<u32, Value>,
+}
+
+impl DynamicMessage {
+    /// Creates a message with no fields set.
+    pub fn new(schema: MessageSchema) -> Self {
+        Self {
+            schema,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Decodes a message of the given type.
+    pub fn decode(schema: MessageSchema, mut buf: impl Buf) -> Result<Self, DecodingError> {
+        let mut message = Self::new(schema);
+        message.merge(&mut Deserializer::new(&mut buf))?;
+        Ok(message)
+    }
+
+    /// Decodes fields until the end of the input, merging them into this message.
+    ///
+    /// Fails with [`DecodingError::TooDeep`] if messages are nested more than 100 levels deep.
+    pub fn merge<B: Buf>(&mut self, d: &mut Deserializer<'_, B>) -> Result<(), DecodingError> {
+        self.merge_at(d, 0)
+    }
+
+    /// Merges the fields of a message nested `depth` levels deep.
+    fn merge_at<B: Buf>(
+        &mut self,
+        d: &mut Deserializer<'_, B>,
+        depth: usize,
+    ) -> Result<(), DecodingError> {
+        while d.has_remaining() {
+            match u64::read_field_tag(d) {
+                Ok(tag) => self.decode_field_at(d, tag, depth)?,
+                Err(Ok(wire)) => wire.skip(d)?,
+                Err(Err(e)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn schema(&self) -> &MessageSchema {
+        &self.schema
+    }
+
+    pub fn has_field(&self, field: &FieldSchema) -> bool {
+        self.fields.contains_key(&field.number())
+    }
+
+    /// The value of a field, or its default value if it is not set.
+    pub fn get(&self, field: &FieldSchema) -> Value {
+        match self.fields.get(&field.number()) {
+            Some(value) => value.clone(),
+            None => field.default_value(),
+        }
+    }
+
+    /// The value of a field, if it is set.
+    pub fn get_by_number(&self, number: u32) -> Option<&Value> {
+        self.fields.get(&number)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .get(&self.schema.get_field_by_name(name)?.number())
+    }
+
+    /// Sets a field, clearing the other fields of its `oneof`.
+    ///
+    /// Returns the value back if it does not have the type of the field.
+    pub fn set(&mut self, field: &FieldSchema, value: Value) -> Result<(), Value> {
+        if !value.is_valid_for_field(field) {
+            return Err(value);
+        }
+        if let Some(oneof) = field.data().oneof {
+            for other in self.schema.fields() {
+                if other.data().oneof == Some(oneof) {
+                    self.fields.remove(&other.number());
+                }
+            }
+        }
+        self.fields.insert(field.number(), value);
+        Ok(())
+    }
+
+    /// Sets the field with this name, see [`DynamicMessage::set`].
+    pub fn set_by_name(&mut self, name: &str, value: Value) -> Result<(), Value> {
+        match self.schema.get_field_by_name(name) {
+            Some(field) => self.set(&field, value),
+            None => Err(value),
+        }
+    }
+
+    /// Unsets a field, returning its previous value.
+    pub fn clear(&mut self, field: &FieldSchema) -> Option<Value> {
+        self.fields.remove(&field.number())
+    }
+
+    pub fn clear_by_name(&mut self, name: &str) -> Option<Value> {
+        self.fields
+            .remove(&self.schema.get_field_by_name(name)?.number())
+    }
+
+    /// The fields that are set, ordered by field number.
+    pub fn fields(&self) -> impl Iterator<Item = (FieldSchema, &Value)> {
+        self.fields
+            .iter()
+            .filter_map(move |(n, v)| Some((self.schema.get_field(*n)?, v)))
+    }
+}
+
+impl fmt::Debug for DynamicMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct(self.schema.name());
+        for (field, value) in self.fields() {
+            s.field(field.name(), value);
+        }
+        s.finish()
+    }
+}
+
+fn decode_value<B: Buf>(
+    kind: &Kind,
+    d: &mut Deserializer<'_, B>,
+    depth: usize,
+) -> Result<Value, DecodingError> {
+    Ok(match kind {
+        Kind::Double => Value::F64(f64::decode(d)?),
+        Kind::Float => Value::F32(f32::decode(d)?),
+        Kind::Int64 => Value::I64(u64::decode(d)? as i64),
+        Kind::UInt64 => Value::U64(u64::decode(d)?),
+        // negative `int32`s are sign-extended to 64 bits.
+        Kind::Int32 => Value::I32(u64::decode(d)? as i32),
+        Kind::Fixed64 => Value::U64(d.read_u64()?),
+        Kind::Fixed32 => Value::U32(d.read_u32()?),
+        Kind::Bool => Value::Bool(u64::decode(d)? != 0),
+        Kind::String => Value::String(String::decode(d)?),
+        Kind::Bytes => Value::Bytes(Bytes::decode(d)?),
+        Kind::UInt32 => Value::U32(u64::decode(d)? as u32),
+        Kind::SFixed32 => Value::I32(d.read_u32()? as i32),
+        Kind::SFixed64 => Value::I64(d.read_u64()? as i64),
+        Kind::SInt32 => {
+            let n = u64::decode(d)? as u32;
+            Value::I32((n >> 1) as i32 ^ -((n & 1) as i32))
+        }
+        Kind::SInt64 => {
+            let n = u64::decode(d)?;
+            Value::I64((n >> 1) as i64 ^ -((n & 1) as i64))
+        }
+        Kind::Enum(_) => Value::EnumNumber(u64::decode(d)? as i32),
+        Kind::Message(schema) => {
+            let mut message = DynamicMessage::new(schema.clone());
+            merge_message(&mut message, d, depth)?;
+            Value::Message(message)
+        }
+    })
+}
+
+/// Merges a length-delimited message into `message`, which is a field of a message nested
+/// `depth` levels deep.
+fn merge_message<B: Buf>(
+    message: &mut DynamicMessage,
+    d: &mut Deserializer<'_, B>,
+    depth: usize,
+) -> Result<(), DecodingError> {
+    if depth >= MAX_DEPTH {
+        return Err(DecodingError::TooDeep);
+    }
+    let len = d.read_varint()?;
+    let tk = d.set_limit(len);
+    let res = message.merge_at(d, depth + 1);
+    d.reset_limit(tk);
+    res
+}
+
+impl<'de> DecodableMessage<'de> for DynamicMessage {
+    type Tag = u64;
+
+    fn decode_field<B: Buf>(
+        &mut self,
+        d: &mut Deserializer<'de, B>,
+        tag: u64,
+    ) -> Result<(), DecodingError> {
+        self.decode_field_at(d, tag, 0)
+    }
+}
+
+impl DynamicMessage {
+    fn decode_field_at<B: Buf>(
+        &mut self,
+        d: &mut Deserializer<'_, B>,
+        tag: u64,
+        depth: usize,
+    ) -> Result<(), DecodingError> {
+        let wire = WireTypes::new((tag & 0b111) as u8)?;
+        let number = u32::try_from(tag >> 3).ok();
+        let field = match number.and_then(|n| self.schema.get_field(n)) {
+            Some(field) => field,
+            None => return wire.skip(d),
+        };
+        let kind = field.kind();
+
+        if let Some((key_field, value_field)) = field.map_entry_fields() {
+            if wire != WireTypes::LengthDelimitedWire {
+                return wire.skip(d);
+            }
+            let Kind::Message(entry_schema) = kind else {
+                unreachable!()
+            };
+            let mut entry = DynamicMessage::new(entry_schema);
+            merge_message(&mut entry, d, depth)?;
+            let key = entry.get(&key_field).into_map_key();
+            let value = entry.get(&value_field);
+            if let (Some(key), Value::Map(map)) = (
+                key,
+                self.fields
+                    .entry(field.number())
+                    .or_insert_with(|| Value::Map(BTreeMap::new())),
+            ) {
+                map.insert(key, value);
+            }
+        } else if field.cardinality() == Cardinality::Repeated {
+            let values = match self
+                .fields
+                .entry(field.number())
+                .or_insert_with(|| Value::List(Vec::new()))
+            {
+                Value::List(values) => values,
+                _ => unreachable!("repeated fields are always lists"),
+            };
+            if wire == kind.wire_type() {
+                values.push(decode_value(&kind, d, depth)?);
+            } else if wire == WireTypes::LengthDelimitedWire {
+                // packed
+                let len = d.read_varint()?;
+                let tk = d.set_limit(len);
+                let mut res = Ok(());
+                while res.is_ok() && d.has_remaining() {
+                    res = decode_value(&kind, d, depth).map(|v| values.push(v));
+                }
+                d.reset_limit(tk);
+                res?;
+            } else {
+                wire.skip(d)?;
+            }
+        } else if wire != kind.wire_type() {
+            wire.skip(d)?;
+        } else if let (Kind::Message(_), Some(Value::Message(message))) =
+            (&kind, self.fields.get_mut(&field.number()))
+        {
+            merge_message(message, d, depth)?;
+        } else {
+            let value = decode_value(&kind, d, depth)?;
+            let _ = self.set(&field, value);
+        }
+        Ok(())
+    }
+}
+
+/// A single value as it is written on the wire, without its field tag.
+enum Encoded<'a> {
+    VarInt(u64),
+    Fixed32(u32),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Message(&'a DynamicMessage),
+}
+
+impl<'a> Encoded<'a> {
+    fn new(kind: &Kind, value: &'a Value) -> Self {
+        match (kind, value) {
+            (Kind::SInt32, Value::I32(n)) => Encoded::VarInt(((n << 1) ^ (n >> 31)) as u32 as u64),
+            (Kind::SInt64, Value::I64(n)) => Encoded::VarInt(((n << 1) ^ (n >> 63)) as u64),
+            (Kind::SFixed32, Value::I32(n)) => Encoded::Fixed32(*n as u32),
+            (Kind::SFixed64, Value::I64(n)) => Encoded::Fixed64(*n as u64),
+            (Kind::Fixed32, Value::U32(n)) => Encoded::Fixed32(*n),
+            (Kind::Fixed64, Value::U64(n)) => Encoded::Fixed64(*n),
+            (_, Value::Bool(b)) => Encoded::VarInt(*b as u64),
+            (_, Value::I32(n) | Value::EnumNumber(n)) => Encoded::VarInt(*n as i64 as u64),
+            (_, Value::I64(n)) => Encoded::VarInt(*n as u64),
+            (_, Value::U32(n)) => Encoded::VarInt(*n as u64),
+            (_, Value::U64(n)) => Encoded::VarInt(*n),
+            (_, Value::F32(n)) => Encoded::Fixed32(n.to_bits()),
+            (_, Value::F64(n)) => Encoded::Fixed64(n.to_bits()),
+            (_, Value::String(s)) => Encoded::Bytes(s.as_bytes()),
+            (_, Value::Bytes(b)) => Encoded::Bytes(b),
+            (_, Value::Message(m)) => Encoded::Message(m),
+            (_, Value::List(_) | Value::Map(_)) => {
+                unreachable!("nested lists and maps are not valid values")
+            }
+        }
+    }
+
+    fn wire_type(&self) -> u8 {
+        match self {
+            Encoded::VarInt(_) => VarIntWire::BITS,
+            Encoded::Fixed32(_) => Fixed32Wire::BITS,
+            Encoded::Fixed64(_) => Fixed64Wire::BITS,
+            Encoded::Bytes(_) | Encoded::Message(_) => LengthDelimitedWire::BITS,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Encoded::VarInt(n) => n.size(),
+            Encoded::Fixed32(_) => 4,
+            Encoded::Fixed64(_) => 8,
+            Encoded::Bytes(b) => b.len().size() + b.len(),
+            Encoded::Message(m) => {
+                let len = m.encoded_size();
+                len.size() + len
+            }
+        }
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        match self {
+            Encoded::VarInt(n) => s.write_varint(*n),
+            Encoded::Fixed32(n) => s.write_u32(*n),
+            Encoded::Fixed64(n) => s.write_u64(*n),
+            Encoded::Bytes(b) => {
+                s.write_varint(b.len());
+                s.write_bytes(b);
+            }
+            Encoded::Message(m) => {
+                s.write_varint(m.encoded_size());
+                m.encode(s);
+            }
+        }
+    }
+}
+
+fn tag(number: u32, wire_type: u8) -> u64 {
+    (number as u64) << 3 | wire_type as u64
+}
+
+/// The size of a map entry message, without its tag and length.
+fn entry_size(key: &Encoded<'_>, value: &Encoded<'_>) -> usize {
+    tag(1, key.wire_type()).size() + key.size() + tag(2, value.wire_type()).size() + value.size()
+}
+
+/// Calls `f` with the tag and encoded value of every value written for a field.
+fn for_each_value(field: &FieldSchema, value: &Value, mut f: impl FnMut(Option<u64>, Encoded<'_>)) {
+    let number = field.number();
+    match value {
+        Value::Map(entries) => {
+            let (key_field, value_field) = field
+                .map_entry_fields()
+                .expect("map values are only set on map fields");
+            let (key_kind, value_kind) = (key_field.kind(), value_field.kind());
+            for (key, value) in entries {
+                let key = Value::from(key.clone());
+                let (key, value) = (
+                    Encoded::new(&key_kind, &key),
+                    Encoded::new(&value_kind, value),
+                );
+                let len = entry_size(&key, &value);
+                f(
+                    Some(tag(number, LengthDelimitedWire::BITS)),
+                    Encoded::VarInt(len as u64),
+                );
+                f(None, Encoded::VarInt(tag(1, key.wire_type())));
+                f(None, key);
+                f(None, Encoded::VarInt(tag(2, value.wire_type())));
+                f(None, value);
+            }
+        }
+        Value::List(values) if field.is_packed() => {
+            if values.is_empty() {
+                return;
+            }
+            let kind = field.kind();
+            let len: usize = values.iter().map(|v| Encoded::new(&kind, v).size()).sum();
+            f(
+                Some(tag(number, LengthDelimitedWire::BITS)),
+                Encoded::VarInt(len as u64),
+            );
+            for value in values {
+                f(None, Encoded::new(&kind, value));
+            }
+        }
+        Value::List(values) => {
+            let kind = field.kind();
+            for value in values {
+                let encoded = Encoded::new(&kind, value);
+                f(Some(tag(number, encoded.wire_type())), encoded);
+            }
+        }
+        value => {
+            let encoded = Encoded::new(&field.kind(), value);
+            f(Some(tag(number, encoded.wire_type())), encoded);
+        }
+    }
+}
+
+impl EncodableMessage for DynamicMessage {
+    fn encoded_size(&self) -> usize {
+        let mut size = 0;
+        for (field, value) in self.fields() {
+            for_each_value(&field, value, |tag, encoded| {
+                size += tag.map_or(0, VarInt::size) + encoded.size();
+            });
+        }
+        size
+    }
+
+    fn encode<T: BufMut>(&self, s: &mut ProtobufSerializer<T>) {
+        for (field, value) in self.fields() {
+            for_each_value(&field, value, |tag, encoded| {
+                if let Some(tag) = tag {
+                    s.write_varint(tag);
+                }
+                encoded.encode(s);
+            });
+        }
+    }
+}
+
+fn print_value(p: &mut Printer, kind: &Kind, value: &Value) {
+    match value {
+        Value::Bool(b) => b.print_value(p),
+        Value::I32(n) => n.print_value(p),
+        Value::I64(n) => n.print_value(p),
+        Value::U32(n) => n.print_value(p),
+        Value::U64(n) => n.print_value(p),
+        Value::F32(n) => n.print_value(p),
+        Value::F64(n) => n.print_value(p),
+        Value::String(s) => p.write_quoted(s.as_bytes()),
+        Value::Bytes(b) => p.write_quoted(b),
+        Value::EnumNumber(n) => match kind {
+            Kind::Enum(schema) => match schema.name_of(*n) {
+                Some(name) => p.write_str(name),
+                None => n.print_value(p),
+            },
+            _ => n.print_value(p),
+        },
+        Value::Message(m) => p.message(m),
+        Value::List(_) | Value::Map(_) => {
+            unreachable!("nested lists and maps are not valid values")
+        }
+    }
+}
+
+fn parse_value(p: &mut Parser<'_>, kind: &Kind) -> text_format::Result<Value> {
+    Ok(match kind {
+        Kind::Double => Value::F64(f64::parse_value(p)?),
+        Kind::Float => Value::F32(f32::parse_value(p)?),
+        Kind::Int32 | Kind::SInt32 | Kind::SFixed32 => Value::I32(i32::parse_value(p)?),
+        Kind::Int64 | Kind::SInt64 | Kind::SFixed64 => Value::I64(i64::parse_value(p)?),
+        Kind::UInt32 | Kind::Fixed32 => Value::U32(u32::parse_value(p)?),
+        Kind::UInt64 | Kind::Fixed64 => Value::U64(u64::parse_value(p)?),
+        Kind::Bool => Value::Bool(bool::parse_value(p)?),
+        Kind::String => Value::String(String::parse_value(p)?),
+        Kind::Bytes => Value::Bytes(Bytes::parse_value(p)?),
+        Kind::Enum(schema) => {
+            let token = p.token()?;
+            match token.parse().ok().or_else(|| schema.number_of(token)) {
+                Some(n) => Value::EnumNumber(n),
+                None => return p.error(format!("unknown enum value `{}`", token)),
+            }
+        }
+        Kind::Message(schema) => {
+            let mut message = DynamicMessage::new(schema.clone());
+            p.message(&mut message)?;
+            Value::Message(message)
+        }
+    })
+}
+
+impl TextMessage for DynamicMessage {
+    fn print_fields(&self, p: &mut Printer) {
+        for (field, value) in self.fields() {
+            let kind = field.kind();
+            match value {
+                Value::List(values) => {
+                    for value in values {
+                        p.field_with(field.name(), value.is_message(), |p| {
+                            print_value(p, &kind, value)
+                        });
+                    }
+                }
+                Value::Map(entries) => {
+                    let entry_schema = match kind {
+                        Kind::Message(schema) => schema,
+                        _ => unreachable!("map values are only set on map fields"),
+                    };
+                    for (key, value) in entries {
+                        let mut entry = DynamicMessage::new(entry_schema.clone());
+                        entry.fields.insert(1, key.clone().into());
+                        entry.fields.insert(2, value.clone());
+                        p.field_with(field.name(), true, |p| p.message(&entry));
+                    }
+                }
+                value => p.field_with(field.name(), value.is_message(), |p| {
+                    print_value(p, &kind, value)
+                }),
+            }
+        }
+    }
+
+    /// Parses a field by its name, or by its number.
+    fn merge_field(&mut self, name: &str, p: &mut Parser<'_>) -> text_format::Result<()> {
+        let field = self.schema.get_field_by_name(name).or_else(|| {
+            name.parse()
+                .ok()
+                .and_then(|number| self.schema.get_field(number))
+        });
+        let field = match field {
+            Some(field) => field,
+            None => return p.unknown_field(name),
+        };
+        let kind = field.kind();
+        let message = matches!(kind, Kind::Message(_));
+        p.field_separator(message)?;
+
+        if let Some((key_field, value_field)) = field.map_entry_fields() {
+            let entry = match parse_value(p, &kind)? {
+                Value::Message(entry) => entry,
+                _ => unreachable!("map entries are messages"),
+            };
+            let key = entry.get(&key_field).into_map_key();
+            let value = entry.get(&value_field);
+            if let (Some(key), Value::Map(map)) = (
+                key,
+                self.fields
+                    .entry(field.number())
+                    .or_insert_with(|| Value::Map(BTreeMap::new())),
+            ) {
+                map.insert(key, value);
+            }
+        } else if field.is_list() {
+            let values = match self
+                .fields
+                .entry(field.number())
+                .or_insert_with(|| Value::List(Vec::new()))
+            {
+                Value::List(values) => values,
+                _ => unreachable!("repeated fields are always lists"),
+            };
+            if !p.try_consume('[') {
+                values.push(parse_value(p, &kind)?);
+            } else if !p.try_consume(']') {
+                loop {
+                    values.push(parse_value(p, &kind)?);
+                    if p.try_consume(']') {
+                        break;
+                    }
+                    p.expect(',')?;
+                }
+            }
+        } else if let (true, Some(Value::Message(existing))) =
+            (message, self.fields.get_mut(&field.number()))
+        {
+            p.message(existing)?;
+        } else {
+            let value = parse_value(p, &kind)?;
+            let _ = self.set(&field, value);
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    fn is_message(&self) -> bool {
+        matches!(self, Value::Message(_))
+    }
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use super::*;
+    use crate::json::{
+        object_fields, JsonError, ParseOptions, PrintOptions, ProtoJson, ProtoJsonKey, Result,
+        Value as Json,
+    };
+    use crate::well_known;
+
+    /// Converts well-known types through their static types, since they have special JSON representations.
+    macro_rules! well_known {
+        ($($ty:ident),* $(,)?) => {
+            fn well_known_to_json(message: &DynamicMessage, options: &PrintOptions) -> Option<Json> {
+                let mut buf = Vec::new();
+                match message.schema.full_name() {
+                    $(concat!("google.protobuf.", stringify!($ty)) => {
+                        message.encode(&mut ProtobufSerializer::new(&mut buf));
+                        let value: well_known::$ty = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice())).ok()?;
+                        Some(value.to_json(options))
+                    })*
+                    _ => None,
+                }
+            }
+
+            fn well_known_from_json(
+                schema: &MessageSchema,
+                json: &Json,
+                options: &ParseOptions,
+            ) -> Option<Result<DynamicMessage>> {
+                let mut buf = Vec::new();
+                match schema.full_name() {
+                    $(concat!("google.protobuf.", stringify!($ty)) => {
+                        let value = match well_known::$ty::from_json(json, options) {
+                            Ok(value) => value,
+                            Err(e) => return Some(Err(e)),
+                        };
+                        value.encode(&mut ProtobufSerializer::new(&mut buf));
+                    })*
+                    _ => return None,
+                }
+                Some(DynamicMessage::decode(schema.clone(), buf.as_slice())
+                    .map_err(|e| JsonError::InvalidValue(e.to_string())))
+            }
+        };
+    }
+
+    well_known! {
+        Timestamp, Duration, FieldMask, Struct, Value, ListValue, Empty,
+        DoubleValue, FloatValue, Int64Value, UInt64Value, Int32Value, UInt32Value,
+        BoolValue, StringValue, BytesValue,
+    }
+
+    fn map_key_to_json(key: &MapKey) -> String {
+        match key {
+            MapKey::Bool(b) => b.to_json_key(),
+            MapKey::I32(n) => n.to_json_key(),
+            MapKey::I64(n) => n.to_json_key(),
+            MapKey::U32(n) => n.to_json_key(),
+            MapKey::U64(n) => n.to_json_key(),
+            MapKey::String(s) => s.to_json_key(),
+        }
+    }
+
+    fn map_key_from_json(kind: &Kind, key: &str) -> Result<MapKey> {
+        Ok(match kind {
+            Kind::Bool => MapKey::Bool(bool::from_json_key(key)?),
+            Kind::Int32 | Kind::SInt32 | Kind::SFixed32 => MapKey::I32(i32::from_json_key(key)?),
+            Kind::Int64 | Kind::SInt64 | Kind::SFixed64 => MapKey::I64(i64::from_json_key(key)?),
+            Kind::UInt32 | Kind::Fixed32 => MapKey::U32(u32::from_json_key(key)?),
+            Kind::UInt64 | Kind::Fixed64 => MapKey::U64(u64::from_json_key(key)?),
+            _ => MapKey::String(key.to_owned()),
+        })
+    }
+
+    fn value_to_json(kind: &Kind, value: &super::Value, options: &PrintOptions) -> Json {
+        use super::Value as V;
+        match value {
+            V::Bool(b) => b.to_json(options),
+            V::I32(n) => n.to_json(options),
+            V::I64(n) => n.to_json(options),
+            V::U32(n) => n.to_json(options),
+            V::U64(n) => n.to_json(options),
+            V::F32(n) => n.to_json(options),
+            V::F64(n) => n.to_json(options),
+            V::String(s) => s.to_json(options),
+            V::Bytes(b) => b.to_json(options),
+            V::EnumNumber(n) => match kind {
+                Kind::Enum(schema) if schema.full_name() == "google.protobuf.NullValue" => {
+                    Json::Null
+                }
+                Kind::Enum(schema) => match schema.name_of(*n) {
+                    Some(name) => Json::String(name.to_owned()),
+                    None => n.to_json(options),
+                },
+                _ => n.to_json(options),
+            },
+            V::Message(m) => m.to_json(options),
+            V::List(values) => Json::Array(
+                values
+                    .iter()
+                    .map(|v| value_to_json(kind, v, options))
+                    .collect(),
+            ),
+            V::Map(_) => unreachable!("maps are converted by `DynamicMessage::to_json`"),
+        }
+    }
+
+    fn value_from_json(kind: &Kind, json: &Json, options: &ParseOptions) -> Result<super::Value> {
+        use super::Value as V;
+        Ok(match kind {
+            Kind::Double => V::F64(f64::from_json(json, options)?),
+            Kind::Float => V::F32(f32::from_json(json, options)?),
+            Kind::Int32 | Kind::SInt32 | Kind::SFixed32 => V::I32(i32::from_json(json, options)?),
+            Kind::Int64 | Kind::SInt64 | Kind::SFixed64 => V::I64(i64::from_json(json, options)?),
+            Kind::UInt32 | Kind::Fixed32 => V::U32(u32::from_json(json, options)?),
+            Kind::UInt64 | Kind::Fixed64 => V::U64(u64::from_json(json, options)?),
+            Kind::Bool => V::Bool(bool::from_json(json, options)?),
+            Kind::String => V::String(String::from_json(json, options)?),
+            Kind::Bytes => V::Bytes(Bytes::from_json(json, options)?),
+            Kind::Enum(schema) => match json {
+                Json::String(name) => match schema.number_of(name) {
+                    Some(n) => V::EnumNumber(n),
+                    None => return Err(JsonError::InvalidValue(name.clone())),
+                },
+                Json::Null if schema.full_name() == "google.protobuf.NullValue" => V::EnumNumber(0),
+                json => V::EnumNumber(i32::from_json(json, options)?),
+            },
+            Kind::Message(schema) => {
+                V::Message(DynamicMessage::from_json(schema.clone(), json, options)?)
+            }
+        })
+    }
+
+    impl DynamicMessage {
+        /// Converts this message to the proto3 JSON mapping, printing the fields that are set.
+        pub fn to_json(&self, options: &PrintOptions) -> Json {
+            if let Some(json) = well_known_to_json(self, options) {
+                return json;
+            }
+            let mut object = serde_json::Map::new();
+            for field in self.schema.fields() {
+                let value = match self.fields.get(&field.number()) {
+                    Some(value) => value.clone(),
+                    None if options.emit_default_values
+                        && field.containing_oneof().is_none()
+                        && (field.cardinality() == Cardinality::Repeated
+                            || !matches!(field.kind(), Kind::Message(_))) =>
+                    {
+                        field.default_value()
+                    }
+                    None => continue,
+                };
+                let json = match (&value, field.map_entry_fields()) {
+                    (super::Value::Map(entries), Some((_, value_field))) => {
+                        let kind = value_field.kind();
+                        Json::Object(
+                            entries
+                                .iter()
+                                .map(|(k, v)| {
+                                    (map_key_to_json(k), value_to_json(&kind, v, options))
+                                })
+                                .collect(),
+                        )
+                    }
+                    (value, _) => value_to_json(&field.kind(), value, options),
+                };
+                let name = if options.preserve_proto_field_names {
+                    field.name()
+                } else {
+                    field.json_name()
+                };
+                object.insert(name.to_owned(), json);
+            }
+            Json::Object(object)
+        }
+
+        /// Parses a message of the given type from the proto3 JSON mapping.
+        pub fn from_json(
+            schema: MessageSchema,
+            json: &Json,
+            options: &ParseOptions,
+        ) -> Result<Self> {
+            if let Some(res) = well_known_from_json(&schema, json, options) {
+                return res;
+            }
+            let mut message = DynamicMessage::new(schema);
+            for (name, json) in object_fields(json)? {
+                let field = match message
+                    .schema
+                    .get_field_by_json_name(name)
+                    .or_else(|| message.schema.get_field_by_name(name))
+                {
+                    Some(field) => field,
+                    None if options.ignore_unknown_fields => continue,
+                    None => return Err(JsonError::UnknownField(name.to_owned())),
+                };
+                let kind = field.kind();
+                let value = if let Some((key_field, value_field)) = field.map_entry_fields() {
+                    let (key_kind, value_kind) = (key_field.kind(), value_field.kind());
+                    let entries = match json {
+                        Json::Object(entries) => entries,
+                        _ => return Err(JsonError::UnexpectedType { expected: "object" }),
+                    };
+                    let mut map = BTreeMap::new();
+                    for (key, value) in entries {
+                        map.insert(
+                            map_key_from_json(&key_kind, key)?,
+                            value_from_json(&value_kind, value, options)?,
+                        );
+                    }
+                    super::Value::Map(map)
+                } else if field.is_list() {
+                    match json {
+                        Json::Array(values) => super::Value::List(
+                            values
+                                .iter()
+                                .map(|v| value_from_json(&kind, v, options))
+                                .collect::<Result<_>>()?,
+                        ),
+                        _ => return Err(JsonError::UnexpectedType { expected: "array" }),
+                    }
+                } else {
+                    value_from_json(&kind, json, options)?
+                };
+                let _ = message.set(&field, value);
+            }
+            Ok(message)
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::borrow::Cow;
 use std::marker::PhantomData;
 
-use bytes::BufMut;
+use bytes::{BufMut, Bytes};
 
-use crate::{traits::Signable, wire_types::*, Fixed32, Fixed64, Signed, VarInt};
+use crate::{
+    mask::Mask, traits::Signable, wire_types::*, BytesStr, Fixed32, Fixed64, Signed, VarInt,
+};
 
 pub trait Encodable {
     type Wire: WireType;
@@ -32,11 +35,56 @@ pub trait Encodable {
         s.buf.put_slice(field_number);
         self.encode(s);
     }
+
+    /// Like [`encoded_size()`], but only with the fields in `mask` for messages.
+    ///
+    /// Types without fields ignore the mask.
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, _mask: &Mask) -> usize {
+        self.encoded_size(field_number)
+    }
+
+    /// Like [`encode_field()`], but only with the fields in `mask` for messages.
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        _mask: &Mask,
+    ) {
+        self.encode_field(s, field_number)
+    }
 }
 
 pub trait EncodableMessage {
     fn encoded_size(&self) -> usize;
     fn encode<T: BufMut>(&self, s: &mut ProtobufSerializer<T>);
+
+    /// The size with only the fields in `mask`. Messages that do not support masks have all fields.
+    fn encoded_size_masked(&self, _mask: &Mask) -> usize {
+        self.encoded_size()
+    }
+
+    /// Encodes only the fields in `mask`. Messages that do not support masks encode all fields.
+    fn encode_masked<T: BufMut>(&self, s: &mut ProtobufSerializer<T>, _mask: &Mask) {
+        self.encode(s)
+    }
+}
+
+impl<M: EncodableMessage + ?Sized> EncodableMessage for &M {
+    fn encoded_size(&self) -> usize {
+        M::encoded_size(*self)
+    }
+
+    fn encode<T: BufMut>(&self, s: &mut ProtobufSerializer<T>) {
+        M::encode(*self, s)
+    }
+
+    fn encoded_size_masked(&self, mask: &Mask) -> usize {
+        M::encoded_size_masked(*self, mask)
+    }
+
+    fn encode_masked<T: BufMut>(&self, s: &mut ProtobufSerializer<T>, mask: &Mask) {
+        M::encode_masked(*self, s, mask)
+    }
 }
 
 pub struct EncodeAsRef<T, U: ?Sized>(T, PhantomData<U>);
@@ -65,6 +113,19 @@ impl<T: AsRef<U>, U: ?Sized + Encodable> Encodable for EncodeAsRef<T, U> {
     unsafe fn encode_field_precomputed(&self, s: &mut ProtobufSerializer<impl BufMut>, field_number: &[u8]) {
         self.0.as_ref().encode_field_precomputed(s, field_number)
     }
+
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+        self.0.as_ref().encoded_size_masked(field_number, mask)
+    }
+
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        self.0.as_ref().encode_field_masked(s, field_number, mask)
+    }
 }
 
 pub struct ProtobufSerializer<T> {
@@ -185,7 +246,127 @@ impl Encodable for Vec<u8> {
     }
 }
 
-impl<'a, T: Encodable + ?Sized> Encodable for &'a T {
+impl Encodable for String {
+    type Wire = <str as Encodable>::Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        <str as Encodable>::encoded_size(self, field_number)
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        <str as Encodable>::encode(self, s)
+    }
+}
+
+impl Encodable for Bytes {
+    type Wire = <[u8] as Encodable>::Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        <[u8] as Encodable>::encoded_size(self, field_number)
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        <[u8] as Encodable>::encode(self, s)
+    }
+}
+
+impl Encodable for BytesStr {
+    type Wire = <str as Encodable>::Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        <str as Encodable>::encoded_size(self, field_number)
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        <str as Encodable>::encode(self, s)
+    }
+}
+
+impl<T: Encodable + ToOwned + ?Sized> Encodable for Cow<'_, T> {
+    type Wire = T::Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        T::encoded_size(self, field_number)
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        T::encode(self, s)
+    }
+
+    fn encode_field<V: VarInt>(&self, s: &mut ProtobufSerializer<impl BufMut>, field_number: V) {
+        T::encode_field(self, s, field_number)
+    }
+
+    unsafe fn encode_field_precomputed(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: &[u8],
+    ) {
+        T::encode_field_precomputed(self, s, field_number)
+    }
+
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+        T::encoded_size_masked(self, field_number, mask)
+    }
+
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        T::encode_field_masked(self, s, field_number, mask)
+    }
+}
+
+/// An optional field, which is not encoded at all when it is `None`.
+impl<T: Encodable> Encodable for Option<T> {
+    type Wire = T::Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        self.as_ref().map_or(0, |t| t.encoded_size(field_number))
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        if let Some(t) = self {
+            t.encode(s)
+        }
+    }
+
+    fn encode_field<V: VarInt>(&self, s: &mut ProtobufSerializer<impl BufMut>, field_number: V) {
+        if let Some(t) = self {
+            t.encode_field(s, field_number)
+        }
+    }
+
+    unsafe fn encode_field_precomputed(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: &[u8],
+    ) {
+        if let Some(t) = self {
+            t.encode_field_precomputed(s, field_number)
+        }
+    }
+
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+        self.as_ref()
+            .map_or(0, |t| t.encoded_size_masked(field_number, mask))
+    }
+
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        if let Some(t) = self {
+            t.encode_field_masked(s, field_number, mask)
+        }
+    }
+}
+
+impl<T: Encodable + ?Sized> Encodable for &T {
     type Wire = T::Wire;
 
     fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
@@ -207,4 +388,17 @@ impl<'a, T: Encodable + ?Sized> Encodable for &'a T {
     ) {
         (*self).encode_field_precomputed(s, field_number)
     }
+
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+        T::encoded_size_masked(*self, field_number, mask)
+    }
+
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        (*self).encode_field_masked(s, field_number, mask)
+    }
 }
@@ -5,7 +5,7 @@ use crate::{
     encoding::Encodable,
     traits::Signable,
     wire_types::*,
-    Fixed32, Fixed64, VarInt,
+    Fixed32, Fixed64, Signed, VarInt,
 };
 
 macro_rules! signable {
@@ -17,6 +17,9 @@ macro_rules! signable {
                 const BITS_M1: u32 = <$id>::BITS - 1;
                 ((this << 1) ^ (this >> BITS_M1)) as $storage
             }
+            fn zigzag_decode(n: $storage) -> Self {
+                ((n >> 1) as $id) ^ -((n & 1) as $id)
+            }
         }
     )*};
     ($($id:ident($storage: ty) = $signed:ident),*) => {$(
@@ -27,12 +30,15 @@ macro_rules! signable {
                 const BITS_M1: u32 = <$signed>::BITS - 1;
                 ((this << 1) ^ (this >> BITS_M1)) as $storage
             }
+            fn zigzag_decode(n: $storage) -> $signed {
+                ((n >> 1) as $signed) ^ -((n & 1) as $signed)
+            }
         }
     )*};
 }
 
 signable!(i32(u32), i64(u64));
-signable!(Fixed32(u32) = i32, Fixed64(u32) = i64);
+signable!(Fixed32(u32) = i32, Fixed64(u64) = i64);
 
 crate::seal! {
     for u64,
@@ -82,3 +88,80 @@ impl Decodable<'_> for bool {
         }
     }
 }
+
+impl Encodable for f32 {
+    type Wire = Fixed32Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        field_number.size() + 4
+    }
+
+    fn encode(&self, s: &mut crate::encoding::ProtobufSerializer<impl BufMut>) {
+        s.write_u32(self.to_bits())
+    }
+}
+
+impl Decodable<'_> for f32 {
+    type Wire = Fixed32Wire;
+
+    fn decode<B: bytes::Buf>(
+        deserializer: &mut crate::decoding::Deserializer<'_, B>,
+    ) -> crate::decoding::Result<Self> {
+        deserializer.read_u32().map(f32::from_bits)
+    }
+}
+
+impl Encodable for f64 {
+    type Wire = Fixed64Wire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        field_number.size() + 8
+    }
+
+    fn encode(&self, s: &mut crate::encoding::ProtobufSerializer<impl BufMut>) {
+        s.write_u64(self.to_bits())
+    }
+}
+
+impl Decodable<'_> for f64 {
+    type Wire = Fixed64Wire;
+
+    fn decode<B: bytes::Buf>(
+        deserializer: &mut crate::decoding::Deserializer<'_, B>,
+    ) -> crate::decoding::Result<Self> {
+        deserializer.read_u64().map(f64::from_bits)
+    }
+}
+
+impl Decodable<'_> for Fixed32 {
+    type Wire = Fixed32Wire;
+
+    fn decode<B: bytes::Buf>(
+        deserializer: &mut crate::decoding::Deserializer<'_, B>,
+    ) -> crate::decoding::Result<Self> {
+        deserializer.read_u32().map(Fixed32::new)
+    }
+}
+
+impl Decodable<'_> for Fixed64 {
+    type Wire = Fixed64Wire;
+
+    fn decode<B: bytes::Buf>(
+        deserializer: &mut crate::decoding::Deserializer<'_, B>,
+    ) -> crate::decoding::Result<Self> {
+        deserializer.read_u64().map(Fixed64::new)
+    }
+}
+
+impl<'de, T: Signable> Decodable<'de> for Signed<T>
+where
+    T::Storage: Decodable<'de>,
+{
+    type Wire = <T::Storage as Decodable<'de>>::Wire;
+
+    fn decode<B: bytes::Buf>(
+        deserializer: &mut crate::decoding::Deserializer<'de, B>,
+    ) -> crate::decoding::Result<Self> {
+        T::Storage::decode(deserializer).map(Signed)
+    }
+}
@@ -0,0 +1,526 @@
+//! The canonical proto3 JSON mapping.
+//!
+//! Messages implement [`ProtoJson`] with `#[derive(ProtoJson)]`, enumerations
+//! implement it through [`Enumeration`]. The messages in `google.protobuf.Any`s are
+//! found in the [`TypeRegistry`] of the options.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use serde_json::{Map as JsonMap, Number};
+
+pub use otopr_derive::ProtoJson;
+pub use serde_json::Value;
+
+use crate::registry::TypeRegistry;
+use crate::traits::Enumeration;
+use crate::{BytesStr, Fixed32, Fixed64, Map, Message, Repeated, Signed};
+
+#[derive(Clone, Debug, Default)]
+pub struct PrintOptions {
+    /// Print fields that have their default values instead of omitting them.
+    pub emit_default_values: bool,
+    /// Use the field names from the `.proto` definition instead of lowerCamelCase names.
+    pub preserve_proto_field_names: bool,
+    /// The types that `google.protobuf.Any`s can contain, see [`TypeRegistry::register_json`].
+    pub type_registry: Option<Arc<TypeRegistry>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Ignore fields that the message does not have instead of failing.
+    pub ignore_unknown_fields: bool,
+    /// The types that `google.protobuf.Any`s can contain, see [`TypeRegistry::register_json`].
+    pub type_registry: Option<Arc<TypeRegistry>>,
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    /// A JSON value had the wrong type.
+    UnexpectedType {
+        expected: &'static str,
+    },
+    /// A JSON value had the right type but could not be converted.
+    InvalidValue(String),
+    UnknownField(String),
+    /// A `google.protobuf.Any` had a type URL with no type in the `type_registry` of the options.
+    UnknownTypeUrl(String),
+    Syntax(serde_json::Error),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedType { expected } => write!(f, "expected {}", expected),
+            Self::InvalidValue(v) => write!(f, "invalid value: {}", v),
+            Self::UnknownField(name) => write!(f, "unknown field `{}`", name),
+            Self::UnknownTypeUrl(url) => write!(f, "no type registered for `{}`", url),
+            Self::Syntax(e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Syntax(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Syntax(e)
+    }
+}
+
+pub type Result<T, E = JsonError> = std::result::Result<T, E>;
+
+/// A type with a representation in the proto3 JSON mapping.
+pub trait ProtoJson: Sized {
+    fn to_json(&self, options: &PrintOptions) -> Value;
+
+    fn from_json(value: &Value, options: &ParseOptions) -> Result<Self>;
+
+    /// Whether this is the default value for its type, fields with default values are omitted when printing messages.
+    fn is_default(&self) -> bool {
+        false
+    }
+}
+
+/// A type that can be used as the key of a `map` in JSON objects.
+pub trait ProtoJsonKey: Sized {
+    fn to_json_key(&self) -> String;
+
+    fn from_json_key(key: &str) -> Result<Self>;
+}
+
+/// Prints a message as a JSON string.
+pub fn to_string<T: ProtoJson>(message: &T, options: &PrintOptions) -> String {
+    message.to_json(options).to_string()
+}
+
+/// Parses a message from a JSON string.
+pub fn from_str<T: ProtoJson>(json: &str, options: &ParseOptions) -> Result<T> {
+    T::from_json(&serde_json::from_str(json)?, options)
+}
+
+fn invalid<T>(value: impl fmt::Display) -> Result<T> {
+    Err(JsonError::InvalidValue(value.to_string()))
+}
+
+fn integer<T: std::str::FromStr + TryFrom<i64> + TryFrom<u64>>(value: &Value) -> Result<T> {
+    let n = match value {
+        Value::Number(n) => n,
+        // integers can be quoted.
+        Value::String(s) => return s.parse().or_else(|_| invalid(s)),
+        _ => {
+            return Err(JsonError::UnexpectedType {
+                expected: "integer",
+            })
+        }
+    };
+    let res = if let Some(i) = n.as_i64() {
+        T::try_from(i).ok()
+    } else if let Some(u) = n.as_u64() {
+        T::try_from(u).ok()
+    } else {
+        // exponent notation is allowed as long as the value is integral.
+        n.as_f64()
+            .filter(|f| f.fract() == 0.0 && f.abs() < 2f64.powi(63))
+            .and_then(|f| T::try_from(f as i64).ok())
+    };
+    res.map_or_else(|| invalid(n), Ok)
+}
+
+macro_rules! json_int {
+    ($($ty:ty),* ; quoted $($quoted:ty),*) => {
+        $(
+            impl ProtoJson for $ty {
+                fn to_json(&self, _: &PrintOptions) -> Value {
+                    Value::Number(Number::from(*self))
+                }
+                fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+                    integer(value)
+                }
+                fn is_default(&self) -> bool {
+                    *self == 0
+                }
+            }
+        )*
+        $(
+            impl ProtoJson for $quoted {
+                fn to_json(&self, _: &PrintOptions) -> Value {
+                    // 64 bit integers are strings, since they would lose precision as JavaScript numbers.
+                    Value::String(self.to_string())
+                }
+                fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+                    integer(value)
+                }
+                fn is_default(&self) -> bool {
+                    *self == 0
+                }
+            }
+        )*
+        $(
+            impl ProtoJsonKey for $ty {
+                fn to_json_key(&self) -> String {
+                    self.to_string()
+                }
+                fn from_json_key(key: &str) -> Result<Self> {
+                    key.parse().or_else(|_| invalid(key))
+                }
+            }
+        )*
+        $(
+            impl ProtoJsonKey for $quoted {
+                fn to_json_key(&self) -> String {
+                    self.to_string()
+                }
+                fn from_json_key(key: &str) -> Result<Self> {
+                    key.parse().or_else(|_| invalid(key))
+                }
+            }
+        )*
+    };
+}
+
+json_int!(i32, u32, u16, u8; quoted i64, u64, usize);
+
+impl ProtoJson for bool {
+    fn to_json(&self, _: &PrintOptions) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+        value.as_bool().ok_or(JsonError::UnexpectedType {
+            expected: "boolean",
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        !*self
+    }
+}
+
+impl ProtoJsonKey for bool {
+    fn to_json_key(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_json_key(key: &str) -> Result<Self> {
+        key.parse().or_else(|_| invalid(key))
+    }
+}
+
+macro_rules! json_float {
+    ($($ty:ty),*) => {$(
+        impl ProtoJson for $ty {
+            fn to_json(&self, _: &PrintOptions) -> Value {
+                if self.is_nan() {
+                    Value::String("NaN".into())
+                } else if self.is_infinite() {
+                    Value::String(if *self > 0.0 { "Infinity" } else { "-Infinity" }.into())
+                } else {
+                    Number::from_f64(*self as f64).map_or(Value::Null, Value::Number)
+                }
+            }
+            fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+                let n = match value {
+                    Value::Number(n) => n.as_f64().map_or_else(|| invalid(n), Ok)?,
+                    Value::String(s) => match s.as_str() {
+                        "NaN" => f64::NAN,
+                        "Infinity" => f64::INFINITY,
+                        "-Infinity" => f64::NEG_INFINITY,
+                        s => s.parse().or_else(|_| invalid(s))?,
+                    },
+                    _ => return Err(JsonError::UnexpectedType { expected: "number" }),
+                };
+                let res = n as $ty;
+                if n.is_finite() && res.is_infinite() {
+                    return invalid(n);
+                }
+                Ok(res)
+            }
+            fn is_default(&self) -> bool {
+                *self == 0.0 && self.is_sign_positive()
+            }
+        }
+    )*};
+}
+
+json_float!(f32, f64);
+
+macro_rules! json_wrapper {
+    ($($ty:ty => $inner:ty, $get:expr, $new:expr;)*) => {$(
+        impl ProtoJson for $ty {
+            fn to_json(&self, options: &PrintOptions) -> Value {
+                ProtoJson::to_json(&$get(self), options)
+            }
+            fn from_json(value: &Value, options: &ParseOptions) -> Result<Self> {
+                <$inner>::from_json(value, options).map($new)
+            }
+            fn is_default(&self) -> bool {
+                ProtoJson::is_default(&$get(self))
+            }
+        }
+    )*};
+}
+
+json_wrapper! {
+    Fixed32 => u32, |n: &Fixed32| n.get(), Fixed32::new;
+    Fixed64 => u64, |n: &Fixed64| n.get(), Fixed64::new;
+    Signed<i32> => i32, Signed::get, Signed::new;
+    Signed<i64> => i64, Signed::get, Signed::new;
+    Signed<Fixed32> => i32, Signed::get, Signed::new;
+    Signed<Fixed64> => i64, Signed::get, Signed::new;
+}
+
+fn string(value: &Value) -> Result<&str> {
+    value
+        .as_str()
+        .ok_or(JsonError::UnexpectedType { expected: "string" })
+}
+
+macro_rules! json_string {
+    ($($ty:ty),*) => {$(
+        impl ProtoJson for $ty {
+            fn to_json(&self, _: &PrintOptions) -> Value {
+                Value::String(<str as ToString>::to_string(self))
+            }
+            fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+                string(value).map(|s| String::from(s).into())
+            }
+            fn is_default(&self) -> bool {
+                self.is_empty()
+            }
+        }
+        impl ProtoJsonKey for $ty {
+            fn to_json_key(&self) -> String {
+                <str as ToString>::to_string(self)
+            }
+            fn from_json_key(key: &str) -> Result<Self> {
+                Ok(String::from(key).into())
+            }
+        }
+    )*};
+}
+
+json_string!(String, Box<str>, BytesStr, Cow<'_, str>);
+
+macro_rules! json_bytes {
+    ($($ty:ty),*) => {$(
+        impl ProtoJson for $ty {
+            fn to_json(&self, _: &PrintOptions) -> Value {
+                Value::String(base64::encode(self))
+            }
+            fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+                base64::decode(string(value)?).map(Into::into)
+            }
+            fn is_default(&self) -> bool {
+                self.is_empty()
+            }
+        }
+    )*};
+}
+
+json_bytes!(Vec<u8>, Box<[u8]>, Bytes, Cow<'_, [u8]>);
+
+impl<T: Enumeration> ProtoJson for T {
+    fn to_json(&self, _: &PrintOptions) -> Value {
+        match self.name() {
+            Some(name) => Value::String(name.to_owned()),
+            None => Value::Number(Number::from(self.to_i32())),
+        }
+    }
+
+    fn from_json(value: &Value, _: &ParseOptions) -> Result<Self> {
+        let res = match value {
+            Value::String(name) => T::from_name(name),
+            value => T::from_i32(integer(value)?),
+        };
+        res.map_or_else(|| invalid(value), Ok)
+    }
+
+    fn is_default(&self) -> bool {
+        self.to_i32() == 0
+    }
+}
+
+impl<T: ProtoJson> ProtoJson for Message<T> {
+    fn to_json(&self, options: &PrintOptions) -> Value {
+        self.0.to_json(options)
+    }
+
+    fn from_json(value: &Value, options: &ParseOptions) -> Result<Self> {
+        T::from_json(value, options).map(Message)
+    }
+
+    fn is_default(&self) -> bool {
+        self.0.is_default()
+    }
+}
+
+impl<C> ProtoJson for Repeated<C>
+where
+    C: IntoIterator + FromIterator<C::Item>,
+    for<'a> &'a C: IntoIterator<Item = &'a C::Item>,
+    C::Item: ProtoJson,
+{
+    fn to_json(&self, options: &PrintOptions) -> Value {
+        Value::Array((&**self).into_iter().map(|t| t.to_json(options)).collect())
+    }
+
+    fn from_json(value: &Value, options: &ParseOptions) -> Result<Self> {
+        match value {
+            Value::Array(values) => values
+                .iter()
+                .map(|v| C::Item::from_json(v, options))
+                .collect::<Result<C>>()
+                .map(Repeated::new),
+            _ => Err(JsonError::UnexpectedType { expected: "array" }),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        (&**self).into_iter().next().is_none()
+    }
+}
+
+impl<K, V, T> ProtoJson for Map<K, V, T>
+where
+    T: FromIterator<(K, V)>,
+    for<'a> &'a T: IntoIterator<Item = (&'a K, &'a V)>,
+    K: ProtoJsonKey,
+    V: ProtoJson,
+{
+    fn to_json(&self, options: &PrintOptions) -> Value {
+        Value::Object(
+            (&**self)
+                .into_iter()
+                .map(|(k, v)| (k.to_json_key(), v.to_json(options)))
+                .collect(),
+        )
+    }
+
+    fn from_json(value: &Value, options: &ParseOptions) -> Result<Self> {
+        match value {
+            Value::Object(entries) => entries
+                .iter()
+                .map(|(k, v)| Ok((K::from_json_key(k)?, V::from_json(v, options)?)))
+                .collect::<Result<T>>()
+                .map(Map::new),
+            _ => Err(JsonError::UnexpectedType { expected: "object" }),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        (&**self).into_iter().next().is_none()
+    }
+}
+
+/// Builds the JSON object of a message. Used by `#[derive(ProtoJson)]`.
+pub struct ObjectWriter<'a> {
+    options: &'a PrintOptions,
+    object: JsonMap<String, Value>,
+}
+
+impl<'a> ObjectWriter<'a> {
+    pub fn new(options: &'a PrintOptions) -> Self {
+        Self {
+            options,
+            object: JsonMap::new(),
+        }
+    }
+
+    pub fn field<T: ProtoJson>(&mut self, json_name: &str, proto_name: &str, value: &T) {
+        if self.options.emit_default_values || !value.is_default() {
+            self.field_always(json_name, proto_name, value)
+        }
+    }
+
+    /// Writes a field even if it has the default value of its type.
+    pub fn field_always<T: ProtoJson>(&mut self, json_name: &str, proto_name: &str, value: &T) {
+        let name = if self.options.preserve_proto_field_names {
+            proto_name
+        } else {
+            json_name
+        };
+        self.object
+            .insert(name.to_owned(), value.to_json(self.options));
+    }
+
+    pub fn finish(self) -> Value {
+        Value::Object(self.object)
+    }
+}
+
+/// Iterates over the fields of a JSON object for parsing a message. Used by `#[derive(ProtoJson)]`.
+///
+/// Fields set to `null` are skipped, since they mean the default value.
+pub fn object_fields(value: &Value) -> Result<impl Iterator<Item = (&str, &Value)>> {
+    match value {
+        Value::Object(object) => Ok(object
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| (k.as_str(), v))),
+        _ => Err(JsonError::UnexpectedType { expected: "object" }),
+    }
+}
+
+/// Standard base64 with padding when printing, also accepting URL-safe and unpadded input when parsing.
+mod base64 {
+    use super::{invalid, Result};
+
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    pub fn encode(bytes: &[u8]) -> String {
+        let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * i) & 0b11_1111) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn sextet(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    pub fn decode(s: &str) -> Result<Vec<u8>> {
+        let trimmed = s.trim_end_matches('=');
+        let mut out = Vec::with_capacity(trimmed.len() * 3 / 4);
+        for chunk in trimmed.as_bytes().chunks(4) {
+            if chunk.len() == 1 {
+                return invalid(s);
+            }
+            let mut n = 0;
+            for (i, &c) in chunk.iter().enumerate() {
+                n |= sextet(c).map_or_else(|| invalid(s), Ok)? << (18 - 6 * i);
+            }
+            out.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::decoding::{Decodable, DecodableMessage, Deserializer, Result};
+use crate::encoding::{Encodable, EncodableMessage, ProtobufSerializer};
+use crate::reflect::{Kind, MessageDescriptor, MessageType, ProtoType};
+use crate::wire_types::*;
+use crate::VarInt;
+
+/// A message field that is decoded the first time it is accessed.
+///
+/// Decoding only keeps the bytes of the message. They are encoded again as they are,
+/// unless the message was changed through [`get_mut`](Self::get_mut).
+///
+/// Masks are ignored: masked decoding and encoding keep or write the whole message, since
+/// its fields are not known until it is decoded.
+pub struct LazyMessage<T> {
+    /// The encoded message, `None` once the decoded value may have changed.
+    raw: Option<Bytes>,
+    value: OnceLock<T>,
+}
+
+impl<T> LazyMessage<T> {
+    /// A message that is already decoded.
+    pub fn new(value: T) -> Self {
+        Self {
+            raw: None,
+            value: OnceLock::from(value),
+        }
+    }
+
+    /// A message that is decoded from `raw` when accessed.
+    pub fn from_raw(raw: Bytes) -> Self {
+        Self {
+            raw: Some(raw),
+            value: OnceLock::new(),
+        }
+    }
+
+    /// The encoded message, if it was created from bytes and has not changed through
+    /// [`get_mut`](Self::get_mut) since.
+    pub fn raw(&self) -> Option<&Bytes> {
+        self.raw.as_ref()
+    }
+
+    pub fn is_decoded(&self) -> bool {
+        self.value.get().is_some()
+    }
+}
+
+impl<T> LazyMessage<T>
+where
+    T: for<'de> DecodableMessage<'de> + Default,
+{
+    /// The message, decoding it if it is not yet.
+    pub fn get(&self) -> Result<&T> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let mut raw = self.raw.as_deref().unwrap_or_default();
+        let value = T::decode(&mut Deserializer::new(&mut raw))?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    /// The message, decoding it if it is not yet. It is encoded from the value afterwards.
+    pub fn get_mut(&mut self) -> Result<&mut T> {
+        self.get()?;
+        self.raw = None;
+        Ok(self.value.get_mut().expect("decoded above"))
+    }
+
+    pub fn into_inner(self) -> Result<T> {
+        self.get()?;
+        Ok(self.value.into_inner().expect("decoded above"))
+    }
+}
+
+impl<T> Default for LazyMessage<T> {
+    /// The empty message, which decodes to the default value.
+    fn default() -> Self {
+        Self::from_raw(Bytes::new())
+    }
+}
+
+impl<T: Clone> Clone for LazyMessage<T> {
+    fn clone(&self) -> Self {
+        Self {
+            raw: self.raw.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LazyMessage<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.value.get(), &self.raw) {
+            (Some(value), _) => f.debug_tuple("LazyMessage").field(value).finish(),
+            (None, raw) => f.debug_struct("LazyMessage").field("raw", raw).finish(),
+        }
+    }
+}
+
+impl<T: EncodableMessage> LazyMessage<T> {
+    fn message_size(&self) -> usize {
+        match (&self.raw, self.value.get()) {
+            (Some(raw), _) => raw.len(),
+            (None, Some(value)) => value.encoded_size(),
+            (None, None) => unreachable!("lazy messages have raw bytes until they are decoded"),
+        }
+    }
+}
+
+impl<T: EncodableMessage> Encodable for LazyMessage<T> {
+    type Wire = LengthDelimitedWire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        let calc_size = self.message_size();
+        field_number.size() + calc_size.size() + calc_size
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        s.write_varint(self.message_size());
+        match (&self.raw, self.value.get()) {
+            (Some(raw), _) => s.write_bytes(raw),
+            (None, Some(value)) => value.encode(s),
+            (None, None) => unreachable!("lazy messages have raw bytes until they are decoded"),
+        }
+    }
+}
+
+impl<'de, T> Decodable<'de> for LazyMessage<T>
+where
+    T: for<'a> DecodableMessage<'a> + Decodable<'de> + EncodableMessage + Default,
+{
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(deserializer: &mut Deserializer<'de, B>) -> Result<Self> {
+        let len = deserializer.read_varint()?;
+        Ok(Self::from_raw(deserializer.read_bytes_shared(len)?))
+    }
+
+    /// Messages that appear more than once are merged, which for the encoded
+    /// bytes is appending them. Once this one has changed, the fields of `other` are
+    /// decoded into it.
+    ///
+    /// If the fields of `other` fail to decode, this one is encoded again with the bytes of
+    /// `other` appended, so that accessing the message reports the error.
+    fn merge(&mut self, other: Self) {
+        if let (Some(raw), Some(other)) = (&self.raw, &other.raw) {
+            let mut merged = Vec::with_capacity(raw.len() + other.len());
+            merged.extend_from_slice(raw);
+            merged.extend_from_slice(other);
+            *self = Self::from_raw(merged.into());
+            return;
+        }
+        let value = match self.get_mut() {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        match (&other.raw, other.value.into_inner()) {
+            (Some(raw), _) => {
+                let mut merged =
+                    Vec::with_capacity(EncodableMessage::encoded_size(value) + raw.len());
+                EncodableMessage::encode(value, &mut ProtobufSerializer::new(&mut merged));
+                if merge_fields(value, raw).is_err() {
+                    merged.extend_from_slice(raw);
+                    *self = Self::from_raw(merged.into());
+                }
+            }
+            (None, Some(other)) => Decodable::merge(value, other),
+            (None, None) => unreachable!("lazy messages have raw bytes until they are decoded"),
+        }
+    }
+}
+
+/// Decodes the fields in `raw` into `value`, which merges them like protobuf does.
+fn merge_fields<T: for<'de> DecodableMessage<'de>>(value: &mut T, mut raw: &[u8]) -> Result<()> {
+    let d = &mut Deserializer::new(&mut raw);
+    while d.has_remaining() {
+        match T::Tag::read_field_tag(d) {
+            Ok(tag) => value.decode_field(d, tag)?,
+            Err(Ok(wire)) => wire.skip(d)?,
+            Err(Err(e)) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl<T: MessageDescriptor> ProtoType for LazyMessage<T> {
+    const KIND: Kind = Kind::Message(MessageType::of::<T>());
+}
@@ -12,7 +12,7 @@ mod macros {
     macro_rules! seal {
         ($(for$(<$($id:ident$(: $bound:path)?),+ $(,)?>)? $ty:ty),+ $(,)?) => {
             $(
-                impl$(<$($id$(: $bound)?),*>)? crate::traits::private::Sealed for $ty {}
+                impl$(<$($id$(: $bound)?),*>)? $crate::traits::private::Sealed for $ty {}
             )*
         };
     }
@@ -21,6 +21,7 @@ mod macros {
 pub mod prelude;
 
 pub mod traits;
+pub use traits::{Enumeration, Name};
 
 pub mod decoding;
 pub mod encoding;
@@ -34,9 +35,128 @@ pub use varint::VarInt;
 mod map;
 pub use map::Map;
 
+mod lazy;
+pub use lazy::LazyMessage;
+
+mod bytes_str;
+pub use bytes_str::BytesStr;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+pub mod text_format;
+
+pub mod well_known;
+
+pub mod registry;
+
+pub mod descriptor;
+
+pub mod dynamic;
+
+pub mod reflect;
+
+pub mod mask;
+
 #[cfg(test)]
 pub mod tests;
 
+/// The message derives reject field numbers that are zero, larger than 536870911, used twice,
+/// reserved for the protobuf implementation or reserved with `#[otopr(reserved(..))]`.
+///
+/// ```
+/// #[derive(otopr::EncodableMessage, otopr::DecodableMessage, Default)]
+/// #[otopr(reserved(2, 4..=6))]
+/// struct Item {
+///     #[otopr(1)]
+///     a: u32,
+///     #[otopr(3)]
+///     b: u32,
+///     #[otopr(7)]
+///     c: u32,
+/// }
+///
+/// #[derive(otopr::EncodableMessage)]
+/// struct Big {
+///     #[otopr(18999)]
+///     a: u32,
+///     #[otopr(20000)]
+///     b: u32,
+///     #[otopr(536870911)]
+///     c: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// struct Item {
+///     #[otopr(0)]
+///     a: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// struct Item {
+///     #[otopr(1)]
+///     a: u32,
+///     #[otopr(1)]
+///     b: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// struct Item {
+///     #[otopr(536870912)]
+///     a: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// struct Item {
+///     #[otopr(19000)]
+///     a: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// struct Item {
+///     #[otopr(19999)]
+///     a: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::EncodableMessage)]
+/// #[otopr(reserved(2))]
+/// struct Item {
+///     #[otopr(2)]
+///     a: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(otopr::DecodableMessage, Default)]
+/// #[otopr(reserved(4..=6))]
+/// struct Item {
+///     #[otopr(1)]
+///     a: u32,
+///     #[otopr(5)]
+///     b: u32,
+/// }
+/// ```
+#[cfg(doctest)]
+pub struct FieldNumberChecks;
+
 mod impls;
 pub mod wire_types;
 
@@ -56,16 +176,41 @@ impl<T: traits::Signable> Signed<T> {
     pub fn new(t: T::From) -> Self {
         Self(T::zigzag_encode(t))
     }
+
+    /// Returns the zigzag-decoded value.
+    pub fn get(&self) -> T::From {
+        T::zigzag_decode(self.0)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
 #[repr(transparent)]
 pub struct Fixed32(u32);
 
+impl Fixed32 {
+    pub const fn new(n: u32) -> Self {
+        Self(n)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
 #[repr(transparent)]
 pub struct Fixed64(u64);
 
+impl Fixed64 {
+    pub const fn new(n: u64) -> Self {
+        Self(n)
+    }
+
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
 #[repr(transparent)]
 pub struct Message<T>(T);
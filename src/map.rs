@@ -79,8 +79,8 @@ where
         for (key, value) in &self.0 {
             s.write_bytes(field_number);
             s.write_varint(key.encoded_size(1) + value.encoded_size(2));
-            key.encode_field_precomputed(s, &[0b00001_000 | K::Wire::BITS]);
-            value.encode_field_precomputed(s, &[0b00010_000 | V::Wire::BITS]);
+            key.encode_field_precomputed(s, &[0b0000_1000 | K::Wire::BITS]);
+            value.encode_field_precomputed(s, &[0b0001_0000 | V::Wire::BITS]);
         }
     }
 
@@ -99,8 +99,8 @@ where
     type Wire = LengthDelimitedWire;
 
     fn merge_from<B: Buf>(&mut self, d: &mut Deserializer<'de, B>) -> crate::decoding::Result<()> {
-        let k_fn = 0b00001_000 | K::Wire::BITS;
-        let v_fn = 0b00010_000 | V::Wire::BITS;
+        let k_fn = 0b0000_1000 | K::Wire::BITS;
+        let v_fn = 0b0001_0000 | V::Wire::BITS;
 
         let msg_len = d.read_varint()?;
         let lmt = d.set_limit(msg_len);
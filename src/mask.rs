@@ -0,0 +1,130 @@
+//! Encoding and decoding only some fields of messages.
+//!
+//! Derived messages encode and decode the fields in a [`Mask`] and skip the others,
+//! also in submessages for fields with nested masks. Other messages ignore masks.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::reflect::{Cardinality, FieldDescriptor, Kind, MessageDescriptor};
+use crate::well_known::FieldMask;
+
+/// A set of fields of a message, with the fields to keep of submessages for some of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mask {
+    /// The fields by number, with `None` keeping the whole field.
+    fields: BTreeMap<u32, Option<Mask>>,
+}
+
+impl Mask {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A mask keeping the whole fields with these numbers.
+    pub fn from_numbers<I: IntoIterator<Item = u32>>(numbers: I) -> Self {
+        numbers.into_iter().fold(Self::new(), Self::field)
+    }
+
+    /// Keeps the whole field with this number.
+    pub fn field(mut self, number: u32) -> Self {
+        self.insert(number, None);
+        self
+    }
+
+    /// Keeps the fields in `mask` of the submessage field with this number.
+    pub fn submessage(mut self, number: u32, mask: Mask) -> Self {
+        self.insert(number, Some(mask));
+        self
+    }
+
+    fn insert(&mut self, number: u32, mask: Option<Mask>) {
+        match (self.fields.get_mut(&number), mask) {
+            // the whole field is already kept.
+            (Some(None), _) => {}
+            (Some(Some(existing)), Some(mask)) => {
+                for (number, nested) in mask.fields {
+                    existing.insert(number, nested);
+                }
+            }
+            (_, mask) => {
+                self.fields.insert(number, mask);
+            }
+        }
+    }
+
+    pub fn contains(&self, number: u32) -> bool {
+        self.fields.contains_key(&number)
+    }
+
+    /// The mask of a submessage field, or `None` if the field is kept whole or not at all.
+    pub fn submask(&self, number: u32) -> Option<&Mask> {
+        self.fields.get(&number).and_then(Option::as_ref)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// A mask from paths of field names like `customer.address.city`.
+    pub fn from_paths<M, I>(paths: I) -> Result<Self, UnknownPath>
+    where
+        M: MessageDescriptor,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut mask = Self::new();
+        for path in paths {
+            let path = path.as_ref();
+            let numbers = resolve(M::fields(), path).ok_or_else(|| UnknownPath(path.to_owned()))?;
+            let (last, parents) = numbers.split_last().expect("paths have at least one field");
+            let nested = parents
+                .iter()
+                .rev()
+                .fold(Self::new().field(*last), |nested, &number| {
+                    Self::new().submessage(number, nested)
+                });
+            for (number, nested) in nested.fields {
+                mask.insert(number, nested);
+            }
+        }
+        Ok(mask)
+    }
+
+    /// A mask from the paths of a `google.protobuf.FieldMask`.
+    pub fn from_field_mask<M: MessageDescriptor>(mask: &FieldMask) -> Result<Self, UnknownPath> {
+        Self::from_paths::<M, _>(mask.paths.iter())
+    }
+}
+
+/// The field numbers along `path`, if it names a field.
+fn resolve(mut fields: &'static [FieldDescriptor], path: &str) -> Option<Vec<u32>> {
+    let mut numbers = Vec::new();
+    let mut names = path.split('.').peekable();
+    while let Some(name) = names.next() {
+        let field = fields.iter().find(|f| f.name == name)?;
+        numbers.push(field.number);
+        if names.peek().is_some() {
+            // only singular and repeated messages have subfields, not maps.
+            fields = match field.kind {
+                Kind::Message(message) if !matches!(field.cardinality, Cardinality::Map { .. }) => {
+                    (message.fields)()
+                }
+                _ => return None,
+            };
+        }
+    }
+    Some(numbers)
+}
+
+/// A path that does not name a field of the message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownPath(pub String);
+
+impl fmt::Display for UnknownPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no field at path `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPath {}
@@ -0,0 +1,213 @@
+//! Static descriptions of messages, for code that works with any message at runtime.
+//!
+//! `#[derive(EncodableMessage)]` implements [`MessageDescriptor`] and [`ProtoType`] for messages,
+//! `#[derive(Enumeration)]` implements [`ProtoType`] for enumerations.
+//! [`ProtoFile`] generates `.proto` files from them.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Deref;
+
+use bytes::Bytes;
+
+use crate::repeated::HasItem;
+use crate::wire_types::WireTypes;
+use crate::{BytesStr, Fixed32, Fixed64, Map, Message, Repeated, Signed};
+
+mod proto_file;
+pub use proto_file::{EnumValueCollision, ProtoFile};
+
+/// A message with a static table of its fields.
+///
+/// Implemented by `#[derive(EncodableMessage)]`, which also implements [`ProtoType`].
+pub trait MessageDescriptor {
+    /// The fully qualified name from `#[otopr(name = "...")]`, or the name of the Rust type.
+    const NAME: &'static str;
+
+    /// The fields in the order they are declared.
+    const FIELDS: &'static [FieldDescriptor];
+
+    fn fields() -> &'static [FieldDescriptor] {
+        Self::FIELDS
+    }
+
+    fn field(number: u32) -> Option<&'static FieldDescriptor> {
+        Self::FIELDS.iter().find(|f| f.number == number)
+    }
+
+    fn field_by_name(name: &str) -> Option<&'static FieldDescriptor> {
+        Self::FIELDS.iter().find(|f| f.name == name)
+    }
+}
+
+/// A type that can be the type of a field.
+pub trait ProtoType {
+    const KIND: Kind;
+
+    const CARDINALITY: Cardinality = Cardinality::Singular;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldDescriptor {
+    /// The name of the struct field, or its index for tuple structs.
+    pub name: &'static str,
+    pub number: u32,
+    /// The wire type of a single value, which is [`WireTypes::LengthDelimitedWire`] for maps.
+    pub wire_type: WireTypes,
+    pub cardinality: Cardinality,
+    /// The type of a single value, which is the value type for maps.
+    pub kind: Kind,
+    /// Whether a repeated scalar is encoded packed, with `#[otopr(packed)]`.
+    pub packed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cardinality {
+    /// A field without presence that is always encoded.
+    Singular,
+    /// An `Option`, which is not encoded if it is `None`.
+    Optional,
+    Repeated,
+    /// A `map` with keys of this kind.
+    Map {
+        key: Kind,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Double,
+    Float,
+    Int64,
+    UInt64,
+    Int32,
+    Fixed64,
+    Fixed32,
+    Bool,
+    String,
+    Bytes,
+    UInt32,
+    SFixed32,
+    SFixed64,
+    SInt32,
+    SInt64,
+    Message(MessageType),
+    Enum(EnumType),
+    /// A field with `#[otopr(encode_via(..))]`, whose type is not known.
+    Custom,
+}
+
+/// A reference to a message type, which can refer to itself through its fields.
+#[derive(Clone, Copy)]
+pub struct MessageType {
+    pub name: &'static str,
+    pub fields: fn() -> &'static [FieldDescriptor],
+}
+
+impl MessageType {
+    pub const fn of<T: MessageDescriptor>() -> Self {
+        Self {
+            name: T::NAME,
+            fields: T::fields,
+        }
+    }
+}
+
+/// Message types are compared by name.
+impl PartialEq for MessageType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MessageType").field(&self.name).finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnumType {
+    pub name: &'static str,
+    /// The names and numbers of the variants.
+    pub values: &'static [(&'static str, i32)],
+}
+
+macro_rules! proto_type {
+    ($($kind:ident: $($ty:ty),*;)*) => {$($(
+        impl ProtoType for $ty {
+            const KIND: Kind = Kind::$kind;
+        }
+    )*)*};
+}
+
+proto_type! {
+    Double: f64;
+    Float: f32;
+    Int64: i64;
+    UInt64: u64, usize;
+    Int32: i32;
+    UInt32: u32, u16, u8;
+    Fixed64: Fixed64;
+    Fixed32: Fixed32;
+    Bool: bool;
+    String: str, String, BytesStr;
+    Bytes: [u8], Vec<u8>, Bytes;
+    SInt32: Signed<i32>;
+    SInt64: Signed<i64>;
+    SFixed32: Signed<Fixed32>;
+    SFixed64: Signed<Fixed64>;
+}
+
+impl<T: ProtoType + ?Sized> ProtoType for &T {
+    const KIND: Kind = T::KIND;
+    const CARDINALITY: Cardinality = T::CARDINALITY;
+}
+
+impl<T: ProtoType + ToOwned + ?Sized> ProtoType for Cow<'_, T> {
+    const KIND: Kind = T::KIND;
+    const CARDINALITY: Cardinality = T::CARDINALITY;
+}
+
+impl<T: ProtoType> ProtoType for Option<T> {
+    const KIND: Kind = T::KIND;
+    const CARDINALITY: Cardinality = Cardinality::Optional;
+}
+
+impl<T: MessageDescriptor> ProtoType for Message<T> {
+    const KIND: Kind = Kind::Message(MessageType::of::<T>());
+}
+
+impl<C> ProtoType for Repeated<C>
+where
+    C: Deref,
+    C::Target: HasItem,
+    <C::Target as HasItem>::Item: ProtoType,
+{
+    const KIND: Kind = <<C::Target as HasItem>::Item as ProtoType>::KIND;
+    const CARDINALITY: Cardinality = Cardinality::Repeated;
+}
+
+impl<K: ProtoType, V: ProtoType, T> ProtoType for Map<K, V, T> {
+    const KIND: Kind = V::KIND;
+    const CARDINALITY: Cardinality = Cardinality::Map { key: K::KIND };
+}
+
+/// Converts a snake_case name to camelCase like `protoc` does, starting with an uppercase
+/// letter if `upper_first`.
+pub(crate) fn camel_case(name: &str, upper_first: bool) -> String {
+    let mut upper = upper_first;
+    name.chars()
+        .filter_map(|c| match c {
+            '_' => {
+                upper = true;
+                None
+            }
+            c if upper => {
+                upper = false;
+                Some(c.to_ascii_uppercase())
+            }
+            c => Some(c),
+        })
+        .collect()
+}
@@ -0,0 +1,474 @@
+use std::fmt::{self, Write};
+
+use super::{
+    camel_case, Cardinality, EnumType, FieldDescriptor, Kind, MessageDescriptor, MessageType,
+};
+use crate::descriptor::{
+    field_label, field_type, DescriptorProto, EnumDescriptorProto, EnumOptions,
+    EnumValueDescriptorProto, FieldDescriptorProto, FieldOptions, FileDescriptorProto,
+    MessageOptions, OneofDescriptorProto,
+};
+use crate::wire_types::WireTypes;
+use crate::Repeated;
+
+/// A `proto3` file generated from derived messages, to share Rust-first schemas with other languages.
+///
+/// The file contains the added messages and every message and enumeration they use that is in the
+/// same package, where a message is in the package before the last `.` of its name and types
+/// without a `.` are in the package of the file. Messages of other packages are referred to by
+/// their full name and the well-known types are imported, other files need [`ProtoFile::import`].
+///
+/// [`ProtoFile::to_proto_string`] prints the `.proto` source and [`ProtoFile::to_descriptor`]
+/// builds the `FileDescriptorProto` that `protoc` would produce from it. Enum values are scoped
+/// to the package, so both fail if two enumerations have a value of the same name, see
+/// [`ProtoFile::check`]. `Display` prints the source without checking it.
+///
+/// ```
+/// # use otopr::reflect::ProtoFile;
+/// #[derive(otopr::EncodableMessage)]
+/// #[otopr(name = "shop.Item")]
+/// struct Item {
+///     #[otopr(1)]
+///     name: String,
+/// }
+///
+/// let file = ProtoFile::new("shop.proto").package("shop").message::<Item>();
+/// assert_eq!(
+///     file.to_string(),
+///     "syntax = \"proto3\";\n\npackage shop;\n\nmessage Item {\n  string name = 1;\n}\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProtoFile {
+    name: String,
+    package: Option<String>,
+    imports: Vec<String>,
+    messages: Vec<MessageType>,
+}
+
+/// The types defined in a file, in the order they were found.
+struct Types {
+    messages: Vec<MessageType>,
+    enums: Vec<EnumType>,
+    imports: Vec<String>,
+}
+
+impl ProtoFile {
+    /// A file with this path, like `shop/item.proto`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            package: None,
+            imports: Vec::new(),
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Imports a file that defines messages of other packages.
+    pub fn import(mut self, path: impl Into<String>) -> Self {
+        self.imports.push(path.into());
+        self
+    }
+
+    /// Adds a message and the types it uses.
+    pub fn message<T: MessageDescriptor>(mut self) -> Self {
+        self.messages.push(MessageType::of::<T>());
+        self
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        match name.rfind('.') {
+            Some(i) => Some(&name[..i]) == self.package.as_deref(),
+            None => true,
+        }
+    }
+
+    fn full_name(&self, name: &str) -> String {
+        match &self.package {
+            Some(package) if !name.contains('.') => format!("{}.{}", package, name),
+            _ => name.to_owned(),
+        }
+    }
+
+    /// The name used in the `.proto` source.
+    fn type_ref(&self, name: &'static str) -> String {
+        if self.is_local(name) {
+            local_name(name).to_owned()
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn types(&self) -> Types {
+        let mut types = Types {
+            messages: Vec::new(),
+            enums: Vec::new(),
+            imports: self.imports.clone(),
+        };
+        for &message in &self.messages {
+            self.visit(message, &mut types);
+        }
+        types
+    }
+
+    fn visit(&self, message: MessageType, types: &mut Types) {
+        if !self.is_local(message.name) {
+            if let Some(import) = well_known_import(message.name) {
+                if !types.imports.iter().any(|i| i == import) {
+                    types.imports.push(import.to_owned());
+                }
+            }
+            return;
+        }
+        if types.messages.contains(&message) {
+            return;
+        }
+        types.messages.push(message);
+        for field in (message.fields)() {
+            let key = match field.cardinality {
+                Cardinality::Map { key } => Some(key),
+                _ => None,
+            };
+            for kind in key.into_iter().chain(Some(field.kind)) {
+                match kind {
+                    Kind::Message(m) => self.visit(m, types),
+                    Kind::Enum(e) if !types.enums.contains(&e) => types.enums.push(e),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Checks that no two enumerations of the file have a value of the same name, which
+    /// `protoc` rejects. Values without `#[otopr(name = "..")]` are named after the variant.
+    pub fn check(&self) -> Result<(), EnumValueCollision> {
+        check_enum_values(&self.types().enums)
+    }
+
+    /// The `.proto` source of this file, if it passes [`ProtoFile::check`].
+    pub fn to_proto_string(&self) -> Result<String, EnumValueCollision> {
+        self.check()?;
+        Ok(self.to_string())
+    }
+
+    /// The `FileDescriptorProto` of this file.
+    pub fn to_descriptor(&self) -> Result<FileDescriptorProto, EnumValueCollision> {
+        let types = self.types();
+        check_enum_values(&types.enums)?;
+        Ok(FileDescriptorProto {
+            name: Some(self.name.clone()),
+            package: self.package.clone(),
+            dependency: Repeated::new(types.imports),
+            message_type: Repeated::new(
+                types
+                    .messages
+                    .iter()
+                    .map(|&m| self.message_descriptor(m))
+                    .collect(),
+            ),
+            enum_type: Repeated::new(types.enums.iter().map(enum_descriptor).collect()),
+            syntax: Some("proto3".to_owned()),
+        })
+    }
+
+    fn message_descriptor(&self, message: MessageType) -> DescriptorProto {
+        let full_name = self.full_name(message.name);
+        let mut descriptor = DescriptorProto {
+            name: Some(local_name(message.name).to_owned()),
+            ..DescriptorProto::default()
+        };
+        for field in (message.fields)() {
+            let mut f =
+                self.field_descriptor(field.name, field.number, field.kind, field.wire_type);
+            match field.cardinality {
+                Cardinality::Singular => {}
+                // messages always have presence.
+                Cardinality::Optional if matches!(field.kind, Kind::Message(_)) => {}
+                Cardinality::Optional => {
+                    f.oneof_index = Some(descriptor.oneof_decl.len() as i32);
+                    f.proto3_optional = Some(true);
+                    descriptor.oneof_decl.push(OneofDescriptorProto {
+                        name: Some(format!("_{}", field_name(field))),
+                    });
+                }
+                Cardinality::Repeated => {
+                    f.label = Some(field_label::REPEATED);
+                    if is_packable(field.kind) && !field.packed {
+                        f.options = Some(FieldOptions {
+                            packed: Some(false),
+                        });
+                    }
+                }
+                Cardinality::Map { key } => {
+                    let entry = format!("{}Entry", camel_case(&field_name(field), true));
+                    f.label = Some(field_label::REPEATED);
+                    f.r#type = Some(field_type::MESSAGE);
+                    f.type_name = Some(format!(".{}.{}", full_name, entry));
+                    descriptor.nested_type.push(DescriptorProto {
+                        name: Some(entry),
+                        field: Repeated::new(vec![
+                            self.field_descriptor("key", 1, key, WireTypes::VarIntWire),
+                            self.field_descriptor("value", 2, field.kind, field.wire_type),
+                        ]),
+                        options: Some(MessageOptions {
+                            map_entry: Some(true),
+                        }),
+                        ..DescriptorProto::default()
+                    });
+                }
+            }
+            descriptor.field.push(f);
+        }
+        descriptor
+    }
+
+    fn field_descriptor(
+        &self,
+        name: &str,
+        number: u32,
+        kind: Kind,
+        wire_type: WireTypes,
+    ) -> FieldDescriptorProto {
+        let (r#type, type_name) = match kind {
+            Kind::Message(m) => (field_type::MESSAGE, Some(m.name)),
+            Kind::Enum(e) => (field_type::ENUM, Some(e.name)),
+            _ => (scalar(kind, wire_type).1, None),
+        };
+        FieldDescriptorProto {
+            name: Some(field_name_str(name)),
+            number: Some(number as i32),
+            label: Some(field_label::OPTIONAL),
+            r#type: Some(r#type),
+            type_name: type_name.map(|name| format!(".{}", self.full_name(name))),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    fn write_field(&self, out: &mut String, field: &FieldDescriptor) -> fmt::Result {
+        let ty = |kind| match kind {
+            Kind::Message(m) => self.type_ref(m.name),
+            Kind::Enum(e) => self.type_ref(e.name),
+            _ => scalar(kind, field.wire_type).0.to_owned(),
+        };
+        let name = field_name(field);
+        match field.cardinality {
+            Cardinality::Singular => {
+                write!(out, "  {} {} = {}", ty(field.kind), name, field.number)?
+            }
+            Cardinality::Optional if matches!(field.kind, Kind::Message(_)) => {
+                write!(out, "  {} {} = {}", ty(field.kind), name, field.number)?
+            }
+            Cardinality::Optional => write!(
+                out,
+                "  optional {} {} = {}",
+                ty(field.kind),
+                name,
+                field.number
+            )?,
+            Cardinality::Repeated => {
+                write!(
+                    out,
+                    "  repeated {} {} = {}",
+                    ty(field.kind),
+                    name,
+                    field.number
+                )?;
+                if is_packable(field.kind) && !field.packed {
+                    out.push_str(" [packed = false]");
+                }
+            }
+            Cardinality::Map { key } => write!(
+                out,
+                "  map<{}, {}> {} = {}",
+                ty(key),
+                ty(field.kind),
+                name,
+                field.number
+            )?,
+        }
+        out.push_str(";\n");
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProtoFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types = self.types();
+        let mut out = String::from("syntax = \"proto3\";\n");
+        if let Some(package) = &self.package {
+            write!(out, "\npackage {};\n", package)?;
+        }
+        if !types.imports.is_empty() {
+            out.push('\n');
+            for import in &types.imports {
+                writeln!(out, "import \"{}\";", import)?;
+            }
+        }
+        for message in &types.messages {
+            writeln!(out, "\nmessage {} {{", local_name(message.name))?;
+            for field in (message.fields)() {
+                self.write_field(&mut out, field)?;
+            }
+            out.push_str("}\n");
+        }
+        for e in &types.enums {
+            writeln!(out, "\nenum {} {{", local_name(e.name))?;
+            if has_aliases(e) {
+                out.push_str("  option allow_alias = true;\n");
+            }
+            for (name, number) in enum_values(e) {
+                writeln!(out, "  {} = {};", name, number)?;
+            }
+            out.push_str("}\n");
+        }
+        f.write_str(&out)
+    }
+}
+
+/// Two enumerations of a file with a value of the same name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnumValueCollision {
+    pub value: &'static str,
+    pub enums: [&'static str; 2],
+}
+
+impl fmt::Display for EnumValueCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "enum value `{}` is defined by both `{}` and `{}`",
+            self.value, self.enums[0], self.enums[1]
+        )
+    }
+}
+
+impl std::error::Error for EnumValueCollision {}
+
+fn check_enum_values(enums: &[EnumType]) -> Result<(), EnumValueCollision> {
+    for (i, e) in enums.iter().enumerate() {
+        for other in &enums[..i] {
+            if let Some(&(value, _)) = e
+                .values
+                .iter()
+                .find(|(name, _)| other.values.iter().any(|(n, _)| n == name))
+            {
+                return Err(EnumValueCollision {
+                    value,
+                    enums: [other.name, e.name],
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn enum_descriptor(e: &EnumType) -> EnumDescriptorProto {
+    EnumDescriptorProto {
+        name: Some(local_name(e.name).to_owned()),
+        value: Repeated::new(
+            enum_values(e)
+                .map(|&(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_owned()),
+                    number: Some(number),
+                })
+                .collect(),
+        ),
+        options: if has_aliases(e) {
+            Some(EnumOptions {
+                allow_alias: Some(true),
+            })
+        } else {
+            None
+        },
+    }
+}
+
+/// The values of an enum with the zero ones first, since proto3 enums must start with zero.
+fn enum_values(e: &EnumType) -> impl Iterator<Item = &(&'static str, i32)> {
+    let zero = e.values.iter().filter(|(_, n)| *n == 0);
+    zero.chain(e.values.iter().filter(|(_, n)| *n != 0))
+}
+
+fn has_aliases(e: &EnumType) -> bool {
+    e.values
+        .iter()
+        .enumerate()
+        .any(|(i, (_, number))| e.values[..i].iter().any(|(_, n)| n == number))
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+fn field_name(field: &FieldDescriptor) -> String {
+    field_name_str(field.name)
+}
+
+/// Fields of tuple structs are named by their index, which is not an identifier.
+fn field_name_str(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("field_{}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Scalars and enumerations, which are packed by default in `proto3` but not by [`Repeated`]
+/// or fields without `#[otopr(packed)]`.
+fn is_packable(kind: Kind) -> bool {
+    !matches!(
+        kind,
+        Kind::String | Kind::Bytes | Kind::Message(_) | Kind::Custom
+    )
+}
+
+/// The name and [`field_type`] of a scalar. Fields with a custom encoding get the type of their wire type.
+fn scalar(kind: Kind, wire_type: WireTypes) -> (&'static str, i32) {
+    match kind {
+        Kind::Double => ("double", field_type::DOUBLE),
+        Kind::Float => ("float", field_type::FLOAT),
+        Kind::Int64 => ("int64", field_type::INT64),
+        Kind::UInt64 => ("uint64", field_type::UINT64),
+        Kind::Int32 => ("int32", field_type::INT32),
+        Kind::Fixed64 => ("fixed64", field_type::FIXED64),
+        Kind::Fixed32 => ("fixed32", field_type::FIXED32),
+        Kind::Bool => ("bool", field_type::BOOL),
+        Kind::String => ("string", field_type::STRING),
+        Kind::Bytes => ("bytes", field_type::BYTES),
+        Kind::UInt32 => ("uint32", field_type::UINT32),
+        Kind::SFixed32 => ("sfixed32", field_type::SFIXED32),
+        Kind::SFixed64 => ("sfixed64", field_type::SFIXED64),
+        Kind::SInt32 => ("sint32", field_type::SINT32),
+        Kind::SInt64 => ("sint64", field_type::SINT64),
+        Kind::Message(_) | Kind::Enum(_) | Kind::Custom => match wire_type {
+            WireTypes::VarIntWire => ("uint64", field_type::UINT64),
+            WireTypes::Fixed64Wire => ("fixed64", field_type::FIXED64),
+            WireTypes::Fixed32Wire => ("fixed32", field_type::FIXED32),
+            WireTypes::LengthDelimitedWire
+            | WireTypes::StartGroupWire
+            | WireTypes::EndGroupWire => ("bytes", field_type::BYTES),
+        },
+    }
+}
+
+/// The file defining a well-known type.
+fn well_known_import(name: &str) -> Option<&'static str> {
+    Some(match name.strip_prefix("google.protobuf.")? {
+        "Any" => "google/protobuf/any.proto",
+        "Duration" => "google/protobuf/duration.proto",
+        "Empty" => "google/protobuf/empty.proto",
+        "FieldMask" => "google/protobuf/field_mask.proto",
+        "Struct" | "Value" | "ListValue" | "NullValue" => "google/protobuf/struct.proto",
+        "Timestamp" => "google/protobuf/timestamp.proto",
+        "DoubleValue" | "FloatValue" | "Int64Value" | "UInt64Value" | "Int32Value"
+        | "UInt32Value" | "BoolValue" | "StringValue" | "BytesValue" => {
+            "google/protobuf/wrappers.proto"
+        }
+        _ => return None,
+    })
+}
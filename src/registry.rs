@@ -0,0 +1,185 @@
+//! Decoding `google.protobuf.Any` into messages whose type is only known at runtime.
+
+use std::any::Any as StdAny;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+
+use crate::decoding::{DecodableMessage, DecodingError, Result};
+use crate::encoding::EncodableMessage;
+#[cfg(feature = "json")]
+use crate::json::{self, ParseOptions, PrintOptions, ProtoJson, Value as Json};
+use crate::well_known::{type_name, Any};
+use crate::Name;
+
+/// A message decoded by a [`TypeRegistry`], which can be downcast to its concrete type.
+pub trait AnyMessage: StdAny + Debug + Send + Sync {
+    /// The fully qualified name of the message type.
+    fn type_name(&self) -> &'static str;
+
+    /// Encodes this message into an `Any` again.
+    fn to_any(&self) -> Any;
+
+    fn as_any(&self) -> &dyn StdAny;
+
+    fn into_any(self: Box<Self>) -> Box<dyn StdAny>;
+}
+
+impl<T> AnyMessage for T
+where
+    T: Name + EncodableMessage + Debug + Send + Sync + 'static,
+{
+    fn type_name(&self) -> &'static str {
+        T::NAME
+    }
+
+    fn to_any(&self) -> Any {
+        Any::pack(self)
+    }
+
+    fn as_any(&self) -> &dyn StdAny {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn StdAny> {
+        self
+    }
+}
+
+impl dyn AnyMessage {
+    pub fn is<T: AnyMessage>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    pub fn downcast_ref<T: AnyMessage>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    /// Returns the concrete message, or this box if it has a different type.
+    pub fn downcast<T: AnyMessage>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
+        if self.is::<T>() {
+            Ok(self.into_any().downcast().unwrap())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+type DecodeFn = fn(&Any) -> Result<Box<dyn AnyMessage>>;
+
+fn decode<T>(any: &Any) -> Result<Box<dyn AnyMessage>>
+where
+    T: AnyMessage + for<'de> DecodableMessage<'de> + Default,
+{
+    any.decode::<T>()
+        .map(|t| Box::new(t) as Box<dyn AnyMessage>)
+}
+
+/// Converts the message in an `Any` to and from its JSON.
+#[cfg(feature = "json")]
+#[derive(Clone, Copy)]
+struct JsonFns {
+    /// `None` if the message fails to decode.
+    to_json: fn(&Any, &PrintOptions) -> Option<Json>,
+    from_json: fn(&str, &Json, &ParseOptions) -> json::Result<Any>,
+}
+
+#[cfg(feature = "json")]
+fn to_json<T>(any: &Any, options: &PrintOptions) -> Option<Json>
+where
+    T: ProtoJson + for<'de> DecodableMessage<'de> + Default,
+{
+    any.decode::<T>().ok().map(|t| t.to_json(options))
+}
+
+#[cfg(feature = "json")]
+fn from_json<T>(type_url: &str, value: &Json, options: &ParseOptions) -> json::Result<Any>
+where
+    T: ProtoJson + EncodableMessage,
+{
+    T::from_json(value, options).map(|t| Any::pack_with_type_url(type_url, &t))
+}
+
+/// Maps fully qualified message names to their types, for decoding `Any`s.
+#[derive(Clone, Default)]
+pub struct TypeRegistry {
+    decoders: HashMap<&'static str, DecodeFn>,
+    #[cfg(feature = "json")]
+    json: HashMap<&'static str, JsonFns>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` under its [`Name`], replacing any type registered under the same name.
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: Name + AnyMessage + for<'de> DecodableMessage<'de> + Default,
+    {
+        self.decoders.insert(T::NAME, decode::<T>);
+        self
+    }
+
+    /// Registers `T` like [`TypeRegistry::register`], and for printing and parsing `Any`s
+    /// with [`ProtoJson`].
+    #[cfg(feature = "json")]
+    pub fn register_json<T>(&mut self) -> &mut Self
+    where
+        T: Name
+            + AnyMessage
+            + ProtoJson
+            + EncodableMessage
+            + for<'de> DecodableMessage<'de>
+            + Default,
+    {
+        self.json.insert(
+            T::NAME,
+            JsonFns {
+                to_json: to_json::<T>,
+                from_json: from_json::<T>,
+            },
+        );
+        self.register::<T>()
+    }
+
+    /// The JSON of the message in `any`, if its type is registered with
+    /// [`TypeRegistry::register_json`] and it decodes.
+    #[cfg(feature = "json")]
+    pub(crate) fn any_to_json(&self, any: &Any, options: &PrintOptions) -> Option<Json> {
+        (self.json.get(any.type_name())?.to_json)(any, options)
+    }
+
+    /// Parses the JSON of a message with the type of `type_url` into an `Any`.
+    #[cfg(feature = "json")]
+    pub(crate) fn any_from_json(
+        &self,
+        type_url: &str,
+        value: &Json,
+        options: &ParseOptions,
+    ) -> json::Result<Any> {
+        match self.json.get(type_name(type_url)) {
+            Some(fns) => (fns.from_json)(type_url, value, options),
+            None => Err(json::JsonError::UnknownTypeUrl(type_url.to_owned())),
+        }
+    }
+
+    /// Whether a type is registered for `type_url`, which can also be a fully qualified name.
+    pub fn contains(&self, type_url: &str) -> bool {
+        self.decoders.contains_key(type_name(type_url))
+    }
+
+    /// Decodes an `Any` into the type registered for its type URL.
+    pub fn decode_any(&self, any: &Any) -> Result<Box<dyn AnyMessage>> {
+        match self.decoders.get(any.type_name()) {
+            Some(decode) => decode(any),
+            None => Err(DecodingError::UnknownTypeUrl(any.type_url.clone())),
+        }
+    }
+}
+
+impl Debug for TypeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
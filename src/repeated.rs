@@ -5,6 +5,7 @@ use bytes::BufMut;
 
 use crate::decoding::Decodable;
 use crate::encoding::{Encodable, ProtobufSerializer};
+use crate::mask::Mask;
 use crate::wire_types::WireType;
 use crate::VarInt;
 
@@ -59,7 +60,7 @@ impl<It, F> RepeatedMap<It, F> {
     }
 }
 
-impl<'a, F, IntoIt, NewIt> RepeatedMap<IntoIt, F>
+impl<F, IntoIt, NewIt> RepeatedMap<IntoIt, F>
 where
     F: Fn(IntoIt) -> NewIt,
     IntoIt: Clone,
@@ -113,6 +114,19 @@ macro_rules! mk_encoder_trait_impls {
         ) {
             self.mk_encoder().encode_field_precomputed(s, field_number)
         }
+
+        fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+            self.mk_encoder().encoded_size_masked(field_number, mask)
+        }
+
+        fn encode_field_masked<V: VarInt>(
+            &self,
+            s: &mut crate::encoding::ProtobufSerializer<impl BufMut>,
+            field_number: V,
+            mask: &Mask,
+        ) {
+            self.mk_encoder().encode_field_masked(s, field_number, mask)
+        }
     };
 }
 
@@ -169,6 +183,23 @@ where
             t.encode(s);
         }
     }
+
+    pub fn encoded_size_masked<V: VarInt>(self, field_number: V, mask: &Mask) -> usize {
+        self.0
+            .map(|t| t.encoded_size_masked(field_number, mask))
+            .sum()
+    }
+
+    pub fn encode_field_masked<V: VarInt>(
+        self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        for t in self.0 {
+            t.encode_field_masked(s, field_number, mask);
+        }
+    }
 }
 
 impl<'de, C> Decodable<'de> for Repeated<C>
@@ -199,6 +230,15 @@ where
         self.0.extend([C::Item::decode(deserializer)?]);
         Ok(())
     }
+
+    fn merge_from_masked<B: bytes::Buf>(
+        &mut self,
+        deserializer: &mut crate::decoding::Deserializer<'de, B>,
+        mask: &Mask,
+    ) -> crate::decoding::Result<()> {
+        self.0.extend([C::Item::decode_masked(deserializer, mask)?]);
+        Ok(())
+    }
 }
 
 impl<C> From<C> for Repeated<C> {
@@ -276,6 +316,7 @@ mod test {
     use otopr::*;
 
     /// Generic struct that holds any sequences of bytes.
+    #[allow(dead_code)]
     #[derive(otopr::EncodableMessage)]
     #[otopr(encode_extra_type_params(TItem))]
     #[otopr(encode_where_clause(
@@ -292,7 +333,7 @@ mod test {
     /// Assert that the types are well-formed, that is, all predicates on the type's `Encodable` impl are fulfilled.
     macro_rules! assert_wf {
         ($($ty:ty),+$(,)?) => {
-            #[allow(unreachable_code)]
+            #[allow(unreachable_code, clippy::diverging_sub_expression)]
             fn __assert_wf() {
                 $(
                     <$ty as otopr::__private::Encodable>::encoded_size(todo!(), 0);
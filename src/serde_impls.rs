@@ -0,0 +1,99 @@
+//! `Serialize` and `Deserialize` implementations for the wrapper types.
+//!
+//! Wrappers serialize transparently as the value they wrap, so that
+//! messages deriving serde traits serialize like plain Rust structs.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::traits::Signable;
+use crate::{BytesStr, Fixed32, Fixed64, Map, Message, Repeated, Signed};
+
+impl<C: Serialize> Serialize for Repeated<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        C::serialize(self, serializer)
+    }
+}
+
+impl<'de, C: Deserialize<'de>> Deserialize<'de> for Repeated<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        C::deserialize(deserializer).map(Repeated::new)
+    }
+}
+
+impl<T: Serialize> Serialize for Message<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Message<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Message)
+    }
+}
+
+impl<K, V, T: Serialize> Serialize for Map<K, V, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        T::serialize(self, serializer)
+    }
+}
+
+impl<'de, K, V, T: Deserialize<'de>> Deserialize<'de> for Map<K, V, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Map::new)
+    }
+}
+
+impl Serialize for Fixed32 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed32 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Fixed32::new)
+    }
+}
+
+impl Serialize for Fixed64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Fixed64::new)
+    }
+}
+
+impl<T: Signable> Serialize for Signed<T>
+where
+    T::From: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de, T: Signable> Deserialize<'de> for Signed<T>
+where
+    T::From: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::From::deserialize(deserializer).map(Signed::new)
+    }
+}
+
+impl Serialize for BytesStr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BytesStr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(BytesStr::from)
+    }
+}
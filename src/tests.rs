@@ -59,6 +59,77 @@ fn test_varint() -> otopr::decoding::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_varint_boundary() -> otopr::decoding::Result<()> {
+    use otopr::VarInt;
+
+    // 128 is the smallest value that needs a continuation byte.
+    for (n, bytes) in [
+        (127u32, &[0x7f][..]),
+        (128, &[0x80, 0x01]),
+        (129, &[0x81, 0x01]),
+    ] {
+        let mut buf = vec![];
+        n.write(&mut buf);
+        assert_eq!(buf, bytes);
+        assert_eq!(n.size(), bytes.len());
+        assert_eq!(u32::read(&mut Deserializer::new(&mut buf.as_slice()))?, n);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_negative_int32() -> otopr::decoding::Result<()> {
+    use otopr::VarInt;
+
+    // negative `int32`s take ten bytes, the same as the `int64` with the same value.
+    let mut buf = vec![];
+    (-1i32).write(&mut buf);
+    assert_eq!(
+        buf,
+        [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+    );
+    assert_eq!((-1i32).size(), 10);
+    assert_eq!(i32::read(&mut Deserializer::new(&mut buf.as_slice()))?, -1);
+    assert_eq!(i64::read(&mut Deserializer::new(&mut buf.as_slice()))?, -1);
+
+    // the five-byte form written before is still read.
+    let old: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x0f];
+    assert_eq!(i32::read(&mut Deserializer::new(&mut &*old))?, -1);
+    Ok(())
+}
+
+#[test]
+fn test_zigzag() {
+    use crate::traits::Signable;
+
+    // https://developers.google.com/protocol-buffers/docs/encoding#signed-ints
+    for (n, encoded) in [
+        (0, 0),
+        (-1, 1),
+        (1, 2),
+        (-2, 3),
+        (i32::MAX, u32::MAX - 1),
+        (i32::MIN, u32::MAX),
+    ] {
+        assert_eq!(i32::zigzag_encode(n), encoded);
+        assert_eq!(i32::zigzag_decode(encoded), n);
+    }
+    for n in [0, -1, 1, i64::MIN, i64::MAX] {
+        assert_eq!(i64::zigzag_decode(i64::zigzag_encode(n)), n);
+    }
+}
+
+#[test]
+fn test_signed_fixed64() {
+    use crate::{Fixed64, Signed};
+
+    // values outside of the `i32` range used to be truncated to 32 bits.
+    for n in [0, -1, 1, i64::from(i32::MAX) + 1, i64::MIN, i64::MAX] {
+        assert_eq!(Signed::<Fixed64>::new(n).get(), n);
+    }
+}
+
 #[test]
 fn test_enumeration() -> otopr::decoding::Result<()> {
     use otopr::Enumeration;
@@ -95,3 +166,1873 @@ fn test_enumeration() -> otopr::decoding::Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "codec")]
+#[tokio::test]
+async fn test_codec_roundtrip() {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    use crate::codec::{CodecError, ProtobufCodec};
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Ping {
+        #[otopr(1)]
+        id: u64,
+        #[otopr(2)]
+        payload: Vec<u8>,
+    }
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut writer = FramedWrite::new(client, ProtobufCodec::<Ping>::new());
+    let mut reader = FramedRead::new(server, ProtobufCodec::<Ping>::with_max_frame_length(64));
+
+    let ping = |id, len| Ping {
+        id,
+        payload: vec![id as u8; len],
+    };
+
+    let send = async {
+        for (id, len) in [(1, 10), (2, 50), (3, 100)] {
+            writer.send(ping(id, len)).await.unwrap();
+        }
+    };
+    let recv = async {
+        assert_eq!(reader.next().await.unwrap().unwrap(), ping(1, 10));
+        assert_eq!(reader.next().await.unwrap().unwrap(), ping(2, 50));
+        assert!(matches!(
+            reader.next().await,
+            Some(Err(CodecError::FrameTooLarge { max: 64, .. }))
+        ));
+    };
+    tokio::join!(send, recv);
+}
+
+#[test]
+fn test_bytes_zero_copy() -> crate::decoding::Result<()> {
+    use bytes::Bytes;
+
+    use crate::BytesStr;
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default)]
+    struct Envelope {
+        #[otopr(1)]
+        topic: BytesStr,
+        #[otopr(2)]
+        payload: Bytes,
+    }
+
+    let mut buf = Vec::new();
+    let envelope = Envelope {
+        topic: BytesStr::from_static("events"),
+        payload: Bytes::from_static(&[1, 2, 3]),
+    };
+    EncodableMessage::encode(&envelope, &mut ProtobufSerializer::new(&mut buf));
+
+    let buf = Bytes::from(buf);
+    let range = buf.as_ptr_range();
+    let decoded: Envelope = DecodableMessage::decode(&mut Deserializer::new(&mut buf.clone()))?;
+
+    assert_eq!(decoded.topic, "events");
+    assert_eq!(decoded.payload, [1, 2, 3][..]);
+    assert!(range.contains(&decoded.topic.as_ptr()));
+    assert!(range.contains(&decoded.payload.as_ptr()));
+    Ok(())
+}
+
+#[test]
+fn test_cow_fragmented() -> crate::decoding::Result<()> {
+    use std::borrow::Cow;
+
+    use bytes::Buf;
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default)]
+    struct Names<'de> {
+        #[otopr(1)]
+        first: Cow<'de, str>,
+        #[otopr(2)]
+        last: Cow<'de, [u8]>,
+    }
+
+    let mut buf = Vec::new();
+    let names = Names {
+        first: Cow::Borrowed("Ferris"),
+        last: Cow::Borrowed(b"Crab"),
+    };
+    EncodableMessage::encode(&names, &mut ProtobufSerializer::new(&mut buf));
+
+    let mut contiguous = buf.as_slice();
+    let decoded: Names = DecodableMessage::decode(&mut Deserializer::borrowed(&mut contiguous))?;
+    assert!(matches!(decoded.first, Cow::Borrowed("Ferris")));
+    assert!(matches!(decoded.last, Cow::Borrowed(b"Crab")));
+
+    // split the buffer in the middle of the first string.
+    let (head, tail) = buf.split_at(4);
+    let mut fragmented = head.chain(tail);
+    let decoded: Names = DecodableMessage::decode(&mut Deserializer::borrowed(&mut fragmented))?;
+    assert!(matches!(decoded.first, Cow::Owned(ref s) if s == "Ferris"));
+    assert!(matches!(decoded.last, Cow::Borrowed(b"Crab")));
+
+    // copies everything when the deserializer cannot borrow.
+    let mut owned = bytes::Bytes::from(buf);
+    let decoded: Names = DecodableMessage::decode(&mut Deserializer::new(&mut owned))?;
+    assert!(matches!(decoded.first, Cow::Owned(ref s) if s == "Ferris"));
+    assert!(matches!(decoded.last, Cow::Owned(ref b) if b == b"Crab"));
+    Ok(())
+}
+
+#[test]
+fn test_borrow_unsupported() {
+    use crate::decoding::DecodingError;
+
+    #[derive(crate::DecodableMessage, Default)]
+    struct Name<'de>(#[otopr(1)] &'de str);
+
+    let mut buf = bytes::BytesMut::from(&[0x0a, 0x02, b'h', b'i'][..]);
+    let res: crate::decoding::Result<Name> =
+        DecodableMessage::decode(&mut Deserializer::new(&mut buf));
+    assert!(matches!(res, Err(DecodingError::BorrowUnsupported)));
+
+    let mut slice = &[0x0a, 0x02, b'h', b'i'][..];
+    let res: crate::decoding::Result<Name> =
+        DecodableMessage::decode(&mut Deserializer::borrowed(&mut slice));
+    assert_eq!(res.unwrap().0, "hi");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_json() {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Fixed64, Map, Message, Repeated, Signed};
+
+    #[derive(crate::EncodableMessage, Serialize, Deserialize, Default, Debug)]
+    struct Point {
+        #[otopr(1)]
+        x: Signed<i32>,
+        #[otopr(2)]
+        y: Signed<i32>,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Debug)]
+    struct Shape {
+        points: Repeated<Vec<Message<Point>>>,
+        tags: Map<String, u32, BTreeMap<String, u32>>,
+        id: Fixed64,
+    }
+
+    let json = r#"{"points":[{"x":-1,"y":2}],"tags":{"a":1},"id":42}"#;
+    let shape: Shape = serde_json::from_str(json).unwrap();
+    assert_eq!(shape.points[0].0.x, Signed::new(-1));
+    assert_eq!(shape.id, Fixed64::new(42));
+    assert_eq!(serde_json::to_string(&shape).unwrap(), json);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_proto_json() -> crate::json::Result<()> {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use crate::json::{self, JsonError, ParseOptions, PrintOptions, ProtoJson};
+    use crate::registry::TypeRegistry;
+    use crate::well_known::{Any, Duration};
+    use crate::{Enumeration, Map, Message, Repeated};
+
+    #[derive(Enumeration, Debug, PartialEq)]
+    enum Status {
+        Unset = 0,
+        Active = 1,
+    }
+
+    #[derive(ProtoJson, Default, Debug, PartialEq)]
+    struct Owner {
+        #[otopr(1)]
+        display_name: String,
+    }
+
+    #[derive(ProtoJson, Default, Debug, PartialEq)]
+    struct Account {
+        #[otopr(1)]
+        account_id: u64,
+        #[otopr(2)]
+        balance: i32,
+        #[otopr(3)]
+        status: Status,
+        #[otopr(4)]
+        avatar: Vec<u8>,
+        #[otopr(5)]
+        owner: Message<Owner>,
+        #[otopr(6)]
+        labels: Repeated<Vec<String>>,
+        #[otopr(7)]
+        limits: Map<u32, i64, BTreeMap<u32, i64>>,
+    }
+
+    let account = Account {
+        account_id: 1 << 60,
+        balance: 0,
+        status: Status::Active,
+        avatar: b"otopr".to_vec(),
+        owner: Message(Owner {
+            display_name: "Ferris".into(),
+        }),
+        labels: Repeated::new(vec!["a".into()]),
+        limits: Map::new(vec![(1, -5)].into_iter().collect()),
+    };
+
+    let printed = json::to_string(&account, &PrintOptions::default());
+    let expected = r#"{"accountId":"1152921504606846976","avatar":"b3RvcHI=","labels":["a"],"limits":{"1":"-5"},"owner":{"displayName":"Ferris"},"status":"Active"}"#;
+    assert_eq!(printed, expected);
+    assert_eq!(
+        json::from_str::<Account>(&printed, &ParseOptions::default())?,
+        account
+    );
+
+    let printed = account.to_json(&PrintOptions {
+        emit_default_values: true,
+        preserve_proto_field_names: true,
+        ..Default::default()
+    });
+    assert_eq!(printed["balance"], 0);
+    assert_eq!(printed["owner"]["display_name"], "Ferris");
+
+    let unknown = r#"{"accountId": 5, "avatar": "b3RvcHI", "extra": true}"#;
+    assert!(matches!(
+        json::from_str::<Account>(unknown, &ParseOptions::default()),
+        Err(JsonError::UnknownField(f)) if f == "extra"
+    ));
+    let parsed: Account = json::from_str(
+        unknown,
+        &ParseOptions {
+            ignore_unknown_fields: true,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(parsed.account_id, 5);
+    assert_eq!(parsed.avatar, b"otopr");
+
+    // `Any`s are printed as the message they contain, if its type is registered.
+    #[derive(
+        crate::EncodableMessage, crate::DecodableMessage, ProtoJson, Default, Debug, PartialEq,
+    )]
+    #[otopr(name = "events.Login")]
+    struct Login {
+        #[otopr(1)]
+        user_id: u64,
+    }
+
+    #[derive(ProtoJson, Default, Debug, PartialEq)]
+    struct Event {
+        #[otopr(1)]
+        payload: Any,
+        #[otopr(2)]
+        timeout: Any,
+    }
+
+    let event = Event {
+        payload: Any::pack(&Login { user_id: 7 }),
+        timeout: Any::pack(&Duration {
+            seconds: 1,
+            nanos: 0,
+        }),
+    };
+    let mut registry = TypeRegistry::new();
+    registry
+        .register_json::<Login>()
+        .register_json::<Duration>();
+    let registry = Some(Arc::new(registry));
+    let printed = json::to_string(
+        &event,
+        &PrintOptions {
+            type_registry: registry.clone(),
+            ..Default::default()
+        },
+    );
+    let expected = r#"{"payload":{"@type":"type.googleapis.com/events.Login","userId":"7"},"timeout":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"1s"}}"#;
+    assert_eq!(printed, expected);
+    let options = ParseOptions {
+        type_registry: registry,
+        ..Default::default()
+    };
+    assert_eq!(json::from_str::<Event>(&printed, &options)?, event);
+
+    // without a registry, the message is printed as bytes and cannot be parsed.
+    let printed = event.payload.to_json(&PrintOptions::default());
+    assert_eq!(printed["value"], "CAc=");
+    assert!(matches!(
+        Any::from_json(&printed, &ParseOptions::default()),
+        Err(JsonError::UnknownTypeUrl(url)) if url == "type.googleapis.com/events.Login"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_text_format() -> crate::text_format::Result<()> {
+    use std::collections::BTreeMap;
+
+    use crate::text_format::{self, TextFormat};
+    use crate::{Enumeration, Map, Message, Repeated};
+
+    #[derive(Enumeration, Debug, PartialEq)]
+    enum Status {
+        Unset = 0,
+        Active = 1,
+    }
+
+    #[derive(TextFormat, Default, Debug, PartialEq)]
+    struct Owner {
+        #[otopr(1)]
+        display_name: String,
+    }
+
+    #[derive(TextFormat, Default, Debug, PartialEq)]
+    struct Account {
+        #[otopr(1)]
+        id: u64,
+        #[otopr(2)]
+        balance: i32,
+        #[otopr(3)]
+        status: Status,
+        #[otopr(4)]
+        avatar: Vec<u8>,
+        #[otopr(5)]
+        owner: Message<Owner>,
+        #[otopr(6)]
+        labels: Repeated<Vec<String>>,
+        #[otopr(7)]
+        limits: Map<u32, i64, BTreeMap<u32, i64>>,
+    }
+
+    let account = Account {
+        id: 7,
+        balance: 0,
+        status: Status::Active,
+        avatar: b"o\"\xff".to_vec(),
+        owner: Message(Owner {
+            display_name: "Ferris\n".into(),
+        }),
+        labels: Repeated::new(vec!["a".into(), "b".into()]),
+        limits: Map::new(vec![(1, -5)].into_iter().collect()),
+    };
+
+    let printed = text_format::to_string(&account);
+    let expected = r#"id: 7
+status: Active
+avatar: "o\"\377"
+owner {
+  display_name: "Ferris\n"
+}
+labels: "a"
+labels: "b"
+limits {
+  key: 1
+  value: -5
+}
+"#;
+    assert_eq!(printed, expected);
+    assert_eq!(text_format::from_str::<Account>(&printed)?, account);
+
+    let parsed: Account = text_format::from_str(
+        "# comment\n1: 0x7, status: 1; avatar: 'o\\\"' \"\\xff\" owner: < display_name: \"Ferris\\n\" >\n\
+         labels: [\"a\", \"b\"] limits { value: -5 key: 1 }",
+    )?;
+    assert_eq!(parsed, account);
+
+    // repeated fields and maps print and parse as `[..]` lists on their own.
+    let mut p = text_format::Printer::default();
+    account.labels.print_value(&mut p);
+    p.write_str(" ");
+    account.limits.print_value(&mut p);
+    let printed = p.finish();
+    assert_eq!(printed, "[\"a\", \"b\"] [{\n  key: 1\n  value: -5\n}]");
+    let mut p = text_format::Parser::new(&printed);
+    assert_eq!(
+        Repeated::<Vec<String>>::parse_value(&mut p)?,
+        account.labels
+    );
+    assert_eq!(
+        Map::<u32, i64, BTreeMap<u32, i64>>::parse_value(&mut p)?,
+        account.limits
+    );
+
+    #[derive(TextFormat, Default, Debug, PartialEq)]
+    struct Reading {
+        #[otopr(1)]
+        high: f64,
+        #[otopr(2)]
+        low: f32,
+        #[otopr(3)]
+        mean: f64,
+    }
+
+    let reading = Reading {
+        high: f64::INFINITY,
+        low: f32::NEG_INFINITY,
+        mean: f64::NAN,
+    };
+    let printed = text_format::to_string(&reading);
+    assert_eq!(printed, "high: inf\nlow: -inf\nmean: nan\n");
+    let parsed: Reading = text_format::from_str(&printed)?;
+    assert_eq!(
+        (parsed.high, parsed.low),
+        (f64::INFINITY, f32::NEG_INFINITY)
+    );
+    assert!(parsed.mean.is_nan());
+    let parsed: Reading = text_format::from_str("high: -Infinity low: 1.5f mean: NaN")?;
+    assert_eq!((parsed.high, parsed.low), (f64::NEG_INFINITY, 1.5));
+    assert!(parsed.mean.is_nan());
+    assert!(text_format::from_str::<Reading>("low: 1.5ff").is_err());
+
+    let err = text_format::from_str::<Account>("id: 1\nnope: 2").unwrap_err();
+    assert_eq!(err.to_string(), "2:5: unknown field `nope`");
+    Ok(())
+}
+
+#[test]
+fn test_well_known() -> crate::decoding::Result<()> {
+    use std::convert::TryFrom;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::well_known::*;
+
+    let ts: Timestamp = "1972-01-01T12:00:20.021+02:00".parse().unwrap();
+    assert_eq!(
+        ts,
+        Timestamp {
+            seconds: 63_108_020,
+            nanos: 21_000_000
+        }
+    );
+    assert_eq!(ts.to_string(), "1972-01-01T10:00:20.021Z");
+    assert!("1972-02-30T00:00:00Z".parse::<Timestamp>().is_err());
+
+    let before_epoch = UNIX_EPOCH - std::time::Duration::from_millis(1500);
+    let ts = Timestamp::from(before_epoch);
+    assert_eq!((ts.seconds, ts.nanos), (-2, 500_000_000));
+    assert_eq!(SystemTime::try_from(ts), Ok(before_epoch));
+
+    let d: Duration = "-1.000000128s".parse().unwrap();
+    assert_eq!((d.seconds, d.nanos), (-1, -128));
+    assert_eq!(d.to_string(), "-1.000000128s");
+    assert!(std::time::Duration::try_from(d).is_err());
+    assert_eq!("s".parse::<Duration>(), Err(ConversionError::InvalidFormat));
+    assert_eq!(
+        "-.5s".parse::<Duration>(),
+        Err(ConversionError::InvalidFormat)
+    );
+    assert_eq!(
+        "99999999999999999999s".parse::<Duration>(),
+        Err(ConversionError::OutOfRange)
+    );
+
+    let any = Any::pack_with_type_url("type.googleapis.com/google.protobuf.Duration", &d);
+    assert_eq!(
+        any.unpack_with_type_url::<Duration>("google.protobuf.Duration")?,
+        Some(d)
+    );
+    assert_eq!(
+        any.unpack_with_type_url::<Timestamp>("google.protobuf.Timestamp")?,
+        None
+    );
+
+    let mut fields = std::collections::BTreeMap::new();
+    fields.insert("n".to_owned(), Value::Number(1.5));
+    fields.insert(
+        "list".to_owned(),
+        Value::List(ListValue {
+            values: vec![Value::Null, Value::Bool(true), Value::String("x".into())].into(),
+        }),
+    );
+    let value = Value::Struct(Struct {
+        fields: fields.into(),
+    });
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&value, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&value));
+    let decoded: Value = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded, value);
+
+    let mut mask = FieldMask::new(vec!["user.name", "id", "user", "id"]);
+    mask.normalize();
+    assert_eq!(mask.to_string(), "id,user");
+    assert!(mask.contains("user.address.city"));
+    let other = FieldMask::new(vec!["user.name", "tags"]);
+    assert_eq!(mask.intersection(&other).to_string(), "user.name");
+    assert_eq!(mask.union(&other).to_string(), "id,tags,user");
+
+    #[cfg(feature = "json")]
+    {
+        use crate::json::{self, ParseOptions, PrintOptions};
+
+        let printed = json::to_string(&value, &PrintOptions::default());
+        assert_eq!(printed, r#"{"list":[null,true,"x"],"n":1.5}"#);
+        assert_eq!(
+            json::from_str::<Value>(&printed, &ParseOptions::default()).unwrap(),
+            value
+        );
+        assert_eq!(
+            json::to_string(&mask, &PrintOptions::default()),
+            r#""id,user""#
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_type_registry() -> crate::decoding::Result<()> {
+    use crate::decoding::DecodingError;
+    use crate::registry::{AnyMessage, TypeRegistry};
+    use crate::well_known::{Any, Timestamp};
+    use crate::Name;
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    #[otopr(name = "events.UserCreated")]
+    struct UserCreated {
+        #[otopr(1)]
+        id: u64,
+        #[otopr(2)]
+        created_at: Timestamp,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    #[otopr(name = "events.UserDeleted")]
+    struct UserDeleted {
+        #[otopr(1)]
+        id: u64,
+    }
+
+    assert_eq!(
+        UserCreated::type_url(),
+        "type.googleapis.com/events.UserCreated"
+    );
+
+    let created = UserCreated {
+        id: 1,
+        created_at: Timestamp {
+            seconds: 1,
+            nanos: 2,
+        },
+    };
+    let any = Any::pack(&created);
+    assert!(any.is::<UserCreated>());
+    assert_eq!(any.unpack::<UserDeleted>()?, None);
+    assert_eq!(any.unpack::<UserCreated>()?.as_ref(), Some(&created));
+
+    let mut registry = TypeRegistry::new();
+    registry.register::<UserCreated>().register::<Timestamp>();
+    assert!(registry.contains("type.googleapis.com/google.protobuf.Timestamp"));
+
+    let events = [any, Any::pack(&Timestamp::default())];
+    let decoded = events
+        .iter()
+        .map(|any| registry.decode_any(any))
+        .collect::<crate::decoding::Result<Vec<Box<dyn AnyMessage>>>>()?;
+    assert_eq!(decoded[0].type_name(), "events.UserCreated");
+    assert_eq!(decoded[0].downcast_ref::<UserCreated>(), Some(&created));
+    assert!(decoded[1].is::<Timestamp>());
+    assert_eq!(decoded[0].to_any(), events[0]);
+
+    let unknown = Any::pack(&UserDeleted { id: 1 });
+    assert!(matches!(
+        registry.decode_any(&unknown),
+        Err(DecodingError::UnknownTypeUrl(url)) if url == "type.googleapis.com/events.UserDeleted"
+    ));
+    Ok(())
+}
+
+/// `package shop; message Item { string name = 1; repeated int32 counts = 2; map<string, int64> prices = 3;
+/// Child child = 4; Color color = 5; sint32 delta = 6; } message Child { string name = 1; } enum Color { RED = 0; BLUE = 1; }`
+/// in proto2.
+fn shop_descriptor_set() -> crate::descriptor::FileDescriptorSet {
+    use crate::descriptor::*;
+
+    fn field(
+        name: &str,
+        number: i32,
+        label: i32,
+        r#type: i32,
+        type_name: &str,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(label),
+            r#type: Some(r#type),
+            type_name: Some(type_name.to_owned()).filter(|n| !n.is_empty()),
+            ..Default::default()
+        }
+    }
+
+    let message = |name: &str, fields: Vec<FieldDescriptorProto>| DescriptorProto {
+        name: Some(name.to_owned()),
+        field: fields.into(),
+        ..Default::default()
+    };
+
+    use field_label::*;
+    use field_type::*;
+    let mut item = message(
+        "Item",
+        vec![
+            field("name", 1, OPTIONAL, STRING, ""),
+            field("counts", 2, REPEATED, INT32, ""),
+            field("prices", 3, REPEATED, MESSAGE, "PricesEntry"),
+            field("child", 4, OPTIONAL, MESSAGE, ".shop.Child"),
+            field("color", 5, OPTIONAL, ENUM, "Color"),
+            field("delta", 6, OPTIONAL, SINT32, ""),
+        ],
+    );
+    let mut entry = message(
+        "PricesEntry",
+        vec![
+            field("key", 1, OPTIONAL, STRING, ""),
+            field("value", 2, OPTIONAL, INT64, ""),
+        ],
+    );
+    entry.options = Some(MessageOptions {
+        map_entry: Some(true),
+    });
+    item.nested_type.push(entry);
+    FileDescriptorSet {
+        file: vec![FileDescriptorProto {
+            name: Some("shop.proto".to_owned()),
+            package: Some("shop".to_owned()),
+            message_type: vec![
+                item,
+                message("Child", vec![field("name", 1, OPTIONAL, STRING, "")]),
+            ]
+            .into(),
+            enum_type: vec![EnumDescriptorProto {
+                name: Some("Color".to_owned()),
+                value: vec![
+                    EnumValueDescriptorProto {
+                        name: Some("RED".to_owned()),
+                        number: Some(0),
+                    },
+                    EnumValueDescriptorProto {
+                        name: Some("BLUE".to_owned()),
+                        number: Some(1),
+                    },
+                ]
+                .into(),
+                options: None,
+            }]
+            .into(),
+            ..Default::default()
+        }]
+        .into(),
+    }
+}
+
+#[test]
+fn test_dynamic_message() -> crate::decoding::Result<()> {
+    use crate::decoding::DecodingError;
+    use crate::dynamic::{DescriptorPool, DynamicMessage, Kind, MapKey, Value};
+    use crate::{Map, Repeated, VarInt};
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Child {
+        #[otopr(1)]
+        name: String,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Item {
+        #[otopr(1)]
+        name: String,
+        #[otopr(2)]
+        counts: Repeated<Vec<i32>>,
+        #[otopr(3)]
+        prices: Map<String, i64>,
+        #[otopr(4)]
+        child: Child,
+        #[otopr(5)]
+        color: i32,
+    }
+
+    let set = shop_descriptor_set();
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&set, &mut ProtobufSerializer::new(&mut buf));
+    let pool = DescriptorPool::decode(buf.as_slice()).unwrap();
+    let schema = pool.get_message(".shop.Item").unwrap();
+    assert_eq!(schema.name(), "Item");
+    let prices = schema.get_field_by_name("prices").unwrap();
+    assert!(prices.is_map());
+    assert!(
+        matches!(schema.get_field(5).unwrap().kind(), Kind::Enum(e) if e.name_of(1) == Some("BLUE"))
+    );
+
+    let mut prices = Map::<String, i64>::default();
+    prices.insert("apple".to_owned(), 3);
+    let original = Item {
+        name: "basket".to_owned(),
+        counts: vec![1, -2, 300].into(),
+        prices,
+        child: Child {
+            name: "apple".to_owned(),
+        },
+        color: 1,
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&original, &mut ProtobufSerializer::new(&mut buf));
+
+    let mut dynamic = DynamicMessage::decode(schema.clone(), buf.as_slice())?;
+    assert_eq!(
+        dynamic.get_by_name("name"),
+        Some(&Value::String("basket".to_owned()))
+    );
+    assert_eq!(
+        dynamic.get_by_name("counts"),
+        Some(&Value::List(vec![
+            Value::I32(1),
+            Value::I32(-2),
+            Value::I32(300)
+        ]))
+    );
+    match dynamic.get_by_name("prices") {
+        Some(Value::Map(map)) => assert_eq!(
+            map.get(&MapKey::String("apple".to_owned())),
+            Some(&Value::I64(3))
+        ),
+        other => panic!("unexpected value {:?}", other),
+    }
+    assert_eq!(dynamic.get_by_name("color"), Some(&Value::EnumNumber(1)));
+    assert!(dynamic.set_by_name("delta", Value::I64(1)).is_err());
+    dynamic.set_by_name("delta", Value::I32(-3)).unwrap();
+    // repeated fields only take lists of their element type.
+    assert!(dynamic
+        .set_by_name("counts", Value::String("1".to_owned()))
+        .is_err());
+
+    let mut reencoded = Vec::new();
+    EncodableMessage::encode(&dynamic, &mut ProtobufSerializer::new(&mut reencoded));
+    assert_eq!(reencoded.len(), dynamic.encoded_size());
+    assert_eq!(
+        DynamicMessage::decode(schema.clone(), reencoded.as_slice())?,
+        dynamic
+    );
+    let decoded: Item =
+        DecodableMessage::decode(&mut Deserializer::new(&mut reencoded.as_slice()))?;
+    assert_eq!(decoded, original);
+
+    // redacting a field without knowing the type at compile time.
+    dynamic.clear_by_name("child");
+    let mut redacted = Vec::new();
+    EncodableMessage::encode(&dynamic, &mut ProtobufSerializer::new(&mut redacted));
+    let decoded: Item = DecodableMessage::decode(&mut Deserializer::new(&mut redacted.as_slice()))?;
+    assert_eq!(decoded.child, Child::default());
+    assert_eq!(decoded.name, original.name);
+
+    // recursive messages fail to decode past a depth instead of overflowing the stack.
+    let mut set = shop_descriptor_set();
+    set.file[0]
+        .message_type
+        .push(crate::descriptor::DescriptorProto {
+            name: Some("Node".to_owned()),
+            field: vec![crate::descriptor::FieldDescriptorProto {
+                name: Some("child".to_owned()),
+                number: Some(1),
+                label: Some(crate::descriptor::field_label::OPTIONAL),
+                r#type: Some(crate::descriptor::field_type::MESSAGE),
+                type_name: Some(".shop.Node".to_owned()),
+                ..Default::default()
+            }]
+            .into(),
+            ..Default::default()
+        });
+    let pool = DescriptorPool::from_file_descriptor_set(&set).unwrap();
+    let node = pool.get_message(".shop.Node").unwrap();
+    let nested = |depth: usize| {
+        let mut buf = Vec::new();
+        for _ in 0..depth {
+            let mut outer = vec![0x0a];
+            VarInt::write(buf.len() as u64, &mut outer);
+            outer.extend_from_slice(&buf);
+            buf = outer;
+        }
+        buf
+    };
+    assert!(DynamicMessage::decode(node.clone(), nested(100).as_slice()).is_ok());
+    assert!(matches!(
+        DynamicMessage::decode(node, nested(5000).as_slice()),
+        Err(DecodingError::TooDeep)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_decode_raw() -> crate::decoding::Result<()> {
+    use crate::decoding::raw::{RawMessage, RawValue};
+    use crate::decoding::DecodingError;
+    use crate::Fixed32;
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default)]
+    struct Inner {
+        #[otopr(1)]
+        id: u64,
+        #[otopr(2)]
+        label: String,
+    }
+
+    #[derive(crate::EncodableMessage, Default)]
+    struct Outer {
+        #[otopr(1)]
+        count: i32,
+        #[otopr(2)]
+        inner: Inner,
+        #[otopr(3)]
+        checksum: Fixed32,
+        #[otopr(4)]
+        data: Vec<u8>,
+        #[otopr(5)]
+        ratio: f64,
+    }
+
+    let outer = Outer {
+        count: 150,
+        inner: Inner {
+            id: 7,
+            label: "a \"b\"".to_owned(),
+        },
+        checksum: Fixed32(0xdeadbeef),
+        data: vec![0xff, 0x00],
+        ratio: 1.0,
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&outer, &mut ProtobufSerializer::new(&mut buf));
+
+    let raw = RawMessage::decode(buf.as_slice())?;
+    assert_eq!(raw.get(1).next(), Some(&RawValue::VarInt(150)));
+    assert!(matches!(raw.get(2).next(), Some(RawValue::Message(m)) if m.fields.len() == 2));
+    assert_eq!(
+        raw.to_string(),
+        "1: 150\n\
+         2 {\n  1: 7\n  2: \"a \\\"b\\\"\"\n}\n\
+         3: 0xdeadbeef\n\
+         4: \"\\377\\000\"\n\
+         5: 0x3ff0000000000000\n"
+    );
+
+    // truncated input fails instead of panicking.
+    for len in 0..buf.len() {
+        let res = RawMessage::decode(&buf[..len]);
+        assert!(matches!(res, Ok(_) | Err(DecodingError::Eof)), "{:?}", res);
+    }
+    assert!(matches!(
+        RawMessage::decode(&buf[..buf.len() - 1]),
+        Err(DecodingError::Eof)
+    ));
+    let mut truncated = &[0x80][..];
+    let mut d = Deserializer::new(&mut truncated);
+    assert!(matches!(
+        crate::wire_types::WireTypes::VarIntWire.skip(&mut d),
+        Err(DecodingError::Eof)
+    ));
+
+    // groups are decoded like nested messages, and skipped by derived messages.
+    let groups = b"\x1b\x08\x05\x13\x08\x01\x14\x1c\x08\x96\x01";
+    let raw = RawMessage::decode(&groups[..])?;
+    assert_eq!(
+        raw.fields[0].wire_type,
+        crate::wire_types::WireTypes::StartGroupWire
+    );
+    assert_eq!(
+        raw.to_string(),
+        "3 {\n  1: 5\n  2 {\n    1: 1\n  }\n}\n1: 150\n"
+    );
+    let inner: Inner = DecodableMessage::decode(&mut Deserializer::new(&mut &groups[..]))?;
+    assert_eq!(inner.id, 150);
+    for invalid in [&b"\x1c"[..], b"\x1b\x08\x05\x24", b"\x1b\x08\x05"] {
+        assert!(RawMessage::decode(invalid).is_err(), "{:?}", invalid);
+    }
+    assert!(matches!(
+        RawMessage::decode(&b"\x1b\x08\x05\x24"[..]),
+        Err(DecodingError::InvalidGroup)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_dynamic_text_format() -> crate::text_format::Result<()> {
+    use crate::dynamic::{DescriptorPool, DynamicMessage, Value};
+    use crate::text_format::{self, Parser};
+
+    let pool = DescriptorPool::from_file_descriptor_set(&shop_descriptor_set()).unwrap();
+    let schema = pool.get_message("shop.Item").unwrap();
+    let mut message = DynamicMessage::new(schema.clone());
+    Parser::new(
+        r#"name: "basket" counts: [1, -2] counts: 3
+        prices { key: "apple" value: 3 } child { name: "x" } color: BLUE 6: -1"#,
+    )
+    .merge_fields(&mut message, None)?;
+    assert_eq!(message.get_by_name("delta"), Some(&Value::I32(-1)));
+    assert_eq!(
+        text_format::to_string(&message),
+        "name: \"basket\"\ncounts: 1\ncounts: -2\ncounts: 3\nprices {\n  key: \"apple\"\n  value: 3\n}\n\
+         child {\n  name: \"x\"\n}\ncolor: BLUE\ndelta: -1\n"
+    );
+
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&message, &mut ProtobufSerializer::new(&mut buf));
+    let decoded = DynamicMessage::decode(schema.clone(), buf.as_slice()).unwrap();
+    assert_eq!(decoded, message);
+
+    #[cfg(feature = "json")]
+    {
+        use crate::json::{ParseOptions, PrintOptions};
+
+        let json = message.to_json(&PrintOptions::default());
+        assert_eq!(
+            json.to_string(),
+            r#"{"child":{"name":"x"},"color":"BLUE","counts":[1,-2,3],"delta":-1,"name":"basket","prices":{"apple":"3"}}"#
+        );
+        let parsed = DynamicMessage::from_json(schema, &json, &ParseOptions::default()).unwrap();
+        assert_eq!(parsed, message);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_message_descriptor() {
+    use crate::reflect::{Cardinality, FieldDescriptor, Kind, MessageDescriptor, ProtoType};
+    use crate::well_known::Timestamp;
+    use crate::{Enumeration, Map, Repeated, Signed};
+
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    enum Status {
+        Active = 0,
+        Banned = 2,
+    }
+
+    #[derive(crate::EncodableMessage)]
+    #[otopr(name = "accounts.User")]
+    struct User<'a, T: crate::encoding::Encodable> {
+        #[otopr(1)]
+        name: &'a str,
+        #[otopr(3)]
+        status: Status,
+        #[otopr(4)]
+        tags: Repeated<Vec<String>>,
+        #[otopr(5)]
+        scores: Map<String, Signed<i64>>,
+        #[otopr(6)]
+        created: Option<Timestamp>,
+        #[otopr(7)]
+        extra: T,
+    }
+
+    #[derive(crate::EncodableMessage)]
+    struct Pair(#[otopr(1)] u32, #[otopr(2)] Vec<u8>);
+
+    type U = User<'static, Pair>;
+    assert_eq!(U::NAME, "accounts.User");
+    assert_eq!(Pair::NAME, "Pair");
+    assert_eq!(
+        U::field(1),
+        Some(&FieldDescriptor {
+            name: "name",
+            number: 1,
+            wire_type: crate::wire_types::WireTypes::LengthDelimitedWire,
+            cardinality: Cardinality::Singular,
+            kind: Kind::String,
+            packed: false,
+        })
+    );
+    match U::field_by_name("status").unwrap().kind {
+        Kind::Enum(e) => assert_eq!(e.values, &[("Active", 0), ("Banned", 2)]),
+        kind => panic!("unexpected kind {:?}", kind),
+    }
+    assert_eq!(U::field(4).unwrap().cardinality, Cardinality::Repeated);
+    let scores = U::field(5).unwrap();
+    assert_eq!(
+        (scores.cardinality, scores.kind),
+        (Cardinality::Map { key: Kind::String }, Kind::SInt64)
+    );
+    assert_eq!(U::field(6).unwrap().cardinality, Cardinality::Optional);
+    assert_eq!(Status::KIND, U::field(3).unwrap().kind);
+    assert_eq!(Status::from_i32(2), Some(Status::Banned));
+
+    /// Lists every field path, like a generic redaction tool would.
+    fn paths(prefix: &str, fields: &[FieldDescriptor], out: &mut Vec<String>) {
+        for f in fields {
+            let path = format!("{}{}", prefix, f.name);
+            if let Kind::Message(m) = f.kind {
+                paths(&format!("{}.", path), (m.fields)(), out);
+            }
+            out.push(path);
+        }
+    }
+    let mut out = Vec::new();
+    paths("", U::fields(), &mut out);
+    assert_eq!(
+        out,
+        [
+            "name",
+            "status",
+            "tags",
+            "scores",
+            "created.seconds",
+            "created.nanos",
+            "created",
+            "extra.0",
+            "extra.1",
+            "extra"
+        ]
+    );
+
+    // messages deriving both are described once, by `EncodableMessage`.
+    #[derive(crate::DecodableMessage, crate::EncodableMessage, Default)]
+    struct Both(#[otopr(1)] u32);
+
+    #[derive(crate::DecodableMessage, Default, crate::EncodableMessage)]
+    struct Separate(#[otopr(1)] Signed<i32>);
+
+    assert_eq!(Both::fields().len(), 1);
+    assert_eq!(Separate::field(1).unwrap().kind, Kind::SInt32);
+}
+
+#[test]
+fn test_proto_file() {
+    use crate::descriptor::FileDescriptorSet;
+    use crate::dynamic::{DescriptorPool, DynamicMessage, MapKey, Value};
+    use crate::reflect::ProtoFile;
+    use crate::well_known::Timestamp;
+    use crate::{Map, Repeated, Signed};
+
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    enum Status {
+        Active = 0,
+        Banned = 2,
+    }
+
+    #[derive(crate::EncodableMessage)]
+    struct Pair(#[otopr(1)] u32, #[otopr(2)] Vec<u8>);
+
+    #[derive(crate::EncodableMessage)]
+    #[otopr(name = "accounts.User")]
+    struct User {
+        #[otopr(1)]
+        name: String,
+        #[otopr(2)]
+        age: Option<u32>,
+        #[otopr(3)]
+        status: Status,
+        #[otopr(4)]
+        ids: Repeated<Vec<i64>>,
+        #[otopr(5)]
+        friend_scores: Map<String, Signed<i64>>,
+        #[otopr(6)]
+        created: Option<Timestamp>,
+        #[otopr(7)]
+        pairs: Repeated<Vec<Pair>>,
+    }
+
+    let file = ProtoFile::new("accounts.proto")
+        .package("accounts")
+        .message::<User>();
+    assert_eq!(
+        file.to_string(),
+        r#"syntax = "proto3";
+
+package accounts;
+
+import "google/protobuf/timestamp.proto";
+
+message User {
+  string name = 1;
+  optional uint32 age = 2;
+  Status status = 3;
+  repeated int64 ids = 4 [packed = false];
+  map<string, sint64> friend_scores = 5;
+  google.protobuf.Timestamp created = 6;
+  repeated Pair pairs = 7;
+}
+
+message Pair {
+  uint32 field_0 = 1;
+  bytes field_1 = 2;
+}
+
+enum Status {
+  Active = 0;
+  Banned = 2;
+}
+"#
+    );
+
+    let timestamp = ProtoFile::new("google/protobuf/timestamp.proto")
+        .package("google.protobuf")
+        .message::<Timestamp>();
+    let set = FileDescriptorSet {
+        file: Repeated::new(vec![
+            timestamp.to_descriptor().unwrap(),
+            file.to_descriptor().unwrap(),
+        ]),
+    };
+    let pool = DescriptorPool::from_file_descriptor_set(&set).unwrap();
+    let schema = pool.get_message("accounts.User").unwrap();
+    assert!(schema.get_field_by_name("friend_scores").unwrap().is_map());
+    assert!(!schema.get_field(4).unwrap().is_packed());
+
+    let mut friend_scores = Map::<String, Signed<i64>>::default();
+    friend_scores.insert("bob".to_owned(), Signed::new(-3));
+    let user = User {
+        name: "alice".to_owned(),
+        age: Some(30),
+        status: Status::Banned,
+        ids: vec![1, 2].into(),
+        friend_scores,
+        created: Some(Timestamp {
+            seconds: 5,
+            nanos: 0,
+        }),
+        pairs: vec![Pair(1, b"a".to_vec())].into(),
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&user, &mut ProtobufSerializer::new(&mut buf));
+    let message = DynamicMessage::decode(schema, buf.as_slice()).unwrap();
+    assert_eq!(message.get_by_name("age"), Some(&Value::U32(30)));
+    assert_eq!(message.get_by_name("status"), Some(&Value::EnumNumber(2)));
+    assert_eq!(
+        message.get_by_name("ids"),
+        Some(&Value::List(vec![Value::I64(1), Value::I64(2)]))
+    );
+    match message.get_by_name("friend_scores") {
+        Some(Value::Map(entries)) => {
+            assert_eq!(
+                entries.get(&MapKey::String("bob".to_owned())),
+                Some(&Value::I64(-3))
+            )
+        }
+        v => panic!("unexpected value {:?}", v),
+    }
+    match message.get_by_name("created") {
+        Some(Value::Message(created)) => {
+            assert_eq!(created.get_by_name("seconds"), Some(&Value::I64(5)))
+        }
+        v => panic!("unexpected value {:?}", v),
+    }
+
+    // enum values are scoped to the package, so values of the same name collide.
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    enum Role {
+        Active = 0,
+        Admin = 1,
+    }
+
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    enum Visibility {
+        #[otopr(name = "VISIBILITY_ACTIVE")]
+        Active = 0,
+    }
+
+    #[derive(crate::EncodableMessage)]
+    struct Member {
+        #[otopr(1)]
+        status: Status,
+        #[otopr(2)]
+        role: Role,
+        #[otopr(3)]
+        visibility: Visibility,
+    }
+
+    let collision = crate::reflect::EnumValueCollision {
+        value: "Active",
+        enums: ["Status", "Role"],
+    };
+    let members = ProtoFile::new("members.proto").message::<Member>();
+    assert_eq!(members.check(), Err(collision.clone()));
+    assert_eq!(members.to_descriptor(), Err(collision.clone()));
+    assert_eq!(members.to_proto_string(), Err(collision));
+    assert!(members.to_string().contains("enum Role {\n  Active = 0;"));
+    assert!(ProtoFile::new("members.proto")
+        .message::<Pair>()
+        .check()
+        .is_ok());
+}
+
+#[test]
+fn test_reserved_field_numbers() -> crate::decoding::Result<()> {
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    #[otopr(reserved(2, 4..=6, 8..10))]
+    struct Item {
+        #[otopr(1)]
+        name: String,
+        #[otopr(3)]
+        count: u32,
+        #[otopr(7)]
+        price: i64,
+        #[otopr(10)]
+        sold: bool,
+    }
+
+    let item = Item {
+        name: "apple".to_owned(),
+        count: 3,
+        price: 120,
+        sold: true,
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&item, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(buf, b"\x0a\x05apple\x18\x03\x38\x78\x50\x01");
+    let decoded: Item = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded, item);
+    Ok(())
+}
+
+#[test]
+fn test_with_conversion() {
+    use crate::decoding::DecodingError;
+    use crate::reflect::{Kind, MessageDescriptor};
+    use crate::well_known::Timestamp;
+    use std::net::IpAddr;
+    use std::time::{Duration, SystemTime};
+
+    mod system_time {
+        use crate::well_known::{ConversionError, Timestamp};
+        use std::convert::TryFrom;
+        use std::time::SystemTime;
+
+        pub fn to_proto(time: &SystemTime) -> Timestamp {
+            Timestamp::from(*time)
+        }
+
+        pub fn from_proto(timestamp: Timestamp) -> Result<SystemTime, ConversionError> {
+            SystemTime::try_from(timestamp)
+        }
+    }
+
+    mod ip_addr {
+        use std::net::{AddrParseError, IpAddr};
+
+        pub fn to_proto(addr: &IpAddr) -> String {
+            addr.to_string()
+        }
+
+        pub fn from_proto(s: String) -> Result<IpAddr, AddrParseError> {
+            s.parse()
+        }
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Debug, PartialEq)]
+    struct Login {
+        #[otopr(1)]
+        #[otopr(with(Timestamp, system_time))]
+        at: SystemTime,
+        #[otopr(2)]
+        #[otopr(with(String, ip_addr))]
+        from: IpAddr,
+    }
+
+    impl Default for Login {
+        fn default() -> Self {
+            Self {
+                at: SystemTime::UNIX_EPOCH,
+                from: IpAddr::from([0, 0, 0, 0]),
+            }
+        }
+    }
+
+    let login = Login {
+        at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        from: IpAddr::from([10, 0, 0, 1]),
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&login, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&login));
+    let decoded: Login =
+        DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice())).unwrap();
+    assert_eq!(decoded, login);
+    assert!(
+        matches!(Login::field(1).unwrap().kind, Kind::Message(m) if m.name == "google.protobuf.Timestamp")
+    );
+    assert_eq!(Login::field(2).unwrap().kind, Kind::String);
+
+    let res: crate::decoding::Result<Login> =
+        DecodableMessage::decode(&mut Deserializer::new(&mut &b"\x12\x03foo"[..]));
+    match res {
+        Err(DecodingError::Conversion(e)) => assert_eq!(e.to_string(), "invalid IP address syntax"),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn test_scalar_attributes() -> crate::decoding::Result<()> {
+    use crate::reflect::{Cardinality, Kind, MessageDescriptor};
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Reading {
+        #[otopr(1, sint32)]
+        delta: i32,
+        #[otopr(2, fixed64)]
+        id: u64,
+        #[otopr(3, packed)]
+        samples: Vec<i64>,
+        #[otopr(4, sint32, packed)]
+        deltas: Vec<i32>,
+        #[otopr(5, sfixed32)]
+        offset: Option<i32>,
+        #[otopr(6, fixed32)]
+        flags: Vec<u32>,
+    }
+
+    let reading = Reading {
+        delta: -2,
+        id: 7,
+        samples: vec![1, 300],
+        deltas: vec![-1, 1],
+        offset: Some(-1),
+        flags: vec![1, 2],
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&reading, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(
+        buf,
+        [
+            &b"\x08\x03"[..],
+            b"\x11\x07\0\0\0\0\0\0\0",
+            b"\x1a\x03\x01\xac\x02",
+            b"\x22\x02\x01\x02",
+            b"\x2d\xff\xff\xff\xff",
+            b"\x35\x01\0\0\0\x35\x02\0\0\0",
+        ]
+        .concat()
+    );
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&reading));
+    let decoded: Reading = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded, reading);
+
+    // packed fields accept unpacked values and the other way around.
+    let decoded: Reading = DecodableMessage::decode(&mut Deserializer::new(
+        &mut &b"\x18\x01\x18\x02\x32\x04\x03\0\0\0"[..],
+    ))?;
+    assert_eq!(decoded.samples, [1, 2]);
+    assert_eq!(decoded.flags, [3]);
+
+    let deltas = Reading::field(4).unwrap();
+    assert_eq!(
+        (deltas.kind, deltas.cardinality, deltas.packed),
+        (Kind::SInt32, Cardinality::Repeated, true)
+    );
+    assert_eq!(Reading::field(5).unwrap().kind, Kind::SFixed32);
+    assert_eq!(
+        Reading::field(5).unwrap().cardinality,
+        Cardinality::Optional
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generic_decode() -> crate::decoding::Result<()> {
+    use crate::{Message, Repeated};
+    use std::str::FromStr;
+
+    #[derive(crate::DecodableMessage, Default)]
+    struct Item {
+        id: u32,
+    }
+
+    #[derive(crate::DecodableMessage, Default)]
+    struct Page<'a, T> {
+        items: Repeated<Vec<T>>,
+        cursor: &'a str,
+    }
+
+    let mut buf = &b"\x0a\x02\x08\x01\x0a\x02\x08\x02\x12\x04next"[..];
+    let page: Page<Message<Item>> =
+        DecodableMessage::decode(&mut Deserializer::borrowed(&mut buf))?;
+    let ids: Vec<_> = page
+        .items
+        .into_inner()
+        .into_iter()
+        .map(|m| m.into_inner().id)
+        .collect();
+    assert_eq!(ids, [1, 2]);
+    assert_eq!(page.cursor, "next");
+
+    mod parse {
+        use std::str::FromStr;
+
+        pub fn from_proto<T: FromStr>(s: String) -> Result<T, T::Err> {
+            s.parse()
+        }
+    }
+
+    #[derive(crate::DecodableMessage, Default, Debug)]
+    #[otopr(decode_where_clause(where T: FromStr, T::Err: Into<Box<dyn std::error::Error + Send + Sync>>))]
+    struct Parsed<T> {
+        #[otopr(with(String, parse))]
+        value: T,
+    }
+
+    let parsed: Parsed<f64> =
+        DecodableMessage::decode(&mut Deserializer::new(&mut &b"\x0a\x032.5"[..]))?;
+    assert_eq!(parsed.value, 2.5);
+    assert!(
+        <Parsed<f64> as DecodableMessage>::decode(&mut Deserializer::new(&mut &b"\x0a\x01x"[..]))
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_skip_and_default() -> crate::decoding::Result<()> {
+    use std::cell::Cell;
+
+    fn default_port() -> u32 {
+        80
+    }
+
+    #[derive(
+        crate::EncodableMessage,
+        crate::DecodableMessage,
+        crate::text_format::TextFormat,
+        Default,
+        Debug,
+    )]
+    #[cfg_attr(feature = "json", derive(crate::json::ProtoJson))]
+    struct Endpoint {
+        host: String,
+        #[otopr(default = "default_port")]
+        port: u32,
+        #[otopr(skip)]
+        lookups: Cell<u32>,
+        #[otopr(skip, default = String::from("local"))]
+        origin: String,
+        retries: i32,
+        #[otopr(default = -1)]
+        weight: i64,
+    }
+
+    let endpoint = Endpoint {
+        host: "a".to_owned(),
+        port: 0,
+        lookups: Cell::new(3),
+        origin: "remote".to_owned(),
+        retries: 2,
+        weight: 7,
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&endpoint, &mut ProtobufSerializer::new(&mut buf));
+    // skipped fields take no field numbers, and fields equal to zero are still written.
+    assert_eq!(buf, b"\x0a\x01a\x10\x00\x18\x02\x20\x07");
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&endpoint));
+
+    let decoded: Endpoint = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(
+        (decoded.port, decoded.lookups.get(), &*decoded.origin),
+        (0, 0, "local")
+    );
+
+    let missing: Endpoint =
+        DecodableMessage::decode(&mut Deserializer::new(&mut &b"\x18\x05"[..]))?;
+    assert_eq!((missing.port, missing.retries, missing.weight), (80, 5, -1));
+    assert_eq!(
+        <Endpoint as crate::reflect::MessageDescriptor>::FIELDS.len(),
+        4
+    );
+
+    // the text format and JSON use the defaults too, and print zero for fields with a default.
+    let text = crate::text_format::to_string(&endpoint);
+    assert!(text.contains("port: 0\n"));
+    let parsed: Endpoint = crate::text_format::from_str(&text).unwrap();
+    assert_eq!(
+        (parsed.port, parsed.weight, &*parsed.origin),
+        (0, 7, "local")
+    );
+    let parsed: Endpoint = crate::text_format::from_str("host: \"b\"").unwrap();
+    assert_eq!(
+        (parsed.port, parsed.weight, &*parsed.origin),
+        (80, -1, "local")
+    );
+
+    #[cfg(feature = "json")]
+    {
+        use crate::json::{self, ParseOptions, PrintOptions};
+
+        let json = json::to_string(&endpoint, &PrintOptions::default());
+        let parsed: Endpoint = json::from_str(&json, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            (parsed.port, parsed.weight, &*parsed.origin),
+            (0, 7, "local")
+        );
+        let parsed: Endpoint = json::from_str("{}", &ParseOptions::default()).unwrap();
+        assert_eq!(
+            (parsed.port, parsed.weight, &*parsed.origin),
+            (80, -1, "local")
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_open_and_closed_enums() -> crate::decoding::Result<()> {
+    use crate::decoding::DecodingError;
+    use crate::Enumeration;
+
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    #[repr(i32)]
+    enum Status {
+        Unspecified = 0,
+        Active = 1,
+        #[otopr(unknown)]
+        Unknown(i32),
+    }
+
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    #[otopr(closed)]
+    enum Mode {
+        Off = 0,
+        On = 1,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Device {
+        status: Status,
+        mode: Mode,
+    }
+
+    // values from newer producers are kept and encoded again as they were.
+    let mut buf = b"\x08\x07\x10\x01".to_vec();
+    let device: Device = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(device.status, Status::Unknown(7));
+    assert_eq!(device.mode, Mode::On);
+    let mut encoded = Vec::new();
+    EncodableMessage::encode(&device, &mut ProtobufSerializer::new(&mut encoded));
+    assert_eq!(encoded, buf);
+
+    let negative = Device {
+        status: Status::Unknown(-2),
+        mode: Mode::Off,
+    };
+    buf.clear();
+    EncodableMessage::encode(&negative, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&negative));
+    let decoded: Device = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded, negative);
+
+    assert_eq!(Status::from_i32(9), Some(Status::Unknown(9)));
+    assert_eq!(Status::Unknown(9).to_i32(), 9);
+    assert_eq!(Status::Unknown(9).name(), None);
+
+    let res = <Device as DecodableMessage>::decode(&mut Deserializer::new(&mut &b"\x10\x05"[..]));
+    assert!(matches!(res, Err(DecodingError::UnknownEnumValue(5))));
+    Ok(())
+}
+
+#[test]
+fn test_enum_names_and_aliases() -> crate::decoding::Result<()> {
+    use crate::reflect::ProtoFile;
+    use crate::Enumeration;
+
+    // aliases are associated consts, so no variant gets an implicit discriminant.
+    #[derive(crate::Enumeration, Debug, PartialEq)]
+    #[otopr(allow_alias, alias(STARTED = Running, name = "PHASE_STARTED"))]
+    enum Phase {
+        #[otopr(name = "PHASE_FAILED")]
+        Failed = -1,
+        #[otopr(name = "PHASE_UNSPECIFIED")]
+        Unspecified = 0,
+        #[otopr(name = "PHASE_RUNNING")]
+        Running = 1,
+        #[otopr(name = "PHASE_DONE")]
+        Done = 2,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Job {
+        phase: Phase,
+    }
+
+    // negative values are sign-extended to ten bytes.
+    let failed = Job {
+        phase: Phase::Failed,
+    };
+    let mut buf = Vec::new();
+    EncodableMessage::encode(&failed, &mut ProtobufSerializer::new(&mut buf));
+    assert_eq!(buf, b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01");
+    assert_eq!(buf.len(), EncodableMessage::encoded_size(&failed));
+    let decoded: Job = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded, failed);
+
+    // aliases encode as their canonical value, which is what decoding produces.
+    buf.clear();
+    EncodableMessage::encode(
+        &Job {
+            phase: Phase::STARTED,
+        },
+        &mut ProtobufSerializer::new(&mut buf),
+    );
+    assert_eq!(buf, b"\x08\x01");
+    let decoded: Job = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded.phase, Phase::Running);
+    assert_eq!(Phase::from_i32(1), Some(Phase::Running));
+    assert_eq!(Phase::STARTED, Phase::Running);
+    assert_eq!(Phase::Done as i32, 2);
+    assert_eq!(Phase::Done.to_i32(), 2);
+
+    assert_eq!(Phase::Failed.as_str_name(), Some("PHASE_FAILED"));
+    assert_eq!(Phase::from_str_name("PHASE_STARTED"), Some(Phase::Running));
+    assert_eq!(Phase::from_name("Running"), None);
+
+    let file = ProtoFile::new("job.proto").message::<Job>();
+    assert!(file.to_string().contains(
+        "enum Phase {\n  option allow_alias = true;\n  PHASE_UNSPECIFIED = 0;\n  PHASE_FAILED = -1;\n  PHASE_RUNNING = 1;\n  PHASE_DONE = 2;\n  PHASE_STARTED = 1;\n}\n"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_field_numbers() {
+    #[derive(crate::EncodableMessage)]
+    struct Order {
+        id: u64,
+        #[otopr(5)]
+        unit_price: u32,
+        #[otopr(skip)]
+        _cached: u32,
+        quantity: u32,
+    }
+
+    #[derive(crate::EncodableMessage)]
+    struct Pair(#[otopr(2)] u32, u32);
+
+    assert_eq!(
+        (
+            Order::FIELD_ID,
+            Order::FIELD_UNIT_PRICE,
+            Order::FIELD_QUANTITY
+        ),
+        (1, 5, 6)
+    );
+    assert_eq!(
+        OrderFieldNumber::UnitPrice.number(),
+        Order::FIELD_UNIT_PRICE
+    );
+    assert_eq!(
+        OrderFieldNumber::from_number(6),
+        Some(OrderFieldNumber::Quantity)
+    );
+    assert_eq!(OrderFieldNumber::from_number(2), None);
+    assert_eq!(OrderFieldNumber::UnitPrice.name(), "unit_price");
+
+    assert_eq!((Pair::FIELD_0, Pair::FIELD_1), (2, 3));
+    assert_eq!(PairFieldNumber::Field1.number(), 3);
+
+    // messages deriving both get them once.
+    #[derive(crate::DecodableMessage, crate::EncodableMessage, Default)]
+    struct Reply {
+        #[otopr(7)]
+        display_name: String,
+    }
+
+    assert_eq!(Reply::FIELD_DISPLAY_NAME, 7);
+    assert_eq!(
+        ReplyFieldNumber::from_number(7),
+        Some(ReplyFieldNumber::DisplayName)
+    );
+}
+
+#[test]
+fn test_mask() -> crate::decoding::Result<()> {
+    use crate::mask::{Mask, UnknownPath};
+    use crate::well_known::FieldMask;
+    use crate::Repeated;
+
+    #[derive(
+        crate::EncodableMessage, crate::DecodableMessage, Clone, Default, Debug, PartialEq,
+    )]
+    struct Customer {
+        name: String,
+        email: String,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug, PartialEq)]
+    struct Order {
+        id: u64,
+        note: String,
+        customer: Option<Customer>,
+        contacts: Repeated<Vec<Customer>>,
+    }
+
+    let customer = || Customer {
+        name: "Ada".to_owned(),
+        email: "ada@example.com".to_owned(),
+    };
+    let order = Order {
+        id: 7,
+        note: "fragile".to_owned(),
+        customer: Some(customer()),
+        contacts: Repeated::new(vec![customer(), customer()]),
+    };
+    let mask = Mask::from_paths::<Order, _>(["id", "customer.name", "contacts.email"]).unwrap();
+    assert_eq!(
+        mask,
+        Mask::new()
+            .field(1)
+            .submessage(3, Mask::new().field(1))
+            .submessage(4, Mask::new().field(2))
+    );
+
+    let mut buf = vec![];
+    EncodableMessage::encode_masked(&order, &mut ProtobufSerializer::new(&mut buf), &mask);
+    assert_eq!(
+        EncodableMessage::encoded_size_masked(&order, &mask),
+        buf.len()
+    );
+    let partial: Order = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    let expected = Order {
+        id: 7,
+        customer: Some(Customer {
+            name: "Ada".to_owned(),
+            ..Default::default()
+        }),
+        contacts: Repeated::new(vec![
+            Customer {
+                email: "ada@example.com".to_owned(),
+                ..Default::default()
+            };
+            2
+        ]),
+        ..Default::default()
+    };
+    assert_eq!(partial, expected);
+
+    // decoding the whole message with the mask gives the same fields.
+    buf.clear();
+    EncodableMessage::encode(&order, &mut ProtobufSerializer::new(&mut buf));
+    let decoded: Order =
+        DecodableMessage::decode_masked(&mut Deserializer::new(&mut buf.as_slice()), &mask)?;
+    assert_eq!(decoded, expected);
+
+    let whole = Mask::from_field_mask::<Order>(&FieldMask {
+        paths: Repeated::new(vec!["customer".to_owned()]),
+    })
+    .unwrap();
+    let decoded: Order =
+        DecodableMessage::decode_masked(&mut Deserializer::new(&mut buf.as_slice()), &whole)?;
+    assert_eq!(decoded.customer, order.customer);
+    assert_eq!(decoded.id, 0);
+
+    // borrowed submessages are masked too.
+    #[derive(crate::EncodableMessage)]
+    struct Shipment<'a> {
+        #[otopr(3)]
+        customer: std::borrow::Cow<'a, Customer>,
+    }
+
+    let ada = customer();
+    let shipment = Shipment {
+        customer: std::borrow::Cow::Borrowed(&ada),
+    };
+    let mask = Mask::new().submessage(3, Mask::new().field(1));
+    buf.clear();
+    EncodableMessage::encode_masked(&shipment, &mut ProtobufSerializer::new(&mut buf), &mask);
+    assert_eq!(
+        EncodableMessage::encoded_size_masked(&shipment, &mask),
+        buf.len()
+    );
+    let decoded: Order = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(decoded.customer, expected.customer);
+
+    assert_eq!(
+        Mask::from_paths::<Order, _>(["customer.phone"]),
+        Err(UnknownPath("customer.phone".to_owned()))
+    );
+    assert_eq!(
+        Mask::from_paths::<Order, _>(["id.value"]),
+        Err(UnknownPath("id.value".to_owned()))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_lazy_message() -> crate::decoding::Result<()> {
+    use crate::decoding::Decodable;
+    use crate::LazyMessage;
+
+    #[derive(
+        crate::EncodableMessage, crate::DecodableMessage, Clone, Default, Debug, PartialEq,
+    )]
+    struct Payload {
+        body: String,
+        size: u32,
+    }
+
+    #[derive(crate::EncodableMessage, crate::DecodableMessage, Default, Debug)]
+    struct Envelope {
+        route: String,
+        payload: LazyMessage<Payload>,
+    }
+
+    // an unknown field in the payload is kept until it is changed.
+    let mut payload = vec![];
+    EncodableMessage::encode(
+        &Payload {
+            body: "hello".to_owned(),
+            size: 5,
+        },
+        &mut ProtobufSerializer::new(&mut payload),
+    );
+    payload.extend_from_slice(b"\x18\x01");
+    let mut buf = b"\x0a\x06orders\x12".to_vec();
+    buf.push(payload.len() as u8);
+    buf.extend_from_slice(&payload);
+
+    let mut envelope: Envelope =
+        DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    assert_eq!(envelope.route, "orders");
+    assert!(!envelope.payload.is_decoded());
+
+    let mut encoded = vec![];
+    EncodableMessage::encode(&envelope, &mut ProtobufSerializer::new(&mut encoded));
+    assert_eq!(encoded, buf);
+    assert_eq!(EncodableMessage::encoded_size(&envelope), buf.len());
+
+    assert_eq!(envelope.payload.get()?.body, "hello");
+    assert!(envelope.payload.is_decoded());
+    assert!(envelope.payload.raw().is_some());
+
+    envelope.payload.get_mut()?.size = 6;
+    assert!(envelope.payload.raw().is_none());
+    encoded.clear();
+    EncodableMessage::encode(&envelope, &mut ProtobufSerializer::new(&mut encoded));
+    let decoded: Envelope =
+        DecodableMessage::decode(&mut Deserializer::new(&mut encoded.as_slice()))?;
+    assert_eq!(
+        decoded.payload.into_inner()?,
+        Payload {
+            body: "hello".to_owned(),
+            size: 6,
+        }
+    );
+
+    // a payload that appears twice is merged.
+    buf.extend_from_slice(b"\x12\x02\x10\x07");
+    let merged: Envelope = DecodableMessage::decode(&mut Deserializer::new(&mut buf.as_slice()))?;
+    let payload = merged.payload.get()?;
+    assert_eq!((payload.body.as_str(), payload.size), ("hello", 7));
+
+    // so is a changed payload, whose decoded value is merged with the new one.
+    let bytes = |b: &'static [u8]| LazyMessage::from_raw(bytes::Bytes::from_static(b));
+    let mut changed = envelope.payload.clone();
+    Decodable::merge(&mut changed, bytes(b"\x10\x08"));
+    let payload = changed.get()?;
+    assert_eq!((payload.body.as_str(), payload.size), ("hello", 8));
+    // bytes that fail to decode are appended to the changed payload, which is kept.
+    Decodable::merge(&mut changed, bytes(b"\x10"));
+    assert!(changed.get().is_err());
+    assert_eq!(
+        changed.raw().map(|raw| &raw[..]),
+        Some(&b"\x0a\x05hello\x10\x08\x10"[..])
+    );
+
+    assert_eq!(
+        LazyMessage::<Payload>::default().into_inner()?,
+        Payload::default()
+    );
+    Ok(())
+}
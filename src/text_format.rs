@@ -0,0 +1,759 @@
+//! The protobuf text format, as in `name: "x" nested { id: 1 }`.
+//!
+//! Messages implement [`TextMessage`] and [`TextFormat`] with `#[derive(TextFormat)]`, which
+//! accepts both field names and field numbers when parsing. Enumerations implement
+//! [`TextFormat`] through [`Enumeration`].
+
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+use bytes::Bytes;
+
+pub use otopr_derive::TextFormat;
+
+use crate::traits::Enumeration;
+use crate::{BytesStr, Fixed32, Fixed64, Map, Message, Repeated, Signed};
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub type Result<T, E = ParseError> = std::result::Result<T, E>;
+
+/// A message in text format, printed as a list of fields.
+pub trait TextMessage {
+    fn print_fields(&self, p: &mut Printer);
+
+    /// Parses the field with the given name, after the name has been consumed.
+    fn merge_field(&mut self, name: &str, p: &mut Parser<'_>) -> Result<()>;
+
+    /// Sets fields whose value when missing is not their `Default`, before any field is parsed.
+    fn init_defaults(&mut self) {}
+}
+
+/// A type that can be the value of a field in text format.
+pub trait TextFormat: Sized {
+    /// Whether this is printed as a `{ ... }` block rather than a scalar.
+    const MESSAGE: bool = false;
+
+    fn print_value(&self, p: &mut Printer);
+
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self>;
+
+    /// Prints this field, unless it has the default value.
+    fn print_field(&self, name: &str, p: &mut Printer) {
+        if !self.is_default() {
+            p.field(name, self);
+        }
+    }
+
+    /// Parses the value of this field after its name, replacing the current value.
+    fn parse_field(&mut self, p: &mut Parser<'_>) -> Result<()> {
+        *self = p.field_value()?;
+        Ok(())
+    }
+
+    fn is_default(&self) -> bool {
+        false
+    }
+}
+
+/// Prints a message in text format.
+pub fn to_string<T: TextMessage>(message: &T) -> String {
+    let mut p = Printer::default();
+    message.print_fields(&mut p);
+    p.finish()
+}
+
+/// Parses a message from text format.
+pub fn from_str<T: TextMessage + Default>(s: &str) -> Result<T> {
+    let mut p = Parser::new(s);
+    let mut message = T::default();
+    message.init_defaults();
+    p.merge_fields(&mut message, None)?;
+    Ok(message)
+}
+
+#[derive(Default)]
+pub struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    /// Writes `name: value` or `name { ... }` on its own line.
+    pub fn field<T: TextFormat>(&mut self, name: &str, value: &T) {
+        self.field_with(name, T::MESSAGE, |p| value.print_value(p))
+    }
+
+    /// Like [`Printer::field`], with the value written by `print_value`.
+    pub fn field_with(&mut self, name: &str, message: bool, print_value: impl FnOnce(&mut Self)) {
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(name);
+        self.out.push_str(if message { " " } else { ": " });
+        print_value(self);
+        self.out.push('\n');
+    }
+
+    /// Writes a `{ ... }` block containing the fields of `message`.
+    pub fn message<T: TextMessage + ?Sized>(&mut self, message: &T) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        message.print_fields(self);
+        self.indent -= 1;
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push('}');
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    /// Writes a quoted string, escaping everything that is not printable ASCII or UTF-8.
+    pub fn write_quoted(&mut self, bytes: &[u8]) {
+        write_quoted(&mut self.out, bytes);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+/// Writes `bytes` as a double quoted string with C escapes, like `protoc` does.
+pub(crate) fn write_quoted(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '"' => out.push_str("\\\""),
+                '\'' => out.push_str("\\'"),
+                '\\' => out.push_str("\\\\"),
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        let _ = write!(out, "\\{:03o}", b);
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            let _ = write!(out, "\\{:03o}", b);
+        }
+    }
+    out.push('"');
+}
+
+pub struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    pub fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        let before = &self.input[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |n| n + 1) + 1;
+        Err(ParseError {
+            line,
+            column,
+            message: message.into(),
+        })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with('#') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest().is_empty()
+    }
+
+    /// Consumes `c` if it is the next character.
+    pub fn try_consume(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, c: char) -> Result<()> {
+        if self.try_consume(c) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", c))
+        }
+    }
+
+    /// Consumes the `:` after a field name, which is optional before messages.
+    pub fn field_separator(&mut self, message: bool) -> Result<()> {
+        if self.try_consume(':') || message {
+            Ok(())
+        } else {
+            self.error("expected `:`")
+        }
+    }
+
+    /// Parses the `:` and value of a field after its name.
+    pub fn field_value<T: TextFormat>(&mut self) -> Result<T> {
+        self.field_separator(T::MESSAGE)?;
+        T::parse_value(self)
+    }
+
+    /// Reads an identifier, a number or another unquoted token.
+    pub fn token(&mut self) -> Result<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return self.error("expected a value");
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Parses a token with `FromStr`, also accepting hexadecimal and octal integers.
+    pub fn parse_token<T: FromStr>(&mut self, what: &str) -> Result<T> {
+        let start = self.pos;
+        let token = self.token()?;
+        if let Ok(t) = token.parse() {
+            return Ok(t);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let radix = if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            u128::from_str_radix(hex, 16).ok()
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            u128::from_str_radix(&digits[1..], 8).ok()
+        } else {
+            None
+        };
+        let res = radix.and_then(|n| {
+            let sign = if negative { "-" } else { "" };
+            format!("{}{}", sign, n).parse().ok()
+        });
+        res.map_or_else(
+            || {
+                self.pos = start;
+                self.error(format!("expected {}, found `{}`", what, token))
+            },
+            Ok,
+        )
+    }
+
+    /// Reads one or more adjacent quoted strings.
+    pub fn string(&mut self) -> Result<Vec<u8>> {
+        self.skip_whitespace();
+        if !self.rest().starts_with(['"', '\'']) {
+            return self.error("expected a string");
+        }
+        let mut out = Vec::new();
+        while let Some(quote) = self
+            .rest()
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+        {
+            self.pos += 1;
+            loop {
+                let mut chars = self.rest().chars();
+                let c = match chars.next() {
+                    Some('\n') | None => return self.error("unterminated string"),
+                    Some(c) => c,
+                };
+                self.pos += c.len_utf8();
+                if c == quote {
+                    break;
+                } else if c != '\\' {
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    continue;
+                }
+                let escaped = chars
+                    .next()
+                    .map_or_else(|| self.error("unterminated string"), Ok)?;
+                self.pos += escaped.len_utf8();
+                match escaped {
+                    'n' => out.push(b'\n'),
+                    'r' => out.push(b'\r'),
+                    't' => out.push(b'\t'),
+                    'a' => out.push(7),
+                    'b' => out.push(8),
+                    'f' => out.push(12),
+                    'v' => out.push(11),
+                    '\\' | '\'' | '"' | '?' => out.push(escaped as u8),
+                    'x' => {
+                        let hex = self.rest();
+                        let len = hex
+                            .chars()
+                            .take(2)
+                            .take_while(char::is_ascii_hexdigit)
+                            .count();
+                        match u8::from_str_radix(&hex[..len], 16) {
+                            Ok(b) => out.push(b),
+                            Err(_) => return self.error("invalid hex escape"),
+                        }
+                        self.pos += len;
+                    }
+                    '0'..='7' => {
+                        let oct = &self.input[self.pos - 1..];
+                        let len = oct
+                            .chars()
+                            .take(3)
+                            .take_while(|c| matches!(c, '0'..='7'))
+                            .count();
+                        match u8::from_str_radix(&oct[..len], 8) {
+                            Ok(b) => out.push(b),
+                            Err(_) => return self.error("invalid octal escape"),
+                        }
+                        self.pos += len - 1;
+                    }
+                    c => return self.error(format!("unknown escape `\\{}`", c)),
+                }
+            }
+            self.skip_whitespace();
+        }
+        Ok(out)
+    }
+
+    /// Parses fields into `message` until `close` or the end of the input if `close` is `None`.
+    pub fn merge_fields<T: TextMessage + ?Sized>(
+        &mut self,
+        message: &mut T,
+        close: Option<char>,
+    ) -> Result<()> {
+        loop {
+            match close {
+                Some(close) if self.try_consume(close) => return Ok(()),
+                Some(close) if self.at_end() => return self.error(format!("expected `{}`", close)),
+                None if self.at_end() => return Ok(()),
+                _ => {}
+            }
+            let name = self.token()?;
+            message.merge_field(name, self)?;
+            let _ = self.try_consume(',') || self.try_consume(';');
+        }
+    }
+
+    /// Parses a `{ ... }` or `< ... >` block into `message`.
+    pub fn message<T: TextMessage + ?Sized>(&mut self, message: &mut T) -> Result<()> {
+        if self.try_consume('{') {
+            self.merge_fields(message, Some('}'))
+        } else if self.try_consume('<') {
+            self.merge_fields(message, Some('>'))
+        } else {
+            self.error("expected `{`")
+        }
+    }
+
+    /// Fails with an error about an unknown field.
+    pub fn unknown_field<T>(&self, name: &str) -> Result<T> {
+        self.error(format!("unknown field `{}`", name))
+    }
+}
+
+macro_rules! text_int {
+    ($($ty:ty),*) => {$(
+        impl TextFormat for $ty {
+            fn print_value(&self, p: &mut Printer) {
+                p.write_str(&self.to_string())
+            }
+            fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+                p.parse_token("an integer")
+            }
+            fn is_default(&self) -> bool {
+                *self == 0
+            }
+        }
+    )*};
+}
+
+text_int!(i32, i64, u32, u64, u16, u8, usize);
+
+impl TextFormat for bool {
+    fn print_value(&self, p: &mut Printer) {
+        p.write_str(if *self { "true" } else { "false" })
+    }
+
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+        match p.token()? {
+            "true" | "True" | "t" | "1" => Ok(true),
+            "false" | "False" | "f" | "0" => Ok(false),
+            token => p.error(format!("expected a boolean, found `{}`", token)),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        !*self
+    }
+}
+
+macro_rules! text_float {
+    ($($ty:ty),*) => {$(
+        impl TextFormat for $ty {
+            fn print_value(&self, p: &mut Printer) {
+                if self.is_nan() {
+                    p.write_str("nan")
+                } else if self.is_infinite() {
+                    p.write_str(if *self > 0.0 { "inf" } else { "-inf" })
+                } else {
+                    p.write_str(&self.to_string())
+                }
+            }
+            fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+                let start = p.pos;
+                let token = p.token()?;
+                let (negative, abs) = match token.strip_prefix('-') {
+                    Some(abs) => (true, abs),
+                    None => (false, token),
+                };
+                let res = match abs.to_ascii_lowercase().as_str() {
+                    "inf" | "infinity" => Ok(<$ty>::INFINITY),
+                    "nan" => Ok(<$ty>::NAN),
+                    // numbers may have an `f` suffix, like `1.5f`.
+                    _ => abs.strip_suffix(['f', 'F']).unwrap_or(abs).parse::<$ty>(),
+                };
+                match res {
+                    Ok(n) if negative => Ok(-n),
+                    Ok(n) => Ok(n),
+                    Err(_) => {
+                        p.pos = start;
+                        p.error(format!("expected a number, found `{}`", token))
+                    }
+                }
+            }
+            fn is_default(&self) -> bool {
+                *self == 0.0 && self.is_sign_positive()
+            }
+        }
+    )*};
+}
+
+text_float!(f32, f64);
+
+macro_rules! text_wrapper {
+    ($($ty:ty => $inner:ty, $get:expr, $new:expr;)*) => {$(
+        impl TextFormat for $ty {
+            fn print_value(&self, p: &mut Printer) {
+                $get(self).print_value(p)
+            }
+            fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+                <$inner>::parse_value(p).map($new)
+            }
+            fn is_default(&self) -> bool {
+                $get(self).is_default()
+            }
+        }
+    )*};
+}
+
+text_wrapper! {
+    Fixed32 => u32, |n: &Fixed32| n.get(), Fixed32::new;
+    Fixed64 => u64, |n: &Fixed64| n.get(), Fixed64::new;
+    Signed<i32> => i32, Signed::get, Signed::new;
+    Signed<i64> => i64, Signed::get, Signed::new;
+    Signed<Fixed32> => i32, Signed::get, Signed::new;
+    Signed<Fixed64> => i64, Signed::get, Signed::new;
+}
+
+fn utf8(p: &Parser<'_>, bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).or_else(|e| p.error(e.utf8_error().to_string()))
+}
+
+macro_rules! text_string {
+    ($($ty:ty),*) => {$(
+        impl TextFormat for $ty {
+            fn print_value(&self, p: &mut Printer) {
+                p.write_quoted(self.as_bytes())
+            }
+            fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+                let bytes = p.string()?;
+                utf8(p, bytes).map(Into::into)
+            }
+            fn is_default(&self) -> bool {
+                self.is_empty()
+            }
+        }
+    )*};
+}
+
+text_string!(String, Box<str>, BytesStr, Cow<'_, str>);
+
+macro_rules! text_bytes {
+    ($($ty:ty),*) => {$(
+        impl TextFormat for $ty {
+            fn print_value(&self, p: &mut Printer) {
+                p.write_quoted(self)
+            }
+            fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+                p.string().map(Into::into)
+            }
+            fn is_default(&self) -> bool {
+                self.is_empty()
+            }
+        }
+    )*};
+}
+
+text_bytes!(Vec<u8>, Box<[u8]>, Bytes, Cow<'_, [u8]>);
+
+impl<T: Enumeration> TextFormat for T {
+    fn print_value(&self, p: &mut Printer) {
+        match self.name() {
+            Some(name) => p.write_str(name),
+            None => p.write_str(&self.to_i32().to_string()),
+        }
+    }
+
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+        let start = p.pos;
+        let token = p.token()?;
+        let res = match token.parse() {
+            Ok(n) => T::from_i32(n),
+            Err(_) => T::from_name(token),
+        };
+        res.map_or_else(
+            || {
+                p.pos = start;
+                p.error(format!("unknown enum value `{}`", token))
+            },
+            Ok,
+        )
+    }
+
+    fn is_default(&self) -> bool {
+        self.to_i32() == 0
+    }
+}
+
+impl<T: TextMessage + Default> TextFormat for Message<T> {
+    const MESSAGE: bool = true;
+
+    fn print_value(&self, p: &mut Printer) {
+        p.message(&self.0)
+    }
+
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+        let mut message = T::default();
+        message.init_defaults();
+        p.message(&mut message)?;
+        Ok(Message(message))
+    }
+
+    /// Merges into the current value like the binary format does.
+    fn parse_field(&mut self, p: &mut Parser<'_>) -> Result<()> {
+        p.field_separator(true)?;
+        p.message(&mut self.0)
+    }
+
+    fn print_field(&self, name: &str, p: &mut Printer) {
+        p.field(name, self)
+    }
+}
+
+impl<C> TextFormat for Repeated<C>
+where
+    C: IntoIterator + FromIterator<C::Item> + Extend<C::Item> + Default,
+    for<'a> &'a C: IntoIterator<Item = &'a C::Item>,
+    C::Item: TextFormat,
+{
+    const MESSAGE: bool = C::Item::MESSAGE;
+
+    /// Prints the elements as a `[a, b]` list.
+    fn print_value(&self, p: &mut Printer) {
+        print_list(p, &**self, |item, p| item.print_value(p))
+    }
+
+    /// Parses either a single element or a `[a, b]` list of elements.
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+        let mut this = Self::default();
+        parse_list(p, |p| {
+            this.extend(Some(C::Item::parse_value(p)?));
+            Ok(())
+        })?;
+        Ok(this)
+    }
+
+    fn print_field(&self, name: &str, p: &mut Printer) {
+        for item in &**self {
+            p.field(name, item);
+        }
+    }
+
+    /// Adds the elements to this field.
+    fn parse_field(&mut self, p: &mut Parser<'_>) -> Result<()> {
+        p.field_separator(C::Item::MESSAGE)?;
+        parse_list(p, |p| {
+            self.extend(Some(C::Item::parse_value(p)?));
+            Ok(())
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        (&**self).into_iter().next().is_none()
+    }
+}
+
+/// Writes `[a, b]`.
+fn print_list<I: IntoIterator>(
+    p: &mut Printer,
+    items: I,
+    mut print: impl FnMut(I::Item, &mut Printer),
+) {
+    p.write_str("[");
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            p.write_str(", ");
+        }
+        print(item, p);
+    }
+    p.write_str("]");
+}
+
+/// Calls `parse` for a single value, or for each value of a `[a, b]` list.
+fn parse_list<'a>(
+    p: &mut Parser<'a>,
+    mut parse: impl FnMut(&mut Parser<'a>) -> Result<()>,
+) -> Result<()> {
+    if !p.try_consume('[') {
+        return parse(p);
+    }
+    if p.try_consume(']') {
+        return Ok(());
+    }
+    loop {
+        parse(p)?;
+        if p.try_consume(']') {
+            return Ok(());
+        }
+        p.expect(',')?;
+    }
+}
+
+/// A map entry, printed as `{ key: k value: v }`.
+struct Entry<K, V>(K, V);
+
+impl<K: TextFormat, V: TextFormat> TextMessage for Entry<&K, &V> {
+    fn print_fields(&self, p: &mut Printer) {
+        p.field("key", self.0);
+        p.field("value", self.1);
+    }
+
+    fn merge_field(&mut self, name: &str, p: &mut Parser<'_>) -> Result<()> {
+        p.unknown_field(name)
+    }
+}
+
+impl<K: TextFormat, V: TextFormat> TextMessage for Entry<Option<K>, Option<V>> {
+    fn print_fields(&self, _: &mut Printer) {}
+
+    fn merge_field(&mut self, name: &str, p: &mut Parser<'_>) -> Result<()> {
+        match name {
+            "key" => self.0 = Some(p.field_value()?),
+            "value" => self.1 = Some(p.field_value()?),
+            _ => return p.unknown_field(name),
+        }
+        Ok(())
+    }
+}
+
+impl<K: Default, V: Default, T: Extend<(K, V)>> Map<K, V, T> {
+    fn parse_entry(&mut self, p: &mut Parser<'_>) -> Result<()>
+    where
+        K: TextFormat,
+        V: TextFormat,
+    {
+        let mut entry = Entry(None, None);
+        p.message(&mut entry)?;
+        self.extend(Some((
+            entry.0.unwrap_or_default(),
+            entry.1.unwrap_or_default(),
+        )));
+        Ok(())
+    }
+}
+
+impl<K, V, T> TextFormat for Map<K, V, T>
+where
+    T: Extend<(K, V)> + Default,
+    for<'a> &'a T: IntoIterator<Item = (&'a K, &'a V)>,
+    K: TextFormat + Default,
+    V: TextFormat + Default,
+{
+    const MESSAGE: bool = true;
+
+    /// Prints the entries as a `[{ key: k value: v }]` list.
+    fn print_value(&self, p: &mut Printer) {
+        print_list(p, &**self, |(key, value), p| p.message(&Entry(key, value)))
+    }
+
+    /// Parses either a single entry or a `[{ key: k value: v }]` list of entries.
+    fn parse_value(p: &mut Parser<'_>) -> Result<Self> {
+        let mut this = Self::default();
+        parse_list(p, |p| this.parse_entry(p))?;
+        Ok(this)
+    }
+
+    fn print_field(&self, name: &str, p: &mut Printer) {
+        for (key, value) in &**self {
+            p.field_with(name, true, |p| p.message(&Entry(key, value)));
+        }
+    }
+
+    fn parse_field(&mut self, p: &mut Parser<'_>) -> Result<()> {
+        p.field_separator(true)?;
+        parse_list(p, |p| self.parse_entry(p))
+    }
+
+    fn is_default(&self) -> bool {
+        (&**self).into_iter().next().is_none()
+    }
+}
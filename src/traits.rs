@@ -2,6 +2,7 @@ use bytes::BufMut;
 
 use crate::{
     encoding::{Encodable, EncodableMessage, ProtobufSerializer},
+    mask::Mask,
     wire_types::*,
     Message, VarInt,
 };
@@ -12,9 +13,10 @@ pub(crate) mod private {
 
 /// A type that can be encoded using ZigZag encoding.
 pub trait Signable: private::Sealed {
-    type Storage: Encodable;
+    type Storage: Encodable + Copy;
     type From;
     fn zigzag_encode(f: Self::From) -> Self::Storage;
+    fn zigzag_decode(n: Self::Storage) -> Self::From;
 }
 
 impl<T: EncodableMessage> Encodable for Message<T> {
@@ -31,4 +33,45 @@ impl<T: EncodableMessage> Encodable for Message<T> {
         s.write_varint(EncodableMessage::encoded_size(&self.0));
         EncodableMessage::encode(&self.0, s)
     }
+
+    fn encoded_size_masked<V: VarInt>(&self, field_number: V, mask: &Mask) -> usize {
+        let calc_size = EncodableMessage::encoded_size_masked(&self.0, mask);
+        field_number.size() + calc_size.size() + calc_size
+    }
+
+    fn encode_field_masked<V: VarInt>(
+        &self,
+        s: &mut ProtobufSerializer<impl BufMut>,
+        field_number: V,
+        mask: &Mask,
+    ) {
+        s.write_varint(field_number << 3 | V::from(Self::Wire::BITS));
+        s.write_varint(EncodableMessage::encoded_size_masked(&self.0, mask));
+        EncodableMessage::encode_masked(&self.0, s, mask)
+    }
+}
+
+/// A protobuf `enum`. Implemented by `#[derive(Enumeration)]`.
+pub trait Enumeration: Sized {
+    /// The value of this variant on the wire.
+    fn to_i32(&self) -> i32;
+
+    fn from_i32(value: i32) -> Option<Self>;
+
+    /// The name of this variant in the `.proto` definition.
+    fn name(&self) -> Option<&'static str>;
+
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+/// A message with a fully qualified protobuf name, like `google.protobuf.Duration`.
+///
+/// Implemented by `#[derive(EncodableMessage)]` for messages with `#[otopr(name = "...")]`.
+pub trait Name {
+    const NAME: &'static str;
+
+    /// The type URL used in `google.protobuf.Any`.
+    fn type_url() -> String {
+        format!("type.googleapis.com/{}", Self::NAME)
+    }
 }
@@ -54,7 +54,7 @@ macro_rules! varint {
         $(
             impl VarInt for $intty {
                 fn write(mut self, buf: &mut impl bytes::BufMut) {
-                    while self > 0b1000_0000 {
+                    while self > 0b0111_1111 {
                         // truncate to the last eight bits and set the
                         // most significant bit to 1.
                         buf.put_u8(self as u8 | 0b1000_0000);
@@ -79,6 +79,9 @@ macro_rules! varint {
                         macro_rules! overflow {
                             () => {{
                                 while byte > 0b0111_1111 {
+                                    if !buf.has_remaining() {
+                                        return Err(eof());
+                                    }
                                     byte = buf.get_u8();
                                 }
                                 return Err(WireTypes::new(byte & 0b111))
@@ -192,4 +195,5 @@ macro_rules! varint_forward {
 }
 
 varint!(u64, u32, u16, u8, usize);
-varint_forward!(i32 as u32, i64 as u64);
+// negative `int32`s are sign-extended to ten bytes like protoc does, and reading truncates.
+varint_forward!(i32 as u64, i64 as u64);
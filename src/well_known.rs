@@ -0,0 +1,993 @@
+//! The `google.protobuf` well-known types.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::decoding::{Decodable, DecodableMessage, Deserializer, Result};
+use crate::encoding::{Encodable, EncodableMessage, ProtobufSerializer};
+use crate::reflect::{
+    Cardinality, FieldDescriptor, Kind, MessageDescriptor, MessageType, ProtoType,
+};
+use crate::text_format::{self, Parser, Printer, TextFormat, TextMessage};
+use crate::wire_types::*;
+use crate::{Enumeration, Map, Name, Repeated, VarInt};
+
+const NANOS_PER_SECOND: i32 = 1_000_000_000;
+
+/// The range of `Duration`s allowed by `google/protobuf/duration.proto`, about 10,000 years.
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+/// An error converting a well-known type to or from a Rust type or string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConversionError {
+    /// The value does not fit in the target type.
+    OutOfRange,
+    /// A string was not in the format of the well-known type.
+    InvalidFormat,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange => f.write_str("value out of range"),
+            Self::InvalidFormat => f.write_str("invalid format"),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// A point in time, as seconds and nanoseconds since the Unix epoch.
+///
+/// Formats as and parses from RFC 3339 strings, like `1972-01-01T10:00:20.021Z`.
+#[derive(
+    crate::EncodableMessage,
+    crate::DecodableMessage,
+    TextFormat,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+)]
+#[otopr(name = "google.protobuf.Timestamp")]
+pub struct Timestamp {
+    #[otopr(1)]
+    pub seconds: i64,
+    /// Always in `0..1_000_000_000` once normalized.
+    #[otopr(2)]
+    pub nanos: i32,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    /// Moves whole seconds out of `nanos`, so that `nanos` is in `0..1_000_000_000`.
+    pub fn normalize(&mut self) {
+        self.seconds = self
+            .seconds
+            .saturating_add((self.nanos / NANOS_PER_SECOND) as i64);
+        self.nanos %= NANOS_PER_SECOND;
+        if self.nanos < 0 {
+            self.seconds = self.seconds.saturating_sub(1);
+            self.nanos += NANOS_PER_SECOND;
+        }
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => Self {
+                seconds: since.as_secs() as i64,
+                nanos: since.subsec_nanos() as i32,
+            },
+            Err(e) => {
+                let mut this = Self {
+                    seconds: -(e.duration().as_secs() as i64),
+                    nanos: -(e.duration().subsec_nanos() as i32),
+                };
+                this.normalize();
+                this
+            }
+        }
+    }
+}
+
+impl TryFrom<Timestamp> for SystemTime {
+    type Error = ConversionError;
+
+    fn try_from(mut timestamp: Timestamp) -> Result<Self, ConversionError> {
+        timestamp.normalize();
+        let nanos = std::time::Duration::from_nanos(timestamp.nanos as u64);
+        let seconds = std::time::Duration::from_secs(timestamp.seconds.unsigned_abs());
+        let time = if timestamp.seconds >= 0 {
+            UNIX_EPOCH.checked_add(seconds)
+        } else {
+            UNIX_EPOCH.checked_sub(seconds)
+        };
+        time.and_then(|t| t.checked_add(nanos))
+            .ok_or(ConversionError::OutOfRange)
+    }
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date of a number of days since the Unix epoch, as `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Writes a fraction of a second with 0, 3, 6 or 9 digits.
+// `u32::is_multiple_of` needs Rust 1.87.
+#[allow(clippy::manual_is_multiple_of)]
+fn write_nanos(f: &mut fmt::Formatter<'_>, nanos: u32) -> fmt::Result {
+    if nanos == 0 {
+        Ok(())
+    } else if nanos % 1_000_000 == 0 {
+        write!(f, ".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        write!(f, ".{:06}", nanos / 1_000)
+    } else {
+        write!(f, ".{:09}", nanos)
+    }
+}
+
+/// Parses a fraction of a second of 1 to 9 digits after the `.`, returning the nanoseconds and the rest of `s`.
+fn parse_nanos(s: &str) -> Result<(i32, &str), ConversionError> {
+    let frac = match s.strip_prefix('.') {
+        Some(frac) => frac,
+        None => return Ok((0, s)),
+    };
+    let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+    if len == 0 || len > 9 {
+        return Err(ConversionError::InvalidFormat);
+    }
+    let nanos: i32 = frac[..len]
+        .parse()
+        .map_err(|_| ConversionError::InvalidFormat)?;
+    Ok((nanos * 10i32.pow(9 - len as u32), &frac[len..]))
+}
+
+fn parse_digits(s: &str, range: std::ops::Range<usize>) -> Result<i64, ConversionError> {
+    s.get(range)
+        .filter(|d| d.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|d| d.parse().ok())
+        .ok_or(ConversionError::InvalidFormat)
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut this = *self;
+        this.normalize();
+        let (year, month, day) = civil_from_days(this.seconds.div_euclid(86400));
+        let seconds = this.seconds.rem_euclid(86400);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+        write_nanos(f, this.nanos as u32)?;
+        f.write_str("Z")
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, ConversionError> {
+        let b = s.as_bytes();
+        if b.len() < 20
+            || b[4] != b'-'
+            || b[7] != b'-'
+            || !matches!(b[10], b'T' | b't')
+            || b[13] != b':'
+            || b[16] != b':'
+        {
+            return Err(ConversionError::InvalidFormat);
+        }
+        let (year, month, day) = (
+            parse_digits(s, 0..4)?,
+            parse_digits(s, 5..7)?,
+            parse_digits(s, 8..10)?,
+        );
+        let (hour, minute, second) = (
+            parse_digits(s, 11..13)?,
+            parse_digits(s, 14..16)?,
+            parse_digits(s, 17..19)?,
+        );
+        let days = days_from_civil(year, month, day);
+        if civil_from_days(days) != (year, month, day) || hour > 23 || minute > 59 || second > 59 {
+            return Err(ConversionError::InvalidFormat);
+        }
+
+        let (nanos, rest) = parse_nanos(&s[19..])?;
+        let offset = match rest.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let offset = parse_digits(rest, 1..3)? * 3600 + parse_digits(rest, 4..6)? * 60;
+                if *sign == b'-' {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+            _ => return Err(ConversionError::InvalidFormat),
+        };
+
+        Ok(Self {
+            seconds: days * 86400 + hour * 3600 + minute * 60 + second - offset,
+            nanos,
+        })
+    }
+}
+
+/// A signed span of time, as seconds and nanoseconds.
+///
+/// Formats as and parses from strings like `1.5s` and `-0.000001s`.
+#[derive(
+    crate::EncodableMessage,
+    crate::DecodableMessage,
+    TextFormat,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+)]
+#[otopr(name = "google.protobuf.Duration")]
+pub struct Duration {
+    #[otopr(1)]
+    pub seconds: i64,
+    /// Has the same sign as `seconds` once normalized.
+    #[otopr(2)]
+    pub nanos: i32,
+}
+
+impl Duration {
+    /// Moves whole seconds out of `nanos` and gives `seconds` and `nanos` the same sign.
+    pub fn normalize(&mut self) {
+        self.seconds = self
+            .seconds
+            .saturating_add((self.nanos / NANOS_PER_SECOND) as i64);
+        self.nanos %= NANOS_PER_SECOND;
+        if self.seconds > 0 && self.nanos < 0 {
+            self.seconds -= 1;
+            self.nanos += NANOS_PER_SECOND;
+        } else if self.seconds < 0 && self.nanos > 0 {
+            self.seconds += 1;
+            self.nanos -= NANOS_PER_SECOND;
+        }
+    }
+}
+
+impl TryFrom<std::time::Duration> for Duration {
+    type Error = ConversionError;
+
+    fn try_from(duration: std::time::Duration) -> Result<Self, ConversionError> {
+        Ok(Self {
+            seconds: i64::try_from(duration.as_secs()).map_err(|_| ConversionError::OutOfRange)?,
+            nanos: duration.subsec_nanos() as i32,
+        })
+    }
+}
+
+impl TryFrom<Duration> for std::time::Duration {
+    type Error = ConversionError;
+
+    /// Fails for negative durations.
+    fn try_from(mut duration: Duration) -> Result<Self, ConversionError> {
+        duration.normalize();
+        if duration.seconds < 0 || duration.nanos < 0 {
+            return Err(ConversionError::OutOfRange);
+        }
+        Ok(Self::new(duration.seconds as u64, duration.nanos as u32))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut this = *self;
+        this.normalize();
+        if this.seconds < 0 || this.nanos < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", this.seconds.unsigned_abs())?;
+        write_nanos(f, this.nanos.unsigned_abs())?;
+        f.write_str("s")
+    }
+}
+
+impl FromStr for Duration {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, ConversionError> {
+        let s = s.strip_suffix('s').ok_or(ConversionError::InvalidFormat)?;
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let len = s.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return Err(ConversionError::InvalidFormat);
+        }
+        // the seconds are all digits, so they only fail to parse if they are too big.
+        let seconds = parse_digits(s, 0..len).map_err(|_| ConversionError::OutOfRange)?;
+        let (nanos, rest) = parse_nanos(&s[len..])?;
+        if !rest.is_empty() {
+            return Err(ConversionError::InvalidFormat);
+        }
+        if seconds > MAX_DURATION_SECONDS {
+            return Err(ConversionError::OutOfRange);
+        }
+        Ok(if negative {
+            Self {
+                seconds: -seconds,
+                nanos: -nanos,
+            }
+        } else {
+            Self { seconds, nanos }
+        })
+    }
+}
+
+/// An arbitrary message along with a URL identifying its type,
+/// like `type.googleapis.com/google.protobuf.Duration`.
+#[derive(
+    crate::EncodableMessage,
+    crate::DecodableMessage,
+    TextFormat,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[otopr(name = "google.protobuf.Any")]
+pub struct Any {
+    #[otopr(1)]
+    pub type_url: String,
+    #[otopr(2)]
+    pub value: Bytes,
+}
+
+impl Any {
+    /// Encodes `message` into an `Any` with the type URL of `M`.
+    pub fn pack<M: Name + EncodableMessage>(message: &M) -> Self {
+        Self::pack_with_type_url(M::type_url(), message)
+    }
+
+    /// Encodes `message` into an `Any` with the given type URL.
+    pub fn pack_with_type_url<M: EncodableMessage + ?Sized>(
+        type_url: impl Into<String>,
+        message: &M,
+    ) -> Self {
+        let mut value = Vec::with_capacity(message.encoded_size());
+        message.encode(&mut ProtobufSerializer::new(&mut value));
+        Self {
+            type_url: type_url.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Decodes the message if it is an `M`, returning `None` otherwise.
+    pub fn unpack<M>(&self) -> Result<Option<M>>
+    where
+        M: Name + for<'de> DecodableMessage<'de> + Default,
+    {
+        self.unpack_with_type_url(M::NAME)
+    }
+
+    /// Decodes the message if it has the type of `type_url`, returning `None` otherwise.
+    ///
+    /// Only the type names after the last `/` of the URLs are compared.
+    pub fn unpack_with_type_url<M>(&self, type_url: &str) -> Result<Option<M>>
+    where
+        M: for<'de> DecodableMessage<'de> + Default,
+    {
+        if self.type_name() != type_name(type_url) {
+            return Ok(None);
+        }
+        self.decode().map(Some)
+    }
+
+    /// Decodes the message as an `M` regardless of the type URL.
+    pub(crate) fn decode<M>(&self) -> Result<M>
+    where
+        M: for<'de> DecodableMessage<'de> + Default,
+    {
+        let mut value = self.value.clone();
+        M::decode(&mut Deserializer::new(&mut value))
+    }
+
+    /// Whether the message is an `M`.
+    pub fn is<M: Name>(&self) -> bool {
+        self.type_name() == M::NAME
+    }
+
+    /// The fully qualified name of the type, which is the part of the URL after the last `/`.
+    pub fn type_name(&self) -> &str {
+        type_name(&self.type_url)
+    }
+}
+
+pub(crate) fn type_name(type_url: &str) -> &str {
+    type_url.rsplit('/').next().unwrap_or_default()
+}
+
+/// `NullValue` is the null variant of [`Value`].
+#[derive(Enumeration, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NullValue {
+    NullValue = 0,
+}
+
+/// A dynamically typed value, equivalent to a JSON value.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Struct(Struct),
+    List(ListValue),
+}
+
+/// A JSON object of [`Value`]s.
+#[derive(
+    crate::EncodableMessage, crate::DecodableMessage, TextFormat, Clone, Debug, Default, PartialEq,
+)]
+#[otopr(name = "google.protobuf.Struct")]
+pub struct Struct {
+    #[otopr(1)]
+    pub fields: Map<String, Value, BTreeMap<String, Value>>,
+}
+
+/// A JSON array of [`Value`]s.
+#[derive(
+    crate::EncodableMessage, crate::DecodableMessage, TextFormat, Clone, Debug, Default, PartialEq,
+)]
+#[otopr(name = "google.protobuf.ListValue")]
+pub struct ListValue {
+    #[otopr(1)]
+    pub values: Repeated<Vec<Value>>,
+}
+
+impl Name for Value {
+    const NAME: &'static str = "google.protobuf.Value";
+}
+
+impl MessageDescriptor for Value {
+    const NAME: &'static str = "google.protobuf.Value";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        Value::oneof_field("null_value", 1, NullValue::KIND),
+        Value::oneof_field("number_value", 2, Kind::Double),
+        Value::oneof_field("string_value", 3, Kind::String),
+        Value::oneof_field("bool_value", 4, Kind::Bool),
+        Value::oneof_field("struct_value", 5, Struct::KIND),
+        Value::oneof_field("list_value", 6, ListValue::KIND),
+    ];
+}
+
+impl ProtoType for Value {
+    const KIND: Kind = Kind::Message(MessageType::of::<Self>());
+}
+
+impl Value {
+    /// A field of the `kind` oneof, which is only encoded when it is set.
+    const fn oneof_field(name: &'static str, number: u32, kind: Kind) -> FieldDescriptor {
+        let wire_type = match kind {
+            Kind::Double => WireTypes::Fixed64Wire,
+            Kind::Enum(_) | Kind::Bool => WireTypes::VarIntWire,
+            _ => WireTypes::LengthDelimitedWire,
+        };
+        FieldDescriptor {
+            name,
+            number,
+            wire_type,
+            cardinality: Cardinality::Optional,
+            kind,
+            packed: false,
+        }
+    }
+}
+
+impl EncodableMessage for Value {
+    fn encoded_size(&self) -> usize {
+        match self {
+            Value::Null => NullValue::NullValue.encoded_size(1u8),
+            Value::Number(n) => n.encoded_size(2u8),
+            Value::String(s) => s.encoded_size(3u8),
+            Value::Bool(b) => b.encoded_size(4u8),
+            Value::Struct(s) => Encodable::encoded_size(s, 5u8),
+            Value::List(l) => Encodable::encoded_size(l, 6u8),
+        }
+    }
+
+    fn encode<T: BufMut>(&self, s: &mut ProtobufSerializer<T>) {
+        match self {
+            Value::Null => s.encode_field(1u8, &NullValue::NullValue),
+            Value::Number(n) => s.encode_field(2u8, n),
+            Value::String(string) => s.encode_field(3u8, string),
+            Value::Bool(b) => s.encode_field(4u8, b),
+            Value::Struct(st) => s.encode_field(5u8, st),
+            Value::List(l) => s.encode_field(6u8, l),
+        }
+    }
+}
+
+impl Encodable for Value {
+    type Wire = LengthDelimitedWire;
+
+    fn encoded_size<V: VarInt>(&self, field_number: V) -> usize {
+        let calc_size = EncodableMessage::encoded_size(self);
+        field_number.size() + calc_size.size() + calc_size
+    }
+
+    fn encode(&self, s: &mut ProtobufSerializer<impl BufMut>) {
+        s.write_varint(EncodableMessage::encoded_size(self));
+        EncodableMessage::encode(self, s)
+    }
+}
+
+impl<'de> DecodableMessage<'de> for Value {
+    type Tag = u8;
+
+    fn decode_field<B: Buf>(&mut self, d: &mut Deserializer<'de, B>, tag: u8) -> Result<()> {
+        match (tag >> 3, WireTypes::new(tag & 0b111)?) {
+            (1, WireTypes::VarIntWire) => {
+                NullValue::decode(d)?;
+                *self = Value::Null;
+            }
+            (2, WireTypes::Fixed64Wire) => *self = Value::Number(f64::decode(d)?),
+            (3, WireTypes::LengthDelimitedWire) => *self = Value::String(String::decode(d)?),
+            (4, WireTypes::VarIntWire) => *self = Value::Bool(bool::decode(d)?),
+            (5, WireTypes::LengthDelimitedWire) => match self {
+                Value::Struct(s) => s.merge_from(d)?,
+                _ => *self = Value::Struct(Decodable::decode(d)?),
+            },
+            (6, WireTypes::LengthDelimitedWire) => match self {
+                Value::List(l) => l.merge_from(d)?,
+                _ => *self = Value::List(Decodable::decode(d)?),
+            },
+            (_, wire) => wire.skip(d)?,
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Decodable<'de> for Value {
+    type Wire = LengthDelimitedWire;
+
+    fn decode<B: Buf>(d: &mut Deserializer<'de, B>) -> Result<Self> {
+        let len = d.read_varint()?;
+        let tk = d.set_limit(len);
+        let value = <Self as DecodableMessage<'de>>::decode(d);
+        d.reset_limit(tk);
+        value
+    }
+}
+
+impl TextMessage for Value {
+    fn print_fields(&self, p: &mut Printer) {
+        match self {
+            Value::Null => p.field("null_value", &NullValue::NullValue),
+            Value::Number(n) => p.field("number_value", n),
+            Value::String(s) => p.field("string_value", s),
+            Value::Bool(b) => p.field("bool_value", b),
+            Value::Struct(s) => p.field("struct_value", s),
+            Value::List(l) => p.field("list_value", l),
+        }
+    }
+
+    fn merge_field(&mut self, name: &str, p: &mut Parser<'_>) -> text_format::Result<()> {
+        *self = match name {
+            "null_value" | "1" => p.field_value::<NullValue>().map(|_| Value::Null)?,
+            "number_value" | "2" => Value::Number(p.field_value()?),
+            "string_value" | "3" => Value::String(p.field_value()?),
+            "bool_value" | "4" => Value::Bool(p.field_value()?),
+            "struct_value" | "5" => Value::Struct(p.field_value()?),
+            "list_value" | "6" => Value::List(p.field_value()?),
+            _ => return p.unknown_field(name),
+        };
+        Ok(())
+    }
+}
+
+impl TextFormat for Value {
+    const MESSAGE: bool = true;
+
+    fn print_value(&self, p: &mut Printer) {
+        p.message(self)
+    }
+
+    fn parse_value(p: &mut Parser<'_>) -> text_format::Result<Self> {
+        let mut this = Self::default();
+        p.message(&mut this)?;
+        Ok(this)
+    }
+}
+
+/// A set of field paths, like `user.display_name`, naming the fields affected by an operation.
+#[derive(
+    crate::EncodableMessage,
+    crate::DecodableMessage,
+    TextFormat,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[otopr(name = "google.protobuf.FieldMask")]
+pub struct FieldMask {
+    #[otopr(1)]
+    pub paths: Repeated<Vec<String>>,
+}
+
+/// Whether `path` is `parent` or one of its subfields.
+fn covers(parent: &str, path: &str) -> bool {
+    path.strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+impl FieldMask {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(paths: I) -> Self {
+        Self {
+            paths: Repeated::new(paths.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Whether `path` is in this mask, either directly or as a subfield of one of its paths.
+    pub fn contains(&self, path: &str) -> bool {
+        self.paths.iter().any(|parent| covers(parent, path))
+    }
+
+    /// Sorts the paths, removing duplicates and paths that are subfields of other paths.
+    pub fn normalize(&mut self) {
+        self.paths.sort();
+        let mut last: Option<String> = None;
+        self.paths.retain(|path| match &last {
+            Some(parent) if covers(parent, path) => false,
+            _ => {
+                last = Some(path.clone());
+                true
+            }
+        });
+    }
+
+    /// The normalized mask of the paths in either mask.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut mask = Self::new(self.paths.iter().chain(other.paths.iter()).cloned());
+        mask.normalize();
+        mask
+    }
+
+    /// The normalized mask of the paths in both masks.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let ours = self.paths.iter().filter(|path| other.contains(path));
+        let theirs = other.paths.iter().filter(|path| self.contains(path));
+        let mut mask = Self::new(ours.chain(theirs).cloned());
+        mask.normalize();
+        mask
+    }
+}
+
+impl fmt::Display for FieldMask {
+    /// Writes the paths separated by commas.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.paths.join(","))
+    }
+}
+
+/// A message with no fields.
+#[derive(
+    crate::EncodableMessage,
+    crate::DecodableMessage,
+    TextFormat,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+#[otopr(name = "google.protobuf.Empty")]
+pub struct Empty {}
+
+macro_rules! wrappers {
+    ($($(#[$doc:meta])* $name:ident($ty:ty) = $proto_name:literal;)*) => {$(
+        $(#[$doc])*
+        #[derive(crate::EncodableMessage, crate::DecodableMessage, TextFormat, Clone, Debug, Default, PartialEq)]
+        #[otopr(name = $proto_name)]
+        pub struct $name {
+            #[otopr(1)]
+            pub value: $ty,
+        }
+
+        impl From<$ty> for $name {
+            fn from(value: $ty) -> Self {
+                Self { value }
+            }
+        }
+
+        #[cfg(feature = "json")]
+        impl crate::json::ProtoJson for $name {
+            fn to_json(&self, options: &crate::json::PrintOptions) -> crate::json::Value {
+                crate::json::ProtoJson::to_json(&self.value, options)
+            }
+
+            fn from_json(value: &crate::json::Value, options: &crate::json::ParseOptions) -> crate::json::Result<Self> {
+                <$ty as crate::json::ProtoJson>::from_json(value, options).map(Self::from)
+            }
+        }
+    )*};
+}
+
+wrappers! {
+    /// A `double` in a message, to tell unset and zero apart.
+    DoubleValue(f64) = "google.protobuf.DoubleValue";
+    /// A `float` in a message, to tell unset and zero apart.
+    FloatValue(f32) = "google.protobuf.FloatValue";
+    /// An `int64` in a message, to tell unset and zero apart.
+    Int64Value(i64) = "google.protobuf.Int64Value";
+    /// A `uint64` in a message, to tell unset and zero apart.
+    UInt64Value(u64) = "google.protobuf.UInt64Value";
+    /// An `int32` in a message, to tell unset and zero apart.
+    Int32Value(i32) = "google.protobuf.Int32Value";
+    /// A `uint32` in a message, to tell unset and zero apart.
+    UInt32Value(u32) = "google.protobuf.UInt32Value";
+    /// A `bool` in a message, to tell unset and false apart.
+    BoolValue(bool) = "google.protobuf.BoolValue";
+    /// A `string` in a message, to tell unset and empty apart.
+    StringValue(String) = "google.protobuf.StringValue";
+    /// A `bytes` in a message, to tell unset and empty apart.
+    BytesValue(Vec<u8>) = "google.protobuf.BytesValue";
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use serde_json::Map as JsonMap;
+
+    use super::*;
+    use crate::json::{JsonError, ParseOptions, PrintOptions, ProtoJson, Value as Json};
+    use crate::reflect::camel_case;
+
+    fn string(value: &Json) -> crate::json::Result<&str> {
+        value
+            .as_str()
+            .ok_or(JsonError::UnexpectedType { expected: "string" })
+    }
+
+    fn parse<T: FromStr>(value: &Json) -> crate::json::Result<T> {
+        let s = string(value)?;
+        s.parse().map_err(|_| JsonError::InvalidValue(s.to_owned()))
+    }
+
+    impl ProtoJson for Timestamp {
+        fn to_json(&self, _: &PrintOptions) -> Json {
+            Json::String(self.to_string())
+        }
+
+        fn from_json(value: &Json, _: &ParseOptions) -> crate::json::Result<Self> {
+            parse(value)
+        }
+    }
+
+    impl ProtoJson for Duration {
+        fn to_json(&self, _: &PrintOptions) -> Json {
+            Json::String(self.to_string())
+        }
+
+        fn from_json(value: &Json, _: &ParseOptions) -> crate::json::Result<Self> {
+            parse(value)
+        }
+    }
+
+    fn snake_case(path: &str) -> String {
+        let mut out = String::with_capacity(path.len());
+        for c in path.chars() {
+            if c.is_ascii_uppercase() {
+                out.push('_');
+                out.push(c.to_ascii_lowercase());
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    impl ProtoJson for FieldMask {
+        fn to_json(&self, _: &PrintOptions) -> Json {
+            let paths: Vec<_> = self.paths.iter().map(|p| camel_case(p, false)).collect();
+            Json::String(paths.join(","))
+        }
+
+        fn from_json(value: &Json, _: &ParseOptions) -> crate::json::Result<Self> {
+            let s = string(value)?;
+            Ok(FieldMask::new(
+                s.split(',').filter(|p| !p.is_empty()).map(snake_case),
+            ))
+        }
+    }
+
+    impl ProtoJson for Value {
+        fn to_json(&self, options: &PrintOptions) -> Json {
+            match self {
+                Value::Null => Json::Null,
+                Value::Number(n) => n.to_json(options),
+                Value::String(s) => Json::String(s.clone()),
+                Value::Bool(b) => Json::Bool(*b),
+                Value::Struct(s) => s.to_json(options),
+                Value::List(l) => l.to_json(options),
+            }
+        }
+
+        fn from_json(value: &Json, options: &ParseOptions) -> crate::json::Result<Self> {
+            Ok(match value {
+                Json::Null => Value::Null,
+                Json::Number(_) => Value::Number(f64::from_json(value, options)?),
+                Json::String(s) => Value::String(s.clone()),
+                Json::Bool(b) => Value::Bool(*b),
+                Json::Object(_) => Value::Struct(Struct::from_json(value, options)?),
+                Json::Array(_) => Value::List(ListValue::from_json(value, options)?),
+            })
+        }
+    }
+
+    impl ProtoJson for Struct {
+        fn to_json(&self, options: &PrintOptions) -> Json {
+            self.fields.to_json(options)
+        }
+
+        fn from_json(value: &Json, options: &ParseOptions) -> crate::json::Result<Self> {
+            Map::from_json(value, options).map(|fields| Struct { fields })
+        }
+    }
+
+    impl ProtoJson for ListValue {
+        fn to_json(&self, options: &PrintOptions) -> Json {
+            self.values.to_json(options)
+        }
+
+        fn from_json(value: &Json, options: &ParseOptions) -> crate::json::Result<Self> {
+            Repeated::from_json(value, options).map(|values| ListValue { values })
+        }
+    }
+
+    /// Whether the JSON of a well-known type goes in the `"value"` of an `Any`, because it is not
+    /// an object of the fields of the message.
+    fn has_special_json(name: &str) -> bool {
+        match name.strip_prefix("google.protobuf.") {
+            Some(name) => matches!(
+                name,
+                "Any"
+                    | "Duration"
+                    | "FieldMask"
+                    | "ListValue"
+                    | "Struct"
+                    | "Timestamp"
+                    | "Value"
+                    | "DoubleValue"
+                    | "FloatValue"
+                    | "Int64Value"
+                    | "UInt64Value"
+                    | "Int32Value"
+                    | "UInt32Value"
+                    | "BoolValue"
+                    | "StringValue"
+                    | "BytesValue"
+            ),
+            None => false,
+        }
+    }
+
+    /// Prints the type URL as `"@type"` next to the fields of the message, or next to its JSON
+    /// in `"value"` for well-known types that are not printed as objects.
+    ///
+    /// The type of the message must be registered with [`TypeRegistry::register_json`] in the
+    /// `type_registry` of the options. Parsing fails with [`JsonError::UnknownTypeUrl`] otherwise,
+    /// while printing falls back to the encoded message as base64 in `"value"`.
+    ///
+    /// [`TypeRegistry::register_json`]: crate::registry::TypeRegistry::register_json
+    impl ProtoJson for Any {
+        fn to_json(&self, options: &PrintOptions) -> Json {
+            let mut object = JsonMap::new();
+            object.insert("@type".to_owned(), Json::String(self.type_url.clone()));
+            let message = options
+                .type_registry
+                .as_ref()
+                .and_then(|registry| registry.any_to_json(self, options));
+            match message {
+                Some(Json::Object(fields)) if !has_special_json(self.type_name()) => {
+                    object.extend(fields)
+                }
+                Some(value) => {
+                    object.insert("value".to_owned(), value);
+                }
+                None => {
+                    object.insert("value".to_owned(), self.value.to_json(options));
+                }
+            }
+            Json::Object(object)
+        }
+
+        fn from_json(value: &Json, options: &ParseOptions) -> crate::json::Result<Self> {
+            let mut object = match value {
+                Json::Object(object) => object.clone(),
+                _ => return Err(JsonError::UnexpectedType { expected: "object" }),
+            };
+            let type_url = match object.remove("@type") {
+                Some(Json::String(type_url)) => type_url,
+                Some(_) => return Err(JsonError::UnexpectedType { expected: "string" }),
+                None if object.is_empty() => return Ok(Any::default()),
+                None => return Err(JsonError::InvalidValue("`Any` without `@type`".to_owned())),
+            };
+            let message = if has_special_json(type_name(&type_url)) {
+                object.remove("value").unwrap_or(Json::Null)
+            } else {
+                Json::Object(object)
+            };
+            let registry = options
+                .type_registry
+                .as_ref()
+                .ok_or_else(|| JsonError::UnknownTypeUrl(type_url.clone()))?;
+            registry.any_from_json(&type_url, &message, options)
+        }
+
+        fn is_default(&self) -> bool {
+            self.type_url.is_empty() && self.value.is_empty()
+        }
+    }
+
+    impl ProtoJson for Empty {
+        fn to_json(&self, _: &PrintOptions) -> Json {
+            Json::Object(Default::default())
+        }
+
+        fn from_json(value: &Json, _: &ParseOptions) -> crate::json::Result<Self> {
+            crate::json::object_fields(value).map(|_| Empty {})
+        }
+    }
+}
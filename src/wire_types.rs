@@ -8,6 +8,7 @@ use crate::{
 /// A WireType.
 pub trait WireType: Sealed {
     const BITS: u8;
+    const WIRE_TYPE: WireTypes;
 }
 
 macro_rules! wires {
@@ -17,10 +18,11 @@ macro_rules! wires {
             pub struct $id;
             impl WireType for $id {
                 const BITS: u8 = $bits;
+                const WIRE_TYPE: WireTypes = WireTypes::$id;
             }
         )*
         #[repr(u8)]
-        #[derive(Copy, Clone, Eq, PartialEq)]
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
         pub enum WireTypes {
             $($id = $bits),*
         }
@@ -39,23 +41,44 @@ wires! {
     VarIntWire = 0,
     Fixed64Wire = 1,
     LengthDelimitedWire = 2,
-    // unsupported wires
-    // StartGroupWire = 3,
-    // EndGroupWire = 4,
+    // groups are deprecated, they are only skipped or decoded raw.
+    StartGroupWire = 3,
+    EndGroupWire = 4,
     Fixed32Wire = 5,
 }
 
 impl WireTypes {
+    /// Skips a value of this wire type, failing with [`DecodingError::Eof`] if the input ends first.
     pub fn skip<B: Buf>(self, d: &mut Deserializer<B>) -> Result<()> {
         match self {
-            WireTypes::VarIntWire => while d.buf.get_u8() > 0b0111_1111 {},
-            WireTypes::Fixed64Wire => d.buf.advance(8),
+            WireTypes::VarIntWire => d.read_varint::<u64>().map(drop),
+            WireTypes::Fixed64Wire => d.read_u64().map(drop),
             WireTypes::LengthDelimitedWire => {
                 let len = d.read_varint()?;
-                d.buf.advance(len);
+                d.check_limit(len, |buf| {
+                    if buf.remaining() < len {
+                        Err(DecodingError::Eof)
+                    } else {
+                        buf.advance(len);
+                        Ok(())
+                    }
+                })
             }
-            WireTypes::Fixed32Wire => d.buf.advance(4),
+            WireTypes::StartGroupWire => {
+                // the fields of a group can be groups too, which end first.
+                let mut depth = 1;
+                while depth > 0 {
+                    let tag: u64 = d.read_varint()?;
+                    match WireTypes::new((tag & 0b111) as u8)? {
+                        WireTypes::StartGroupWire => depth += 1,
+                        WireTypes::EndGroupWire => depth -= 1,
+                        wire => wire.skip(d)?,
+                    }
+                }
+                Ok(())
+            }
+            WireTypes::EndGroupWire => Err(DecodingError::InvalidGroup),
+            WireTypes::Fixed32Wire => d.read_u32().map(drop),
         }
-        Ok(())
     }
 }